@@ -0,0 +1,145 @@
+//! Works out who holds the copyright on each file from who wrote it
+//! (`--per-author`), for projects that several companies contribute to.
+//!
+//! Each author's commits count for their employer, going by a file that
+//! (like `.mailmap`) lists each employer, then the addresses of the authors
+//! who work for them:
+//!
+//! ```text
+//! # Holder          Authors
+//! Acme Corporation  <alice@example.com> <bob@example.com>
+//! Example Inc.      <*@example.com>
+//! ```
+//!
+//! `<*@domain>` stands for everyone with an address there, unless they're
+//! listed by their own address. Authors who aren't listed at all hold
+//! the copyright on their work themselves, by the name they commit with.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::common::{AuthorMap, Year};
+use crate::error::Error;
+use crate::update::Authorship;
+
+lazy_static! {
+    // An address in angle brackets, like <alice@example.com>
+    static ref ADDRESS: Regex = Regex::new(r"<([^<>]*)>").unwrap();
+}
+
+/// Who each author works for
+#[derive(Default)]
+pub struct Employers {
+    /// Employers by (lowercase) address
+    by_address: HashMap<String, String>,
+
+    /// Employers by (lowercase) domain, for `<*@domain>`
+    by_domain: HashMap<String, String>,
+}
+
+impl Employers {
+    /// Who holds the copyright on what the given author (`Name <email>`) wrote
+    pub fn holder(&self, author: &str) -> String {
+        let (name, address) = match ADDRESS.captures(author) {
+            Some(c) => (
+                author[..c.get(0).unwrap().start()].trim(),
+                c[1].to_lowercase(),
+            ),
+            None => (author.trim(), String::new()),
+        };
+        let domain = address.rsplit_once('@').map_or("", |(_, d)| d);
+
+        self.by_address
+            .get(&address)
+            .or_else(|| self.by_domain.get(domain))
+            .cloned()
+            .unwrap_or_else(|| {
+                if name.is_empty() {
+                    address.clone()
+                } else {
+                    name.to_owned()
+                }
+            })
+    }
+}
+
+/// Reads who each author works for from the given file (see above).
+pub fn read_employers(path: &str) -> Result<Employers, Error> {
+    let contents = fs::read_to_string(path).map_err(Error::io(format!("read {}", path)))?;
+    let invalid = |line: usize, problem: &str| {
+        Error::io(format!("parse {}", path))(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {} {}", line, problem),
+        ))
+    };
+
+    let mut employers = Employers::default();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let holder = line[..line.find('<').unwrap_or(line.len())].trim();
+        if holder.is_empty() {
+            return Err(invalid(i + 1, "doesn't start with a holder"));
+        }
+        let addresses: Vec<String> = ADDRESS
+            .captures_iter(line)
+            .map(|c| c[1].trim().to_lowercase())
+            .collect();
+        if addresses.is_empty() {
+            return Err(invalid(i + 1, "doesn't list any <addresses>"));
+        }
+
+        for address in addresses {
+            match address.strip_prefix("*@") {
+                Some(domain) => employers
+                    .by_domain
+                    .insert(domain.to_owned(), holder.to_owned()),
+                None => employers.by_address.insert(address, holder.to_owned()),
+            };
+        }
+    }
+    Ok(employers)
+}
+
+/// Works out who holds each file from who wrote it: each author's employer,
+/// in the order they first changed it (or sorted, if asked),
+/// with the years their authors did.
+/// Files no one has committed to yet are left to the usual organizations.
+pub fn attribute(
+    authors: AuthorMap,
+    employers: &Employers,
+    sort: bool,
+) -> HashMap<String, Authorship> {
+    let mut attributed = HashMap::new();
+    for (path, commits) in authors {
+        let mut holders: Vec<(String, Vec<Year>)> = Vec::new();
+        for (author, year) in commits {
+            let holder = employers.holder(&author);
+            match holders.iter_mut().find(|(h, _)| *h == holder) {
+                Some((_, years)) => years.push(year),
+                None => holders.push((holder, vec![year])),
+            }
+        }
+        if holders.is_empty() {
+            continue;
+        }
+        if sort {
+            holders.sort();
+        }
+
+        let (holders, mut years): (Vec<String>, Vec<Vec<Year>>) = holders.into_iter().unzip();
+        for y in &mut years {
+            y.sort();
+            y.dedup();
+        }
+        attributed.insert(path, Authorship { holders, years });
+    }
+    attributed
+}
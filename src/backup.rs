@@ -0,0 +1,190 @@
+//! Backs up files before we rewrite them (`--backup`), and restores them
+//! (`copyrighter restore`), for anyone nervous about us editing files
+//! with uncommitted work in place.
+//!
+//! Each file is copied to `<file><suffix>` (`<file>.bak` by default)
+//! just before it's changed, and listed in a manifest at the top of the repo
+//! (before it's changed too, so a run that dies partway can still be undone).
+//! That way `restore` knows what to put back (and which files, like new
+//! `.license` companions, to remove) without guessing which `.bak` files
+//! are ours. Each run that changes anything replaces the last one's backups
+//! and manifest, so `restore` undoes the latest run. Files that are already
+//! there where a backup would go, but aren't backups from the last run,
+//! are left alone: the backup goes to `<file><suffix>.1` (or `.2`...) instead.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// The suffix backups get if `--backup` is given without one
+pub const DEFAULT_SUFFIX: &str = ".bak";
+
+/// Where we list the files the last run changed, one JSON entry per line
+pub const MANIFEST_PATH: &str = ".copyrighter-backups.json";
+
+/// Backs up each file a run rewrites to `<file><suffix>`
+pub struct Backups {
+    suffix: String,
+
+    /// This run's manifest, once it's backed something up
+    manifest: Mutex<Option<Manifest>>,
+}
+
+struct Manifest {
+    file: File,
+
+    /// The files we've listed in it so far
+    paths: HashSet<String>,
+
+    /// The backups we've made so far
+    backups: HashSet<String>,
+
+    /// The backups the last run made, which we can replace
+    last_backups: HashSet<String>,
+}
+
+impl Manifest {
+    /// Replaces the last run's manifest with a new (empty) one.
+    fn start() -> io::Result<Self> {
+        let last_backups = match read_entries() {
+            Ok(entries) => entries.into_iter().filter_map(|e| e.backup).collect(),
+            Err(_) => HashSet::new(),
+        };
+        Ok(Self {
+            file: File::create(MANIFEST_PATH)?,
+            paths: HashSet::new(),
+            backups: HashSet::new(),
+            last_backups,
+        })
+    }
+
+    /// Lists the given entry, writing it out right away.
+    fn add(&mut self, entry: Entry) -> io::Result<()> {
+        let line = serde_json::to_string(&entry).unwrap() + "\n";
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        self.paths.insert(entry.path);
+        self.backups.extend(entry.backup);
+        Ok(())
+    }
+
+    /// Where the given file's backup should go: `<file><suffix>`,
+    /// unless something other than one of the last run's backups
+    /// (or one of ours) is already there.
+    fn backup_path(&self, path: &str, suffix: &str) -> String {
+        let base = format!("{}{}", path, suffix);
+        let taken = |p: &String| {
+            self.backups.contains(p) || (Path::new(p).exists() && !self.last_backups.contains(p))
+        };
+        let mut backup = base.clone();
+        let mut n = 1;
+        while taken(&backup) {
+            backup = format!("{}.{}", base, n);
+            n += 1;
+        }
+        backup
+    }
+}
+
+impl Backups {
+    pub fn new(suffix: String) -> Self {
+        Self {
+            suffix,
+            manifest: Mutex::new(None),
+        }
+    }
+
+    /// Runs `f` on this run's manifest, starting it if we haven't yet.
+    fn with_manifest<F>(&self, f: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Manifest) -> io::Result<()>,
+    {
+        let mut manifest = self.manifest.lock().unwrap();
+        if manifest.is_none() {
+            *manifest = Some(Manifest::start()?);
+        }
+        f(manifest.as_mut().unwrap())
+    }
+
+    /// Copies the given file to its backup and lists it in the manifest,
+    /// unless we've backed it up (or created it) already this run.
+    /// (Otherwise its backup would hold what we changed it to.)
+    pub fn back_up(&self, path: &str) -> io::Result<()> {
+        self.with_manifest(|manifest| {
+            if manifest.paths.contains(path) {
+                return Ok(());
+            }
+            let backup = manifest.backup_path(path, &self.suffix);
+            fs::copy(path, &backup)?;
+            manifest.add(Entry {
+                path: path.to_owned(),
+                backup: Some(backup),
+            })
+        })
+    }
+
+    /// Lists the given file in the manifest as one we're creating
+    /// (so `restore()` removes it). Must be called before creating it.
+    pub fn creating(&self, path: &str) -> io::Result<()> {
+        self.with_manifest(|manifest| {
+            manifest.add(Entry {
+                path: path.to_owned(),
+                backup: None,
+            })
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: String,
+
+    /// Where the file's old contents are,
+    /// or none if the run created it
+    backup: Option<String>,
+}
+
+/// Reads the entries in the last run's manifest.
+fn read_entries() -> io::Result<Vec<Entry>> {
+    fs::read_to_string(MANIFEST_PATH)?
+        .lines()
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+/// Undoes the last run made with `--backup`: puts each file's backup back
+/// in its place, and removes files it created.
+/// Returns what we did to each file, e.g. `Restored src/main.rs`.
+pub fn restore() -> Result<Vec<String>, Error> {
+    let entries = match read_entries() {
+        Ok(e) => e,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(Error::NoBackups),
+        Err(e) => return Err(Error::io(format!("read {}", MANIFEST_PATH))(e)),
+    };
+
+    let mut done = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match entry.backup {
+            Some(backup) => {
+                fs::rename(&backup, &entry.path)
+                    .map_err(Error::io(format!("restore {} from {}", entry.path, backup)))?;
+                done.push(format!("Restored {}", entry.path));
+            }
+            None => match fs::remove_file(&entry.path) {
+                Ok(()) => done.push(format!("Removed {}", entry.path)),
+                // (The run died before it could create it.)
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(Error::io(format!("remove {}", entry.path))(e)),
+            },
+        }
+    }
+
+    fs::remove_file(MANIFEST_PATH).map_err(Error::io(format!("remove {}", MANIFEST_PATH)))?;
+    Ok(done)
+}
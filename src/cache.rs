@@ -0,0 +1,203 @@
+//! Remembers the years we found for each file between runs, so that
+//! re-running on a repo that hasn't changed (much) doesn't walk its
+//! whole history again.
+//!
+//! The cache lives in the Git directory (`.git/copyrighter-cache`),
+//! and is keyed by HEAD and each file's blob in it. A file's years are reused
+//! if its blob hasn't changed and no commits between the cached HEAD and
+//! the current one touched it; everything else is looked up as usual.
+//! Changing which commits are ignored, or how history is read,
+//! throws the whole cache out.
+//!
+//! The cache is only ever a shortcut: if it can't be read or written,
+//! we just do all the work.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{PathSet, Year, YearMap};
+use crate::error::Error;
+use crate::git::{self, DateSource, GitOptions, SHA1};
+use crate::history::Method;
+
+/// Bump this whenever what we store (or what it means) changes.
+const VERSION: u32 = 1;
+
+/// The cache file, in the Git directory
+const CACHE_FILE: &str = "copyrighter-cache";
+
+#[derive(Serialize, Deserialize)]
+struct Cache {
+    version: u32,
+    head: String,
+
+    /// The ignored commits, method, and dates the years were found with
+    settings: String,
+
+    files: HashMap<String, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    blob: String,
+    years: Vec<u16>,
+}
+
+/// Describes what (besides the history itself) determines the years we find.
+fn settings(git: &GitOptions, ignoring_commits: &HashSet<SHA1>, method: Method) -> String {
+    let mut ignored: Vec<String> = ignoring_commits.iter().map(|c| c.to_string()).collect();
+    ignored.sort();
+    let whitespace = if git.ignore_whitespace { " -w" } else { "" };
+    let dates = match git.date_source {
+        DateSource::Author => "",
+        DateSource::Committer => " by committer date",
+    };
+    format!(
+        "{:?}{}{} ignoring [{}]",
+        method,
+        whitespace,
+        dates,
+        ignored.join(",")
+    )
+}
+
+fn cache_path(git: &GitOptions) -> Result<PathBuf, Error> {
+    Ok(git::git_dir(git)?.join(CACHE_FILE))
+}
+
+/// Finds the years each of the given files was changed with `find`,
+/// except for the files whose years we already know from the last run
+/// (unless `git.cache` is off).
+pub fn get_year_map<F>(
+    git: &GitOptions,
+    paths: PathSet,
+    ignoring_commits: &HashSet<SHA1>,
+    method: Method,
+    find: F,
+) -> Result<YearMap, Error>
+where
+    F: FnOnce(PathSet) -> Result<YearMap, Error>,
+{
+    if !git.cache {
+        return find(paths);
+    }
+
+    // Without a HEAD (e.g., in a new repo), there's nothing to key on.
+    let (head, blobs) = match (
+        git::commit_ish_into_sha(git, "HEAD"),
+        git::list_head_blobs(git),
+    ) {
+        (Ok(h), Ok(b)) => (h, b),
+        _ => return find(paths),
+    };
+    let settings = settings(git, ignoring_commits, method);
+
+    let mut known = load(git, &head, &settings, &blobs);
+
+    // Look up whatever we don't already know.
+    let (cached, unknown): (Vec<String>, Vec<String>) = paths
+        .into_iter()
+        .partition(|p| known.contains_key(p.trim_start_matches("./")));
+    info!("Found {} files' years in the cache", cached.len());
+    let mut years = if unknown.is_empty() {
+        YearMap::default()
+    } else {
+        find(unknown.into_iter().collect())?
+    };
+
+    for path in cached {
+        let entry = &known[path.trim_start_matches("./")];
+        let file_years = entry.years.iter().filter_map(|y| Year::new(*y)).collect();
+        years.insert(path, file_years);
+    }
+
+    // Remember what we just found (for files that are in HEAD).
+    for (path, file_years) in &years {
+        let path = path.trim_start_matches("./");
+        if let Some(blob) = blobs.get(path) {
+            known.insert(
+                path.to_owned(),
+                Entry {
+                    blob: blob.to_string(),
+                    years: file_years.iter().map(|y| y.get()).collect(),
+                },
+            );
+        }
+    }
+    store(
+        git,
+        Cache {
+            version: VERSION,
+            head: head.to_string(),
+            settings,
+            files: known,
+        },
+    );
+
+    Ok(years)
+}
+
+/// Loads the entries from the last run that are still good as of `head`.
+fn load(
+    git: &GitOptions,
+    head: &SHA1,
+    settings: &str,
+    blobs: &HashMap<String, SHA1>,
+) -> HashMap<String, Entry> {
+    let cache: Cache = match cache_path(git)
+        .ok()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+    {
+        Some(c) => c,
+        None => return HashMap::new(),
+    };
+    if cache.version != VERSION || cache.settings != settings {
+        return HashMap::new();
+    }
+
+    // Commits since (or, after a rebase or checkout, instead of) the cached
+    // HEAD change the history of the files they touch.
+    let touched = match SHA1::parse(&cache.head) {
+        Ok(h) if h == *head => HashSet::new(),
+        Ok(h) => match git::list_files_changed_between(git, &h, head) {
+            Ok(t) => t,
+            // (The old HEAD might not even exist anymore.)
+            Err(_) => return HashMap::new(),
+        },
+        Err(_) => return HashMap::new(),
+    };
+
+    cache
+        .files
+        .into_iter()
+        .filter(|(path, entry)| {
+            !touched.contains(path) && blobs.get(path).is_some_and(|b| b.to_string() == entry.blob)
+        })
+        .collect()
+}
+
+/// Saves the cache, replacing the old one all at once
+/// so that concurrent runs don't read half of it.
+fn store(git: &GitOptions, cache: Cache) {
+    let path = match cache_path(git) {
+        Ok(p) => p,
+        Err(_) => return,
+    };
+    let dir = path.parent().unwrap_or(&path).to_owned();
+
+    let _ = tempfile::Builder::new()
+        .prefix("copyrighter-cache-")
+        .tempfile_in(dir)
+        .and_then(|mut temp| {
+            serde_json::to_writer(&mut temp, &cache)?;
+            temp.flush()?;
+            temp.persist(&path).map_err(|e| e.error)?;
+            Ok(())
+        });
+}
@@ -0,0 +1,277 @@
+//! Comment styles, and figuring out which one a file uses.
+//!
+//! When we create a new notice, we need to pick a comment style for it.
+//! We try, in order:
+//!
+//! 1. Extensions the user mapped with `--comment-style`
+//! 2. The file's name or extension
+//! 3. The file's shebang, if it has one
+//! 4. The file's contents (whatever comment it starts with, if any)
+//! 5. The default style (`--default-comment-style`, or `//` if unspecified)
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use itertools::Itertools;
+
+/// A way to write a comment
+#[derive(Debug, PartialEq, Eq)]
+pub struct CommentStyle {
+    /// Starts the comment (e.g., `//` or `/*`)
+    pub opener: &'static str,
+
+    /// Starts each line inside a comment spanning several lines:
+    /// the opener again for line comments, or e.g. ` *` in a block comment
+    pub continuation: &'static str,
+
+    /// Ends the comment, if the style needs it (e.g., `*/`)
+    pub closer: Option<&'static str>,
+}
+
+impl CommentStyle {
+    /// The line that ends a comment spanning several lines, if the style
+    /// needs one. The closer lines up with the continuation's marker,
+    /// if it has one (e.g. ` */` under ` *`).
+    pub fn closing_line(&self) -> Option<String> {
+        let closer = self.closer?;
+        let marker = self.continuation.trim_start();
+        let indent = if marker.is_empty() {
+            ""
+        } else {
+            &self.continuation[..self.continuation.len() - marker.len()]
+        };
+        Some(format!("{}{}", indent, closer))
+    }
+
+    /// Does the given text (e.g. what a line starts with, trimmed)
+    /// open, continue, or close a comment in this style?
+    pub fn marks(&self, text: &[u8]) -> bool {
+        let continuation = self.continuation.trim();
+        text == self.opener.as_bytes()
+            || self.closer.is_some_and(|c| text == c.as_bytes())
+            || (!continuation.is_empty() && text == continuation.as_bytes())
+    }
+}
+
+/// A style where each line of a comment starts with the opener
+const fn line_style(opener: &'static str) -> CommentStyle {
+    CommentStyle {
+        opener,
+        continuation: opener,
+        closer: None,
+    }
+}
+
+/// A style with paired delimiters around the comment
+const fn block_style(
+    opener: &'static str,
+    continuation: &'static str,
+    closer: &'static str,
+) -> CommentStyle {
+    CommentStyle {
+        opener,
+        continuation,
+        closer: Some(closer),
+    }
+}
+
+pub const DOUBLE_SLASH: CommentStyle = line_style("//");
+// C-style blocks get a column of stars; others are just indented.
+pub const SLASH_STAR: CommentStyle = block_style("/*", " *", "*/");
+pub const HASH: CommentStyle = line_style("#");
+pub const DOUBLE_DASH: CommentStyle = line_style("--");
+pub const SEMICOLON: CommentStyle = line_style(";");
+pub const XML: CommentStyle = block_style("<!--", " ", "-->");
+pub const JINJA: CommentStyle = block_style("{#", " ", "#}");
+pub const ERB: CommentStyle = block_style("<%#", " ", "%>");
+
+/// Every style we know about
+pub const ALL_STYLES: &[&CommentStyle] = &[
+    &XML,
+    &DOUBLE_SLASH,
+    &SLASH_STAR,
+    &DOUBLE_DASH,
+    &HASH,
+    &SEMICOLON,
+    &JINJA,
+    &ERB,
+];
+
+/// The styles C-like languages have (which is most of them)
+const C_LIKE: &[&CommentStyle] = &[&DOUBLE_SLASH, &SLASH_STAR];
+
+/// Looks up a style by its opener, as given on the command line.
+pub fn from_opener(opener: &str) -> Option<&'static CommentStyle> {
+    ALL_STYLES.iter().find(|s| s.opener == opener).copied()
+}
+
+/// A regex alternation matching any style's opener
+pub fn opener_pattern() -> String {
+    ALL_STYLES.iter().map(|s| regex::escape(s.opener)).join("|")
+}
+
+/// A regex alternation matching any style's closer
+pub fn closer_pattern() -> String {
+    ALL_STYLES
+        .iter()
+        .filter_map(|s| s.closer)
+        .map(regex::escape)
+        .join("|")
+}
+
+/// The styles comments in a file with the given name or extension can be in:
+/// the one its new notices get (see `from_extension()`), and any others
+/// its language has (e.g. `/* */` besides `//` in C).
+/// Files in languages we don't know could have any of them.
+///
+/// (`#` starts a comment in Python, but a preprocessor directive in C,
+/// so we can't take it as one everywhere.)
+pub fn used_in(path: &str) -> &'static [&'static CommentStyle] {
+    let style = match from_extension(path) {
+        Some(s) => s,
+        None => return ALL_STYLES,
+    };
+    match style.opener {
+        "//" | "/*" => C_LIKE,
+        "#" => &[&HASH],
+        "--" => &[&DOUBLE_DASH],
+        ";" => &[&SEMICOLON],
+        "<!--" => &[&XML],
+        "{#" => &[&JINJA],
+        "<%#" => &[&ERB],
+        _ => ALL_STYLES,
+    }
+}
+
+/// Guesses a file's comment style from its name or extension.
+pub fn from_extension(path: &str) -> Option<&'static CommentStyle> {
+    let path = Path::new(path);
+
+    // Some files are known by name instead of by extension.
+    let style = match path.file_name()?.to_str()? {
+        "Makefile" | "makefile" | "GNUmakefile" | "CMakeLists.txt" | "Dockerfile"
+        | "Containerfile" | "Rakefile" | "Gemfile" | "Vagrantfile" | "BUILD" | "WORKSPACE"
+        | ".gitignore" | ".gitattributes" | ".dockerignore" => Some(&HASH),
+        _ => None,
+    };
+    if style.is_some() {
+        return style;
+    }
+
+    let style = match path.extension()?.to_str()? {
+        "c" | "cc" | "cpp" | "cxx" | "c++" | "h" | "hh" | "hpp" | "hxx" | "h++" | "ipp" | "inl"
+        | "m" | "mm" | "rs" | "go" | "java" | "js" | "mjs" | "cjs" | "jsx" | "ts" | "tsx"
+        | "cs" | "fs" | "swift" | "kt" | "kts" | "scala" | "groovy" | "gradle" | "dart" | "d"
+        | "zig" | "proto" | "php" | "sol" | "v" | "sv" | "glsl" | "hlsl" | "cu" | "scss"
+        | "less" => &DOUBLE_SLASH,
+        "css" => &SLASH_STAR,
+        "py" | "pyi" | "pyx" | "sh" | "bash" | "zsh" | "fish" | "ksh" | "rb" | "pl" | "pm"
+        | "r" | "R" | "jl" | "nim" | "cr" | "ex" | "exs" | "tcl" | "ps1" | "psm1" | "yml"
+        | "yaml" | "toml" | "cfg" | "conf" | "cmake" | "mk" | "make" | "bzl" | "bazel" | "nix"
+        | "tf" | "hcl" | "awk" | "sed" | "dockerfile" => &HASH,
+        "sql" | "lua" | "hs" | "lhs" | "elm" | "ada" | "adb" | "ads" | "vhd" | "vhdl" => {
+            &DOUBLE_DASH
+        }
+        "el" | "lisp" | "lsp" | "cl" | "clj" | "cljs" | "cljc" | "edn" | "scm" | "ss" | "rkt"
+        | "asm" | "s" | "S" | "ini" => &SEMICOLON,
+        "html" | "htm" | "xhtml" | "xml" | "xsd" | "xsl" | "xslt" | "svg" | "vue" | "md"
+        | "markdown" | "plist" | "csproj" | "vcxproj" | "props" | "targets" => &XML,
+        "j2" | "jinja" | "jinja2" => &JINJA,
+        "erb" => &ERB,
+        _ => return None,
+    };
+    Some(style)
+}
+
+/// Guesses a file's comment style from its shebang line (if it has one),
+/// based on the interpreter it names.
+pub fn from_shebang(first_line: &str) -> Option<&'static CommentStyle> {
+    let command = first_line.strip_prefix("#!")?.trim();
+    let mut words = command.split_whitespace();
+
+    // For `#!/usr/bin/env foo`, the interpreter is foo.
+    let mut interpreter = Path::new(words.next()?).file_name()?.to_str()?;
+    if interpreter == "env" {
+        interpreter = words.find(|w| !w.starts_with('-'))?;
+    }
+
+    // Strip version numbers from the likes of python3 or lua5.3
+    let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    let style = match interpreter {
+        "node" | "deno" | "bun" | "ts-node" | "rust-script" | "scala" | "kotlin" => &DOUBLE_SLASH,
+        "lua" | "luajit" | "runghc" | "runhaskell" => &DOUBLE_DASH,
+        "sbcl" | "clisp" | "racket" | "guile" | "emacs" => &SEMICOLON,
+        // Nearly every other scripting language uses #,
+        // which is convenient since that's how the shebang starts.
+        _ => &HASH,
+    };
+    Some(style)
+}
+
+/// Guesses a file's comment style from the comment it starts with, if any.
+pub fn from_contents(first_line: &str) -> Option<&'static CommentStyle> {
+    let trimmed = first_line.trim_start();
+    ALL_STYLES
+        .iter()
+        .find(|s| trimmed.starts_with(s.opener))
+        .copied()
+}
+
+/// Maps files to the comment style their new notices should use.
+pub struct StyleRegistry {
+    /// Extensions the user mapped to styles, which beat our guesses
+    overrides: HashMap<String, &'static CommentStyle>,
+
+    /// The style we use when we can't work one out for a file
+    default: &'static CommentStyle,
+}
+
+impl StyleRegistry {
+    pub fn new(default: &'static CommentStyle) -> StyleRegistry {
+        StyleRegistry {
+            overrides: HashMap::new(),
+            default,
+        }
+    }
+
+    /// Use the given style for files with the given extension
+    /// (without the dot).
+    pub fn add_override(&mut self, extension: &str, style: &'static CommentStyle) {
+        self.overrides.insert(extension.to_owned(), style);
+    }
+
+    /// The styles comments in the given file can be in (see `used_in()`).
+    /// Files with an extension the user mapped could have any of them,
+    /// since we don't know what they're written in.
+    pub fn used_in(&self, path: &str) -> &'static [&'static CommentStyle] {
+        if self.override_for(path).is_some() {
+            ALL_STYLES
+        } else {
+            used_in(path)
+        }
+    }
+
+    /// The style the user mapped the given file's extension to, if any
+    fn override_for(&self, path: &str) -> Option<&'static CommentStyle> {
+        Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| self.overrides.get(e).copied())
+    }
+
+    /// Picks the style for a new notice in the given file, trying in order:
+    ///
+    /// 1. User-provided extension mappings
+    /// 2. The file's name or extension
+    /// 3. The file's shebang
+    /// 4. The comment the file starts with
+    /// 5. The default
+    pub fn detect(&self, path: &str, first_line: &str) -> &'static CommentStyle {
+        self.override_for(path)
+            .or_else(|| from_extension(path))
+            .or_else(|| from_shebang(first_line))
+            .or_else(|| from_contents(first_line))
+            .unwrap_or(self.default)
+    }
+}
@@ -1,9 +1,236 @@
-///! Common types and functions used by the rest of the binary.
+//! Common types and functions used by the rest of the binary.
 
+use std::ffi::OsStr;
+use std::fmt::{self, Display, Formatter};
+
+use log::warn;
 use rayon_hash::{HashMap, HashSet};
 
-pub type Year = u16;
+/// What we exit with when something goes wrong.
+/// (1 is reserved for `--check` finding out-of-date notices.)
+pub const ERROR_EXIT_CODE: i32 = 2;
+
+/// A calendar year that could plausibly appear in a copyright notice.
+///
+/// Only constructed through `Year::new()`, so garbage like year 0 or 65535
+/// is caught where it's parsed instead of landing in somebody's header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Year(u16);
+
+impl Year {
+    /// The earliest year we'll believe. (Nobody was writing this code before.)
+    pub const EARLIEST: u16 = 1900;
+
+    /// The latest year we'll believe.
+    pub const LATEST: u16 = 2999;
+
+    /// Returns the given year if it's plausible, or `None` otherwise.
+    pub fn new(y: u16) -> Option<Year> {
+        if (Year::EARLIEST..=Year::LATEST).contains(&y) {
+            Some(Year(y))
+        } else {
+            None
+        }
+    }
+
+    pub fn get(self) -> u16 {
+        self.0
+    }
+}
+
+impl Display for Year {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
 
 pub type YearMap = HashMap<String, Vec<Year>>;
 
 pub type PathSet = HashSet<String>;
+
+/// Turns the given paths (e.g. as `git ls-files -z` lists them) into the
+/// `String`s we keep paths as, skipping (with a warning) any whose names
+/// aren't UTF-8, and any empty ones.
+pub fn utf8_paths<'a, I>(paths: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    paths
+        .into_iter()
+        .filter(|p| !p.is_empty())
+        .filter_map(utf8_path_from_bytes)
+        .collect()
+}
+
+/// Like `utf8_paths()`, for one path (e.g. from an entry that has more
+/// than the path in it).
+pub fn utf8_path_from_bytes(path: &[u8]) -> Option<String> {
+    match std::str::from_utf8(path) {
+        Ok(p) => Some(p.to_owned()),
+        Err(_) => {
+            warn_not_utf8(&String::from_utf8_lossy(path));
+            None
+        }
+    }
+}
+
+/// Like `utf8_paths()`, for a path from the OS (e.g. on the command line).
+pub fn utf8_path(path: &OsStr) -> Option<String> {
+    let utf8 = path.to_str().map(str::to_owned);
+    if utf8.is_none() {
+        warn_not_utf8(&path.to_string_lossy());
+    }
+    utf8
+}
+
+fn warn_not_utf8(lossy: &str) {
+    warn!("Skipped {}: its name isn't UTF-8", lossy);
+}
+
+/// Who wrote (as `Name <email>`) each commit that changed each file,
+/// and the year they did, oldest first
+pub type AuthorMap = HashMap<String, Vec<(String, Year)>>;
+
+/// Returns the year it is right now, according to the system clock.
+pub fn current_year() -> Year {
+    let year = (time::now().tm_year + 1900) as u16;
+    Year::new(year).expect("The system clock is set to an implausible year")
+}
+
+/// Expands placeholders in user-provided notice text.
+///
+/// Currently that's just `{now_year}`, the year of this run.
+/// (Distinct from the years the file was modified, which are listed
+/// after the word "Copyright".)
+pub fn expand_placeholders(text: &str) -> String {
+    text.replace("{now_year}", &current_year().to_string())
+}
+
+/// "1 file" or "`n` files", for telling the user how many files something
+/// happened to.
+pub fn count_files(n: usize) -> String {
+    if n == 1 {
+        "1 file".to_owned()
+    } else {
+        format!("{} files", n)
+    }
+}
+
+/// Discards header-provided years that Git history should account for.
+pub fn trim_header_years(header_years: &mut YearMap, first_year: Year) {
+    // We trust Git history more than we do copyright comments,
+    // so discard all years after the year of the first Git commit
+    // from the ones we parsed out of the files.
+    //
+    // Unless the first commit was made at 00:00:00 on January 1,
+    // there's a chance changes were made that year before Git,
+    // so we keep the first year around.
+    for val in header_years.values_mut() {
+        val.retain(|&y| y <= first_year);
+    }
+}
+
+/// Moves years before `min` up to it, and years after `max` down to it,
+/// for policies like "no notice may claim a year before we were incorporated".
+/// Each file's years are kept sorted and deduplicated.
+pub fn clamp_years(years: &mut YearMap, min: Option<Year>, max: Option<Year>) {
+    for val in years.values_mut() {
+        clamp_year_list(val, min, max);
+    }
+}
+
+/// Like `clamp_years()`, for a single list of years.
+pub fn clamp_year_list(years: &mut Vec<Year>, min: Option<Year>, max: Option<Year>) {
+    for y in years.iter_mut() {
+        if let Some(min) = min {
+            *y = (*y).max(min);
+        }
+        if let Some(max) = max {
+            *y = (*y).min(max);
+        }
+    }
+    years.sort();
+    years.dedup();
+}
+
+/// Merges the years from headers and from history into one sorted,
+/// deduplicated list per file.
+pub fn combine_year_maps(header_years: YearMap, git_years: YearMap) -> YearMap {
+    // Merge the smaller map into the larger to try to avoid a realloc
+    let (mut larger, smaller) = if git_years.len() > header_years.len() {
+        (git_years, header_years)
+    } else {
+        (header_years, git_years)
+    };
+
+    // Transfer all of smaller's entries into larger.
+    for (k, mut v) in smaller {
+        let e = larger.entry(k).or_insert_with(Vec::new);
+        e.append(&mut v);
+    }
+
+    // Sort and dedup our master map.
+    for v in larger.values_mut() {
+        v.sort();
+        v.dedup();
+        // Once sorted and deduped, we won't be modifying this anymore,
+        // so free up any memory we aren't using.
+        v.shrink_to_fit();
+    }
+
+    // Ditto for the hashmap itself
+    larger.shrink_to_fit();
+
+    larger
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn years(ys: &[u16]) -> Vec<Year> {
+        ys.iter().map(|&y| Year::new(y).unwrap()).collect()
+    }
+
+    #[test]
+    fn implausible_years_are_rejected() {
+        for y in [0, 1, Year::EARLIEST - 1, Year::LATEST + 1, u16::MAX] {
+            assert_eq!(Year::new(y), None, "{}", y);
+        }
+        for y in [Year::EARLIEST, 2019, Year::LATEST] {
+            assert_eq!(Year::new(y).map(Year::get), Some(y));
+        }
+        assert_eq!(Year::new(2019).unwrap().to_string(), "2019");
+    }
+
+    #[test]
+    fn header_years_after_history_starts_are_trimmed() {
+        let mut header_years = YearMap::default();
+        header_years.insert("a.c".to_owned(), years(&[2010, 2014, 2015, 2018]));
+        header_years.insert("b.c".to_owned(), years(&[2018]));
+
+        trim_header_years(&mut header_years, Year::new(2015).unwrap());
+        assert_eq!(header_years["a.c"], years(&[2010, 2014, 2015]));
+        assert_eq!(header_years["b.c"], years(&[]));
+    }
+
+    #[test]
+    fn combined_years_are_sorted_and_deduped() {
+        let mut history = YearMap::default();
+        history.insert("a.c".to_owned(), years(&[2021, 2019, 2021]));
+        let mut notices = YearMap::default();
+        notices.insert("a.c".to_owned(), years(&[2019, 2003]));
+        notices.insert("b.c".to_owned(), years(&[2010]));
+
+        let combined = combine_year_maps(notices, history);
+        assert_eq!(combined["a.c"], years(&[2003, 2019, 2021]));
+        assert_eq!(combined["b.c"], years(&[2010]));
+    }
+
+    #[test]
+    fn one_file_is_singular() {
+        assert_eq!(count_files(0), "0 files");
+        assert_eq!(count_files(1), "1 file");
+        assert_eq!(count_files(12), "12 files");
+    }
+}
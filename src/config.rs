@@ -0,0 +1,186 @@
+//! Reads `.copyrighter.toml` from the top of the repo, so teams can keep
+//! their copyright policy there instead of in shell scripts.
+//!
+//! Keys are named after their command line options, e.g.
+//!
+//! ```toml
+//! organization = "Fluke Corporation"
+//! organization-suffix = "All rights reserved."
+//! ignore-commits = ["1234abc"]
+//! ext = ["cpp", "h"]
+//! exclude = ["vendor/**"]
+//! year-separator = ", "
+//!
+//! [comment-style]
+//! tpl = "{#"
+//!
+//! [organization-by-path]
+//! "legacy/**" = "OldCo Inc."
+//!
+//! [organization-years]
+//! "OldCo Inc." = "2012-2018"
+//! ```
+//!
+//! Options given on the command line override whatever's here.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// Where we look for a config file
+pub const CONFIG_PATH: &str = ".copyrighter.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub organization: OneOrMany,
+    pub organization_suffix: Option<String>,
+
+    /// Maps globs to the organization(s) for files matching them
+    pub organization_by_path: BTreeMap<String, OneOrMany>,
+
+    /// Maps other names the organization went by to the years it did
+    /// (e.g. `2012-2018`, or `-2018` and `2019-`)
+    pub organization_years: BTreeMap<String, String>,
+
+    pub sort_holders: bool,
+    pub per_author: Option<String>,
+    pub year_separator: Option<String>,
+    pub gap_tolerance: Option<u16>,
+    pub min_year: Option<YearSetting>,
+    pub max_year: Option<YearSetting>,
+    pub symbol: Option<String>,
+    pub format: Option<String>,
+    pub normalize: bool,
+    pub force_org: bool,
+    pub allow_holder: Vec<String>,
+    pub spdx: Option<String>,
+    pub template: Option<String>,
+    pub placement: Option<String>,
+    pub add_missing_only: bool,
+    pub update_only: bool,
+    pub write_strategy: Option<String>,
+    pub backup: Option<String>,
+    pub preserve_mtime: bool,
+    pub allow_chmod: bool,
+    pub jobs: Option<usize>,
+    pub default_comment_style: Option<String>,
+
+    /// Maps extensions to comment styles, like `--comment-style`
+    pub comment_style: HashMap<String, String>,
+
+    pub scan_lines: Option<usize>,
+    pub max_line_length: Option<usize>,
+
+    pub git_backend: Option<String>,
+    pub ignore_commits: Vec<String>,
+    pub ignore_revs_file: Option<String>,
+    pub ignore_authors: Option<String>,
+    pub ignore_message: Vec<String>,
+    pub no_merges: bool,
+    pub ignore_whitespace: bool,
+    pub date_source: Option<String>,
+    pub color: Option<String>,
+    pub output_format: Option<String>,
+    pub include_dirty: bool,
+    pub strict_history: bool,
+    pub no_cache: bool,
+    pub include_generated: bool,
+    pub changed_since: Option<String>,
+    pub years_from: Option<String>,
+    pub ext: Vec<String>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub skip_vendored: bool,
+}
+
+/// A year, or a name for one (like `"now"`)
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum YearSetting {
+    Year(u16),
+    Named(String),
+}
+
+impl YearSetting {
+    pub fn into_string(self) -> String {
+        match self {
+            YearSetting::Year(y) => y.to_string(),
+            YearSetting::Named(n) => n,
+        }
+    }
+}
+
+/// A single string or a list of them
+/// (for options that can be repeated on the command line)
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for OneOrMany {
+    fn default() -> Self {
+        OneOrMany::Many(Vec::new())
+    }
+}
+
+impl OneOrMany {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(s) => vec![s],
+            OneOrMany::Many(v) => v,
+        }
+    }
+}
+
+/// A starter config file (for `copyrighter init`) with the given
+/// organization(s) and extensions, if we know any,
+/// and other common keys commented out.
+pub fn starter(organizations: &[String], extensions: &[String]) -> String {
+    let quote = |s: &String| toml::Value::String(s.clone()).to_string();
+    let organization = match organizations {
+        [] => "# organization = \"Fluke Corporation\"".to_owned(),
+        [one] => format!("organization = {}", quote(one)),
+        many => format!("organization = [{}]", many.iter().map(quote).join(", ")),
+    };
+    let ext = match extensions {
+        [] => "# ext = [\"c\", \"h\"]".to_owned(),
+        some => format!("ext = [{}]", some.iter().map(quote).join(", ")),
+    };
+
+    format!(
+        "# Settings for copyrighter. Keys are named after its command line options\n\
+         # (see `copyrighter update --help`), which override what's here.\n\
+         \n\
+         {}\n\
+         # organization-suffix = \"All rights reserved.\"\n\
+         {}\n\
+         # exclude = [\"vendor/**\"]\n\
+         # ignore-revs-file = \".git-blame-ignore-revs\"\n\
+         # spdx = \"MIT\"\n",
+        organization, ext
+    )
+}
+
+/// Reads the config file if there is one, or returns the default (empty)
+/// config if there isn't. Fails if the file is there but we can't make
+/// sense of it, since silently ignoring policy is worse than stopping.
+pub fn load() -> Result<Config, Error> {
+    let contents = match fs::read_to_string(CONFIG_PATH) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(Error::io(format!("read {}", CONFIG_PATH))(e)),
+    };
+
+    toml::from_str(&contents).map_err(|source| Error::Config {
+        path: CONFIG_PATH.to_owned(),
+        source,
+    })
+}
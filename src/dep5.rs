@@ -0,0 +1,180 @@
+//! Writes a machine-readable `debian/copyright` file (DEP-5) for the repo
+//! (`copyrighter dep5`), so packagers don't have to keep one up by hand.
+//!
+//! Files are grouped by who holds them (as in `notice_file`: with the years
+//! their notices will list once updated) and their license, which is
+//! the one we'd give them (`--spdx`), or whatever their
+//! `SPDX-License-Identifier:` line says. The group with the most files
+//! becomes the catch-all `Files: *` paragraph, and each of the others lists
+//! its files, or the directories all of whose files are in it (as `dir/*`).
+//! (Later paragraphs override earlier ones, so the exceptions come after.)
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use itertools::Itertools;
+use log::error;
+use rayon::prelude::*;
+
+use crate::common::{Year, YearMap};
+use crate::encoding;
+use crate::notice::find_license;
+use crate::notice_file::{file_holders, read_notice, year_ranges};
+use crate::update::NoticeOptions;
+
+/// The format we write, which starts the file
+pub const FORMAT: &str = "https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/";
+
+/// What we say about files without a license
+const UNKNOWN_LICENSE: &str = "UNKNOWN";
+
+/// What a group of files have in common: their copyright lines
+/// (e.g. `2019-2021 Acme`) and their license
+type Stanza = (Vec<String>, String);
+
+/// The header paragraph's fields, if given
+#[derive(Default)]
+pub struct Header {
+    /// The name of the upstream project
+    pub upstream_name: Option<String>,
+
+    /// Where to get the upstream source
+    pub source: Option<String>,
+}
+
+/// Works out the given files' holders and licenses, and renders them
+/// (after the given header) as a DEP-5 `debian/copyright` file.
+pub fn render(header: &Header, years: &YearMap, options: &NoticeOptions) -> String {
+    let files: Vec<(String, Stanza)> = years
+        .par_iter()
+        .filter_map(|(path, years)| match stanza(path, years, options) {
+            Ok(s) => Some((path.trim_start_matches("./").to_owned(), s)),
+            Err(e) => {
+                error!("Couldn't read {}: {}", path, e);
+                None
+            }
+        })
+        .collect();
+
+    let mut text = format!("Format: {}\n", FORMAT);
+    if let Some(name) = &header.upstream_name {
+        text.push_str(&format!("Upstream-Name: {}\n", name));
+    }
+    if let Some(source) = &header.source {
+        text.push_str(&format!("Source: {}\n", source));
+    }
+
+    let mut groups: BTreeMap<&Stanza, Vec<&str>> = BTreeMap::new();
+    for (path, stanza) in &files {
+        groups.entry(stanza).or_default().push(path);
+    }
+    // (The biggest group comes first; ties go to whichever sorts first.)
+    let mut groups: Vec<(&Stanza, Vec<&str>)> = groups.into_iter().collect();
+    groups.sort_by_key(|(_, paths)| std::cmp::Reverse(paths.len()));
+
+    let by_path: HashMap<&str, &Stanza> = files.iter().map(|(p, s)| (p.as_str(), s)).collect();
+    for (i, (stanza, paths)) in groups.iter().enumerate() {
+        let globs = if i == 0 {
+            vec!["*".to_owned()]
+        } else {
+            globs(paths, stanza, &by_path)
+        };
+        text.push_str(&paragraph(&globs, stanza));
+    }
+    text
+}
+
+/// Finds what the given file's paragraph says.
+fn stanza(path: &str, years: &[Year], options: &NoticeOptions) -> io::Result<Stanza> {
+    let notice = read_notice(path, &options.scan)?;
+    let copyrights = file_holders(path, years, options, notice.as_ref())
+        .into_iter()
+        .map(|(holder, years)| {
+            if years.is_empty() {
+                holder
+            } else {
+                format!("{} {}", year_ranges(&years), holder)
+            }
+        })
+        .collect();
+
+    // We'd leave the license of someone else's file alone, too.
+    let foreign = notice
+        .as_ref()
+        .is_some_and(|n| options.foreign_holder(path, &n.holders).is_some());
+    let license = match &options.license {
+        Some(id) if !foreign => Some(id.clone()),
+        _ => find_license(
+            BufReader::new(encoding::open(path)?),
+            &options.scan.for_file(path),
+        )?
+        .map(|l| l.id),
+    };
+    let license = license.map_or_else(|| UNKNOWN_LICENSE.to_owned(), |id| license_name(&id));
+
+    Ok((copyrights, license))
+}
+
+/// DEP-5 writes SPDX expressions' operators in lowercase,
+/// e.g. `MIT or Apache-2.0`.
+fn license_name(id: &str) -> String {
+    id.split(' ')
+        .map(|word| match word {
+            "OR" => "or",
+            "AND" => "and",
+            "WITH" => "with",
+            w => w,
+        })
+        .join(" ")
+}
+
+/// Lists the given files (which share the given stanza) for their paragraph,
+/// giving the highest directories all of whose files share it as `dir/*`.
+fn globs(paths: &[&str], stanza: &Stanza, by_path: &HashMap<&str, &Stanza>) -> Vec<String> {
+    // The stanzas of the files in (or under) each directory
+    let mut dirs: HashMap<&Path, BTreeSet<&Stanza>> = HashMap::new();
+    for (path, s) in by_path {
+        for dir in Path::new(path).ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            dirs.entry(dir).or_default().insert(s);
+        }
+    }
+
+    let mut globs = BTreeSet::new();
+    for path in paths {
+        let ancestors: Vec<&Path> = Path::new(path)
+            .ancestors()
+            .skip(1)
+            .take_while(|d| !d.as_os_str().is_empty())
+            .collect();
+        let dir = ancestors
+            .iter()
+            .rev()
+            .find(|d| dirs[*d].len() == 1 && dirs[*d].contains(stanza));
+        globs.insert(match dir {
+            Some(d) => format!("{}/*", d.display()),
+            None => (*path).to_owned(),
+        });
+    }
+    globs.into_iter().collect()
+}
+
+/// Renders a `Files` paragraph, after a blank line.
+fn paragraph(globs: &[String], (copyrights, license): &Stanza) -> String {
+    let mut text = format!("\nFiles: {}\n", globs.join("\n "));
+    match copyrights.split_first() {
+        Some((first, rest)) => {
+            text.push_str(&format!("Copyright: {}\n", first));
+            for line in rest {
+                text.push_str(&format!("           {}\n", line));
+            }
+        }
+        // (Copyright is required, even if nobody's named.)
+        None => text.push_str("Copyright: NONE\n"),
+    }
+    text.push_str(&format!("License: {}\n", license));
+    text
+}
@@ -0,0 +1,169 @@
+//! Renders the edits we'd make to files as unified diffs,
+//! for dry runs to show (or `git apply` to make later),
+//! and colors them for terminals.
+
+use crate::update::Edit;
+
+/// How many unchanged lines we show around a change
+const CONTEXT_LINES: usize = 3;
+
+/// Renders a unified diff of making the given edit to a file
+/// with the given contents, or `None` if the edit doesn't change anything.
+/// (Lines that aren't UTF-8 are shown as best we can.)
+pub fn unified_diff(path: &str, contents: &[u8], edit: &Edit) -> Option<String> {
+    let edit_end = edit.start + edit.old_length;
+    let mut edited = contents.to_vec();
+    edited.splice(edit.start..edit_end, edit.text.bytes());
+
+    let old: Vec<&[u8]> = contents.split_inclusive(|b| *b == b'\n').collect();
+    let new: Vec<&[u8]> = edited.split_inclusive(|b| *b == b'\n').collect();
+
+    // Our edits only touch one spot in the file,
+    // so everything around the lines that differ is context.
+    let prefix = old.iter().zip(&new).take_while(|(o, n)| o == n).count();
+    if prefix == old.len() && prefix == new.len() {
+        return None;
+    }
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+
+    let before = prefix.min(CONTEXT_LINES);
+    let after = suffix.min(CONTEXT_LINES);
+    let hunk_start = prefix - before;
+    let old_end = old.len() - suffix;
+    let new_end = new.len() - suffix;
+
+    // Paths are relative to the top of the repo, which is what Git wants.
+    let path = path.trim_start_matches("./");
+    let mut diff = format!(
+        "diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n@@ -{1} +{2} @@\n",
+        path,
+        hunk_range(hunk_start, old_end - hunk_start + after),
+        hunk_range(hunk_start, new_end - hunk_start + after),
+    );
+
+    for line in &old[hunk_start..prefix] {
+        push_line(&mut diff, ' ', line);
+    }
+    for (marker, line) in diff_lines(&old[prefix..old_end], &new[prefix..new_end]) {
+        push_line(&mut diff, marker, line);
+    }
+    for line in &old[old_end..old_end + after] {
+        push_line(&mut diff, ' ', line);
+    }
+
+    Some(diff)
+}
+
+/// Renders a unified diff that creates a file with the given contents.
+pub fn new_file_diff(path: &str, contents: &str) -> String {
+    let path = path.trim_start_matches("./");
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let mut diff = format!(
+        "diff --git a/{0} b/{0}\nnew file mode 100644\n--- /dev/null\n+++ b/{0}\n@@ -0,0 +{1} @@\n",
+        path,
+        hunk_range(0, lines.len()),
+    );
+    for line in lines {
+        push_line(&mut diff, '+', line.as_bytes());
+    }
+    diff
+}
+
+/// Marks each line as kept (' '), removed ('-'), or added ('+'),
+/// keeping as many as possible via the longest common subsequence.
+/// (We only diff the part of the file around our edit, so quadratic is fine.)
+fn diff_lines<'a>(old: &[&'a [u8]], new: &[&'a [u8]]) -> Vec<(char, &'a [u8])> {
+    // common[i][j] is how many lines old[i..] and new[j..] have in common.
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Formats the (zero-based) start and length of a hunk the way diff does:
+/// one-based, unless the hunk is empty, in which case it's the line before.
+fn hunk_range(start: usize, length: usize) -> String {
+    if length == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, length)
+    }
+}
+
+fn push_line(diff: &mut String, marker: char, line: &[u8]) {
+    diff.push(marker);
+    diff.push_str(&String::from_utf8_lossy(line));
+    if !line.ends_with(b"\n") {
+        diff.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// Colors a patch with ANSI escapes the way `git diff` does for a terminal:
+/// file headers in bold, hunk headers in cyan, removed lines in red,
+/// and added lines in green.
+pub fn colorize(patch: &str) -> String {
+    const BOLD: &str = "\x1b[1m";
+    const CYAN: &str = "\x1b[36m";
+    const RED: &str = "\x1b[31m";
+    const GREEN: &str = "\x1b[32m";
+    const RESET: &str = "\x1b[m";
+
+    let mut colored = String::with_capacity(patch.len() * 2);
+    // (A removed line could start with "--- " too, so only the lines
+    // between "diff --git" and the first hunk are file headers.)
+    let mut in_header = false;
+    for line in patch.split_inclusive('\n') {
+        if line.starts_with("diff --git ") {
+            in_header = true;
+        } else if line.starts_with("@@") {
+            in_header = false;
+        }
+
+        let color = if in_header {
+            BOLD
+        } else if line.starts_with("@@") {
+            CYAN
+        } else if line.starts_with('-') {
+            RED
+        } else if line.starts_with('+') {
+            GREEN
+        } else {
+            colored.push_str(line);
+            continue;
+        };
+        let text = line.trim_end_matches('\n');
+        colored.push_str(color);
+        colored.push_str(text);
+        colored.push_str(RESET);
+        colored.push_str(&line[text.len()..]);
+    }
+    colored
+}
@@ -0,0 +1,201 @@
+//! Finds the files to work on ourselves, so that the usual invocation
+//! doesn't need `find -exec`.
+//!
+//! We ask Git (or Mercurial) which files are in the repo (tracked, or untracked
+//! but not ignored), then keep the ones matching the given extensions and globs.
+//!
+//! With `--skip-vendored`, we also pass over files that are probably someone
+//! else's: ones the repo ignores, and ones in directories like `vendor/`.
+//!
+//! Whatever `.copyrighterignore` matches is left out, however we got it.
+
+use std::collections::HashSet;
+use std::path::{Component, Path};
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::GitignoreBuilder;
+use log::debug;
+
+use crate::common::PathSet;
+use crate::error::Error;
+use crate::git::{self, GitOptions};
+use crate::vcs::Vcs;
+
+/// Where teams list files we should never touch, written like a `.gitignore`
+pub const IGNORE_PATH: &str = ".copyrighterignore";
+
+/// Directories that usually hold other people's code
+pub const VENDORED_DIRS: &[&str] = &["third_party", "third-party", "node_modules", "vendor"];
+
+/// Which of the repo's files we want
+pub struct FileFilter {
+    /// Extensions (without the dot) of files we want.
+    /// If empty, any extension will do.
+    extensions: Vec<String>,
+
+    /// Globs matching files we want, if any were given
+    include: Option<GlobSet>,
+
+    /// Globs matching files we don't want, even if they'd otherwise match
+    exclude: GlobSet,
+}
+
+impl FileFilter {
+    /// Builds a filter from the given extensions and globs,
+    /// or complains about the first bad glob.
+    pub fn new(
+        extensions: Vec<String>,
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<FileFilter, globset::Error> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(include)?)
+        };
+
+        Ok(FileFilter {
+            extensions,
+            include,
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    /// Does the filter want the given path?
+    pub fn matches(&self, path: &str) -> bool {
+        let extension_matches = self.extensions.is_empty()
+            || Path::new(path)
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| self.extensions.iter().any(|x| x == e));
+
+        extension_matches
+            && self.include.as_ref().is_none_or(|i| i.is_match(path))
+            && !self.exclude.is_match(path)
+    }
+
+    /// Does an --include glob name the given path?
+    fn explicitly_includes(&self, path: &str) -> bool {
+        self.include.as_ref().is_some_and(|i| i.is_match(path))
+    }
+}
+
+/// Parses a glob to match paths in the repo against.
+pub fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    // Like .gitignore, * shouldn't match across directories. (** does.)
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in globs {
+        builder.add(glob(pattern)?);
+    }
+    builder.build()
+}
+
+/// Finds all the files in the repo that the filter wants.
+pub fn find_files(vcs: &dyn Vcs, filter: &FileFilter) -> Result<PathSet, Error> {
+    Ok(vcs
+        .list_files()?
+        .into_iter()
+        // Deleted files that haven't been committed yet are still tracked.
+        .filter(|f| filter.matches(f) && Path::new(f).is_file())
+        .collect())
+}
+
+/// Is the given file in one of the `VENDORED_DIRS`?
+pub fn is_vendored(path: &str) -> bool {
+    Path::new(path)
+        .parent()
+        .into_iter()
+        .flat_map(|p| p.components())
+        .any(|c| match c {
+            Component::Normal(dir) => dir.to_str().is_some_and(|d| VENDORED_DIRS.contains(&d)),
+            _ => false,
+        })
+}
+
+/// Drops the files the repo ignores, and the ones in `VENDORED_DIRS`
+/// that the filter's --include globs don't name, so that we don't put
+/// our notices on other people's code.
+/// (Files we find ourselves were never ignored, but ones we're given might be.)
+pub fn skip_vendored(vcs: &dyn Vcs, paths: PathSet, filter: &FileFilter) -> Result<PathSet, Error> {
+    let ignored: HashSet<String> = vcs
+        .ignored_files(&paths)?
+        .into_iter()
+        .map(|p| p.trim_start_matches("./").to_owned())
+        .collect();
+
+    Ok(paths
+        .into_iter()
+        .filter(|p| {
+            let path = p.trim_start_matches("./");
+            if ignored.contains(path) {
+                debug!("Skipping {}: {} ignores it", path, vcs.kind().name());
+                false
+            } else if is_vendored(path) && !filter.explicitly_includes(path) {
+                debug!("Skipping {}: it looks vendored", path);
+                false
+            } else {
+                true
+            }
+        })
+        .collect())
+}
+
+/// Drops the files `.copyrighterignore` matches, if there is one.
+/// (Paths outside the repo are left for Git to complain about.)
+pub fn skip_ignored(paths: PathSet) -> Result<PathSet, Error> {
+    if !Path::new(IGNORE_PATH).is_file() {
+        return Ok(paths);
+    }
+
+    let mut builder = GitignoreBuilder::new(".");
+    if let Some(source) = builder.add(IGNORE_PATH) {
+        return Err(Error::IgnoreFile {
+            path: IGNORE_PATH.to_owned(),
+            source,
+        });
+    }
+    let ignore = builder.build().map_err(|source| Error::IgnoreFile {
+        path: IGNORE_PATH.to_owned(),
+        source,
+    })?;
+
+    Ok(paths
+        .into_iter()
+        .filter(|p| {
+            let path = Path::new(p.trim_start_matches("./"));
+            let skip = !path.has_root()
+                && !path.starts_with("..")
+                && ignore.matched_path_or_any_parents(path, false).is_ignore();
+            if skip {
+                debug!("Skipping {}: {} matches it", path.display(), IGNORE_PATH);
+            }
+            !skip
+        })
+        .collect())
+}
+
+/// Finds the staged files (see `git::list_staged_files()`) that the filter wants.
+pub fn find_staged_files(git: &GitOptions, filter: &FileFilter) -> Result<PathSet, Error> {
+    Ok(git::list_staged_files(git)?
+        .into_iter()
+        .filter(|f| filter.matches(f) && Path::new(f).is_file())
+        .collect())
+}
+
+/// Finds the files changed since the given ref (see `git::list_changed_files()`)
+/// that the filter wants.
+pub fn find_changed_files(
+    git: &GitOptions,
+    since: &str,
+    filter: &FileFilter,
+) -> Result<PathSet, Error> {
+    Ok(git::list_changed_files(git, since)?
+        .into_iter()
+        // Files the changes deleted are still listed.
+        .filter(|f| filter.matches(f) && Path::new(f).is_file())
+        .collect())
+}
@@ -0,0 +1,138 @@
+//! Some files (mostly ones from Windows tools) are in UTF-16, which we can
+//! tell by their byte order mark, and some older ones are in Latin-1,
+//! which we can tell by them not being UTF-8. Everything else reads files
+//! as UTF-8 (or close enough, see `notice`), so we decode those for it,
+//! and encode what we write to them back the way they were.
+
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+
+/// How much of a file we look at to tell how it's encoded
+/// (Notices are at the top, so that's what we need to get right.)
+const SNIFF_LENGTH: u64 = 8 * 1024;
+
+/// How a file's text is encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 (or anything else that isn't one of the others)
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+
+    /// ISO 8859-1, which we take any text that isn't UTF-8 to be.
+    /// (Each byte is its own character, so whatever the file really holds,
+    /// the parts we don't edit are written back just as they were.)
+    Latin1,
+}
+
+impl Encoding {
+    /// Tells how the given file contents are encoded by how they start.
+    pub fn detect(start: &[u8]) -> Self {
+        if start.starts_with(&[0xff, 0xfe]) {
+            Encoding::Utf16Le
+        } else if start.starts_with(&[0xfe, 0xff]) {
+            Encoding::Utf16Be
+        } else if looks_latin1(start) {
+            Encoding::Latin1
+        } else {
+            Encoding::Utf8
+        }
+    }
+
+    /// Decodes the given contents to UTF-8.
+    /// (The byte order mark becomes UTF-8's, so offsets past it line up.)
+    pub fn decode(self, bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+        let unit = match self {
+            Encoding::Utf8 => return Ok(bytes),
+            Encoding::Latin1 => {
+                return Ok(bytes
+                    .into_iter()
+                    .map(char::from)
+                    .collect::<String>()
+                    .into_bytes())
+            }
+            Encoding::Utf16Le => u16::from_le_bytes,
+            Encoding::Utf16Be => u16::from_be_bytes,
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "it isn't valid UTF-16");
+        if !bytes.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        let units = bytes.chunks_exact(2).map(|c| unit([c[0], c[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map(String::into_bytes)
+            .map_err(|_| invalid())
+    }
+
+    /// Encodes the given UTF-8 (e.g. what we decoded, once it's edited)
+    /// the way this file wants it, or fails if it has characters that
+    /// the file's encoding can't hold.
+    pub fn encode(self, utf8: &[u8]) -> io::Result<Vec<u8>> {
+        let unit = match self {
+            Encoding::Utf8 => return Ok(utf8.to_vec()),
+            Encoding::Latin1 => {
+                return String::from_utf8_lossy(utf8)
+                    .chars()
+                    .map(|c| {
+                        u8::try_from(c).map_err(|_| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("it's Latin-1, which has no {:?}", c),
+                            )
+                        })
+                    })
+                    .collect()
+            }
+            Encoding::Utf16Le => u16::to_le_bytes,
+            Encoding::Utf16Be => u16::to_be_bytes,
+        };
+        Ok(String::from_utf8_lossy(utf8)
+            .encode_utf16()
+            .flat_map(unit)
+            .collect())
+    }
+}
+
+/// Does the given start of a file look like Latin-1 text?
+/// It does if it isn't UTF-8, unless it's binary (with NULs).
+/// (A character cut off where `start` ends doesn't count against it.)
+fn looks_latin1(start: &[u8]) -> bool {
+    let start = &start[..start.len().min(SNIFF_LENGTH as usize)];
+    match std::str::from_utf8(start) {
+        Ok(_) => false,
+        Err(e) => e.error_len().is_some() && !start.contains(&0),
+    }
+}
+
+/// Finds how the given file is encoded, and returns it, along with
+/// a file to read its contents from as UTF-8: itself, or a decoded copy
+/// if it's UTF-16 or Latin-1.
+pub fn utf8_view(mut fh: File) -> io::Result<(Encoding, File)> {
+    let mut start = Vec::new();
+    (&mut fh).take(SNIFF_LENGTH).read_to_end(&mut start)?;
+    fh.rewind()?;
+    let encoding = Encoding::detect(&start);
+    if encoding == Encoding::Utf8 {
+        return Ok((encoding, fh));
+    }
+
+    let mut contents = Vec::new();
+    fh.read_to_end(&mut contents)?;
+    let mut decoded = tempfile::tempfile()?;
+    decoded.write_all(&encoding.decode(contents)?)?;
+    decoded.rewind()?;
+    Ok((encoding, decoded))
+}
+
+/// Opens the given file to read as UTF-8 (see `utf8_view()`).
+pub fn open(path: &str) -> io::Result<File> {
+    Ok(utf8_view(File::open(path)?)?.1)
+}
+
+/// Reads the given file's contents as UTF-8 (see `utf8_view()`).
+pub fn read(path: &str) -> io::Result<Vec<u8>> {
+    let contents = fs::read(path)?;
+    Encoding::detect(&contents).decode(contents)
+}
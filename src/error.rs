@@ -0,0 +1,97 @@
+//! The ways a run can go wrong, so that they can be passed up to whoever
+//! called us (main, or a tool using the library) instead of exiting.
+
+use std::io;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Couldn't run `git {command}`: {source}")]
+    GitSpawn { command: String, source: io::Error },
+
+    /// Git ran, but failed (and printed why to stderr).
+    #[error("Git couldn't {what}")]
+    GitFailed { what: String },
+
+    #[error("Unexpected output from Git: {0}")]
+    GitOutput(String),
+
+    #[error("Couldn't run `hg {command}`: {source}")]
+    HgSpawn { command: String, source: io::Error },
+
+    /// Mercurial ran, but failed (and printed why to stderr).
+    #[error("Mercurial couldn't {what}")]
+    HgFailed { what: String },
+
+    #[error("Unexpected output from Mercurial: {0}")]
+    HgOutput(String),
+
+    /// Something only Git repos can do was asked of another kind.
+    #[error("{what} isn't supported in {vcs} repositories")]
+    Unsupported { what: String, vcs: &'static str },
+
+    #[error("History provided an implausible commit year: {0}")]
+    ImplausibleYear(i64),
+
+    #[cfg(feature = "libgit2")]
+    #[error("Couldn't {what}: {}", source.message())]
+    Libgit2 { what: String, source: git2::Error },
+
+    #[error("Not in a Git or Mercurial repository")]
+    NotInRepo,
+
+    #[error("Not at the top of the repository\n(This makes reasoning about paths much simpler.)")]
+    NotAtRepoTop,
+
+    /// `--staged` can't tell which changes are being committed.
+    #[error(
+        "Some staged files have unstaged changes too: {0}\n\
+         (Stage or stash them first, so that only what's being committed is updated.)"
+    )]
+    PartiallyStaged(String),
+
+    /// `restore` was run without a backed-up run to undo.
+    #[error("There's nothing to restore\n(Only runs with --backup can be undone.)")]
+    NoBackups,
+
+    /// `init` was asked to write over something that's already there.
+    #[error("{0} already exists\n(Use --force to replace it.)")]
+    AlreadyExists(String),
+
+    /// Reading or writing a file (or the like) failed.
+    #[error("Couldn't {what}: {source}")]
+    Io { what: String, source: io::Error },
+
+    #[error("Couldn't parse {path}: {source}")]
+    Config {
+        path: String,
+        source: toml::de::Error,
+    },
+
+    /// `.copyrighterignore` has a pattern we can't make sense of.
+    #[error("Couldn't parse {path}: {source}")]
+    IgnoreFile { path: String, source: ignore::Error },
+
+    /// `copyrighter watch` couldn't keep an eye on the working tree.
+    #[error("Couldn't watch the working tree: {0}")]
+    Watch(notify::Error),
+}
+
+impl Error {
+    /// Wraps an I/O error with `what` we were trying to do,
+    /// e.g. `read .git-blame-ignore-revs`.
+    pub fn io<S: Into<String>>(what: S) -> impl FnOnce(io::Error) -> Error {
+        let what = what.into();
+        move |source| Error::Io { what, source }
+    }
+
+    /// Wraps a libgit2 error with `what` we were trying to do.
+    #[cfg(feature = "libgit2")]
+    pub fn libgit2<S: Into<String>>(what: S) -> impl FnOnce(git2::Error) -> Error {
+        let what = what.into();
+        move |source| Error::Libgit2 { what, source }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -2,70 +2,93 @@
 //! This is useful for years that may have taken place before adding the file
 //! to Git.
 
-use std::fs::File;
-use std::io::{self, BufReader};
-use std::io::prelude::*;
+use std::io::{self, BufRead, BufReader, Cursor, Seek, SeekFrom};
 
-use lazy_static::lazy_static;
+use log::{debug, error, warn};
 use rayon::prelude::*;
-use regex::Regex;
 
 use crate::common::*;
+use crate::encoding::{self, Encoding};
+use crate::notice::{find_notice, find_repeated_lines, parse_year_list, ScanOptions};
 
-pub fn get_year_map(paths: PathSet) -> YearMap {
+pub fn get_year_map(paths: PathSet, scan: &ScanOptions) -> YearMap {
+    scan_files(paths, scan, |path, e| {
+        error!("Couldn't read {}: {}", path, e)
+    })
+}
+
+/// Like `get_year_map()`, but for files we're about to update,
+/// which reports any we can't read, so we don't complain about them here too.
+pub fn get_year_map_to_update(paths: PathSet, scan: &ScanOptions) -> YearMap {
+    scan_files(paths, scan, |path, e| {
+        debug!("Couldn't read {} (yet): {}", path, e)
+    })
+}
+
+/// Scans the given files, leaving out (and telling `on_error` about)
+/// any we can't read.
+fn scan_files<F>(paths: PathSet, scan: &ScanOptions, on_error: F) -> YearMap
+where
+    F: Fn(&str, io::Error) + Sync,
+{
     // Let's paralellize! I'm assuming this process will be largely bottlenecked
     // by the I/O of actually reading the files, but we can let the OS'es I/O
     // scheduler figure that out.
     paths
         .into_par_iter()
-        .filter_map(|path| match scan_file(&path) {
+        .filter_map(|path| match scan_file(&path, scan) {
             Ok(v) => Some((path, v)),
             Err(e) => {
-                eprintln!("Error reading {}: {}", path, e);
+                on_error(&path, e);
                 None
             }
         })
         .collect()
 }
 
-fn scan_file(path: &str) -> io::Result<Vec<Year>> {
-    // Open the file and read in the first line.
-    let mut first_line = String::new();
-    {
-        let fh = File::open(path)?;
-        let mut br = BufReader::new(fh);
-        br.read_line(&mut first_line)?;
-    }
+/// Finds the years listed in the notice at the top of the given contents
+/// of the file at `path` (e.g. read from stdin, instead of from the file).
+pub fn scan_contents(path: &str, contents: &[u8], options: &ScanOptions) -> io::Result<Vec<Year>> {
+    let contents = Encoding::detect(contents).decode(contents.to_vec())?;
+    scan(path, Cursor::new(contents), options)
+}
 
-    lazy_static! {
-        static ref COPYRIGHT: Regex = Regex::new(r"^\s*/[/*].*[Cc]opyright").unwrap();
-        static ref YEAR_OR_RANGE: Regex =
-            Regex::new(r"((\d{4})\s*[-–—]\s*(\d{4}))|(\d{4})").unwrap();
-    }
+fn scan_file(path: &str, options: &ScanOptions) -> io::Result<Vec<Year>> {
+    scan(path, BufReader::new(encoding::open(path)?), options)
+}
+
+fn scan<R: BufRead + Seek>(
+    path: &str,
+    mut reader: R,
+    options: &ScanOptions,
+) -> io::Result<Vec<Year>> {
+    // Look for a notice at the top.
+    let scan = options.for_file(path);
+    let notice = find_notice(&mut reader, &scan)?;
 
     let mut years: Vec<Year> = Vec::new();
 
-    // The first line isn't a copyright line. Move on to the next file.
-    if !COPYRIGHT.is_match(&first_line) {
-        return Ok(years);
-    }
+    let notice = match notice {
+        Some(n) => n,
+        // There's no copyright notice. Move on to the next file.
+        None => return Ok(years),
+    };
 
-    for cap in YEAR_OR_RANGE.captures_iter(&first_line) {
-        match cap.get(1) {
-            // A single year:
-            None => {
-                years.push(cap.get(4).unwrap().as_str().parse().unwrap());
-            }
-            // A range of years (<yyyy>-<yyyy>):
-            Some(_) => {
-                let start: Year = cap.get(2).unwrap().as_str().parse().unwrap();
-                let end: Year = cap.get(3).unwrap().as_str().parse().unwrap();
+    // Lines further down that repeat one of the notice's holders
+    // count as part of it.
+    reader.seek(SeekFrom::Start(0))?;
+    let repeated = find_repeated_lines(reader, &notice, &scan)?;
 
-                for i in start..=end {
-                    years.push(i);
-                }
-            }
-        };
+    let lists = notice
+        .year_lists
+        .iter()
+        .chain(repeated.iter().map(|r| &r.year_list));
+    for list in lists {
+        let (mut parsed, complaints) = parse_year_list(list);
+        for complaint in complaints {
+            warn!("{} in {}", complaint, path);
+        }
+        years.append(&mut parsed);
     }
 
     Ok(years)
@@ -0,0 +1,50 @@
+//! Reads the years each file was changed from someone else's records
+//! (`--years-from`), for builds that already track that and just want us
+//! to rewrite notices.
+//!
+//! The file maps paths (relative to the top of the repo) to the years
+//! each one was changed, like
+//!
+//! ```json
+//! {
+//!   "src/main.rs": [2019, 2020],
+//!   "src/lib.rs": [2020]
+//! }
+//! ```
+//!
+//! These stand in for history: notices' years are trimmed and merged
+//! with them just as they would be with years from Git.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+use crate::common::{Year, YearMap};
+use crate::error::Error;
+
+/// Reads the years each file was changed from the given file.
+pub fn read_year_map(path: &str) -> Result<YearMap, Error> {
+    let json = fs::read_to_string(path).map_err(Error::io(format!("read {}", path)))?;
+    let listed: BTreeMap<String, Vec<u16>> =
+        serde_json::from_str(&json).map_err(|e| Error::io(format!("parse {}", path))(e.into()))?;
+
+    let mut years = YearMap::default();
+    for (file, file_years) in listed {
+        let mut checked = Vec::with_capacity(file_years.len());
+        for y in file_years {
+            match Year::new(y) {
+                Some(y) => checked.push(y),
+                None => {
+                    return Err(Error::io(format!("read {}", path))(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("it gives {} the implausible year {}", file, y),
+                    )));
+                }
+            }
+        }
+        checked.sort();
+        checked.dedup();
+        years.insert(file.trim_start_matches("./").to_owned(), checked);
+    }
+    Ok(years)
+}
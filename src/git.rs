@@ -1,14 +1,22 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::env;
-use std::error::Error;
 use std::fmt::{self, Display, Formatter};
-use std::process::{exit, Command, Stdio};
-use std::str;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
 
-use crate::common::Year;
+use regex::Regex;
+
+use crate::common::{utf8_path_from_bytes, utf8_paths, PathSet, Year, YearMap};
+use crate::error::Error;
+
+#[cfg(feature = "libgit2")]
+mod libgit2;
 
 /// A 20-byte SHA1 hash, used for identifying objects in Git.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct SHA1 {
     bytes: [u8; 20],
 }
@@ -19,18 +27,15 @@ pub enum SHA1ParseError {
     InvalidHexadecimal,
 }
 
-impl Error for SHA1ParseError {
-    fn description(&self) -> &str {
+impl std::error::Error for SHA1ParseError {}
+
+impl Display for SHA1ParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match *self {
             SHA1ParseError::IncorrectLength => "String is not 40 characters long",
             SHA1ParseError::InvalidHexadecimal => "String is not valid hexadecimal",
         }
-    }
-}
-
-impl Display for SHA1ParseError {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        self.description().fmt(f)
+        .fmt(f)
     }
 }
 
@@ -71,146 +76,1090 @@ impl Display for SHA1 {
     }
 }
 
-impl Default for SHA1 {
-    fn default() -> SHA1 {
-        SHA1 { bytes: [0; 20] }
+/// How we talk to Git
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Run the `git` executable
+    Cli,
+
+    /// Use libgit2. Listing files (for --ext, etc.) and `git blame`
+    /// still run the executable.
+    #[cfg(feature = "libgit2")]
+    Libgit2,
+}
+
+/// Which of each commit's dates we go by
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DateSource {
+    /// When the change was first made, which rebases and cherry-picks keep
+    Author,
+
+    /// When the commit was made, i.e. when the change landed.
+    /// Rebases and cherry-picks update it.
+    Committer,
+}
+
+/// How we talk to Git, and how we read its history
+#[derive(Clone, Debug)]
+pub struct GitOptions {
+    /// The Git executable to run, if not just `git` from the PATH
+    pub binary: Option<String>,
+
+    /// Whether we run `git` or use libgit2
+    pub backend: Backend,
+
+    /// Don't count changes that only touch whitespace (like `git log -w`)
+    /// when finding the years files were changed
+    pub ignore_whitespace: bool,
+
+    /// Which of each commit's dates we go by
+    pub date_source: DateSource,
+
+    /// Reuse the years we found for files on the last run (see `cache.rs`)
+    pub cache: bool,
+}
+
+impl Default for GitOptions {
+    fn default() -> Self {
+        GitOptions {
+            binary: None,
+            backend: Backend::Cli,
+            ignore_whitespace: false,
+            date_source: DateSource::Author,
+            cache: true,
+        }
+    }
+}
+
+/// The `git log --format` placeholder for the (ISO 8601-like) date we go by
+fn date_placeholder(git: &GitOptions) -> &'static str {
+    match git.date_source {
+        DateSource::Author => "%ai",
+        DateSource::Committer => "%ci",
+    }
+}
+
+/// Environment variables we don't pass on to Git: ones that would point it
+/// at some other repo than the one we're at the top of, add to its config,
+/// or change what it prints.
+///
+/// (`GIT_INDEX_FILE` is passed on, since pre-commit hooks for `git commit -a`
+/// and partial commits are given a temporary index in it, which is what
+/// `--staged` should look at.)
+const GIT_ENV_OVERRIDES: &[&str] = &[
+    "GIT_DIR",
+    "GIT_WORK_TREE",
+    "GIT_COMMON_DIR",
+    "GIT_NAMESPACE",
+    "GIT_CONFIG",
+    "GIT_CONFIG_PARAMETERS",
+    "GIT_CONFIG_COUNT",
+    "GIT_EXTERNAL_DIFF",
+    "GIT_DIFF_OPTS",
+    "GIT_FLUSH",
+    "LANGUAGE",
+];
+
+/// Config that changes what the commands we parse print, and what we
+/// set it to instead, whatever the repo's (or the user's) config says:
+/// UTF-8 logs, unquoted paths (for anything that isn't separated by NULs
+/// with -z), and no colors, signatures, or decorations mixed in.
+const GIT_CONFIG_OVERRIDES: &[&str] = &[
+    "i18n.logOutputEncoding=UTF-8",
+    "core.quotePath=false",
+    "color.ui=false",
+    "log.showSignature=false",
+    "log.decorate=false",
+];
+
+/// Starts a Git command in a clean environment (see `GIT_ENV_OVERRIDES`
+/// and `GIT_CONFIG_OVERRIDES`), configured so that it can't stop to ask
+/// the user anything or page its output.
+///
+/// We only ever parse stdout, so chatty wrappers that write extra stuff
+/// to stderr are fine.
+fn git_command(git: &GitOptions) -> Command {
+    let mut cmd = Command::new(git.binary.as_deref().unwrap_or("git"));
+    for var in GIT_ENV_OVERRIDES {
+        cmd.env_remove(var);
+    }
+    for config in GIT_CONFIG_OVERRIDES {
+        cmd.arg("-c").arg(config);
     }
+    // (Messages in the C locale are what we expect, if we ever look.)
+    cmd.env("LC_ALL", "C")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_PAGER", "cat")
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .stdin(Stdio::null());
+    cmd
 }
 
-pub fn assert_at_repo_top() {
-    let output = Command::new("git")
-        .arg("rev-parse")
-        .arg("--show-toplevel")
+/// Runs the given Git command and returns what it printed,
+/// or an error saying it couldn't do `what`.
+/// (Git prints the details of what went wrong to stderr itself.)
+fn run_git(cmd: &mut Command, what: &str) -> Result<String, Error> {
+    let output = run_git_bytes(cmd, what)?;
+    String::from_utf8(output)
+        .map_err(|_| Error::GitOutput(format!("`git {}` printed invalid UTF-8", subcommand(cmd))))
+}
+
+/// Like `run_git()`, but returns what Git printed as it is,
+/// e.g. for paths that might not be UTF-8.
+fn run_git_bytes(cmd: &mut Command, what: &str) -> Result<Vec<u8>, Error> {
+    let output = cmd
         .stdout(Stdio::piped())
         .stderr(Stdio::inherit())
         .output()
-        .expect("Couldn't run `git rev-parse` to find top-level dir");
+        .map_err(|source| Error::GitSpawn {
+            command: subcommand(cmd),
+            source,
+        })?;
 
     if !output.status.success() {
-        eprintln!("Error: not in a Git directory");
-        exit(1);
+        return Err(Error::GitFailed {
+            what: what.to_owned(),
+        });
     }
 
-    let tld = String::from_utf8(output.stdout).expect("git rev-parse returned invalid UTF-8");
+    Ok(output.stdout)
+}
 
-    let trimmed_tld = tld.trim();
+/// Runs the given Git command, handing each line it prints to `on_line`
+/// as it does, or returns an error saying it couldn't do `what`.
+///
+/// Unlike `run_git()`, this doesn't hold onto the whole output,
+/// which for the log of a file with tens of thousands of commits
+/// (times however many threads are reading logs) adds up.
+/// If `on_line` fails, Git is stopped.
+fn stream_git<F>(cmd: &mut Command, what: &str, mut on_line: F) -> Result<(), Error>
+where
+    F: FnMut(&str) -> Result<(), Error>,
+{
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|source| Error::GitSpawn {
+            command: subcommand(cmd),
+            source,
+        })?;
 
-    let cwd = env::current_dir().expect("Couldn't get current directory");
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    let read = loop {
+        line.clear();
+        match stdout.read_line(&mut line) {
+            Ok(0) => break Ok(()),
+            Ok(_) => {
+                if let Err(e) = on_line(line.trim_end_matches(&['\r', '\n'][..])) {
+                    break Err(e);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                break Err(Error::GitOutput(format!(
+                    "`git {}` printed invalid UTF-8",
+                    subcommand(cmd)
+                )))
+            }
+            Err(e) => {
+                break Err(Error::io(format!("read `git {}` output", subcommand(cmd)))(
+                    e,
+                ))
+            }
+        }
+    };
+    if read.is_err() {
+        // (It'd otherwise block on a full pipe forever.)
+        let _ = child.kill();
+    }
 
-    if trimmed_tld != cwd.to_str().expect("Current directory is not valid UTF-8") {
-        eprintln!(
-            "{}\n{}",
-            "Error: not at the top of a Git directory",
-            "(This makes reasoning about paths much simpler.)"
-        );
-        exit(1);
+    let succeeded = child.wait().map(|s| s.success()).unwrap_or(false);
+    read?;
+    if !succeeded {
+        return Err(Error::GitFailed {
+            what: what.to_owned(),
+        });
     }
+    Ok(())
 }
 
-pub fn commit_ish_into_sha(commit_ish: &str) -> SHA1 {
-    let output = Command::new("git")
-        .arg("rev-parse")
-        .arg("--verify")
-        .arg(commit_ish)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .output()
-        .expect("Couldn't spawn `git rev-parse` to parse ignored commit");
+/// The Git subcommand (e.g. `log`) the given command runs,
+/// past the config `git_command(git)` gives it
+fn subcommand(cmd: &Command) -> String {
+    let mut args = cmd.get_args();
+    while let Some(arg) = args.next() {
+        if arg == "-c" {
+            args.next();
+        } else {
+            return arg.to_string_lossy().into_owned();
+        }
+    }
+    String::new()
+}
 
-    if !output.status.success() {
-        eprintln!("Error: git rev-parse failed to parse {:?}", commit_ish);
-        exit(1);
+/// Complains about a line of output from `git <command>` we didn't expect.
+fn unexpected(command: &str, line: &str) -> Error {
+    Error::GitOutput(format!("`git {}` printed {:?}", command, line))
+}
+
+fn parse_sha(sha: &str) -> Result<SHA1, Error> {
+    SHA1::parse(sha).map_err(|e| Error::GitOutput(format!("{:?}: {}", sha, e)))
+}
+
+/// Lists the files Git knows about, or would if they were added:
+/// tracked files plus untracked ones that aren't ignored (by .gitignore, etc.).
+pub fn list_files(git: &GitOptions) -> Result<Vec<String>, Error> {
+    let output = run_git_bytes(
+        git_command(git)
+            .arg("ls-files")
+            .arg("-z")
+            .arg("--cached")
+            .arg("--others")
+            .arg("--exclude-standard"),
+        "list files",
+    )?;
+
+    Ok(utf8_paths(output.split(|b| *b == 0)))
+}
+
+/// Lists the files changed between the merge base of the given ref
+/// and HEAD (i.e. `git diff <ref>...HEAD`), like the files touched by a PR.
+pub fn list_changed_files(git: &GitOptions, since: &str) -> Result<Vec<String>, Error> {
+    let output = run_git_bytes(
+        git_command(git)
+            .arg("diff")
+            .arg("--name-only")
+            .arg("-z")
+            .arg(format!("{}...HEAD", since))
+            .arg("--"),
+        &format!("find files changed since {:?}", since),
+    )?;
+
+    Ok(utf8_paths(output.split(|b| *b == 0)))
+}
+
+/// Lists the files with changes staged for the next commit
+/// (but not ones whose deletion is staged).
+pub fn list_staged_files(git: &GitOptions) -> Result<Vec<String>, Error> {
+    let output = run_git_bytes(
+        git_command(git)
+            .arg("diff")
+            .arg("--cached")
+            .arg("--name-only")
+            .arg("-z")
+            .arg("--diff-filter=d")
+            .arg("--"),
+        "list staged files",
+    )?;
+
+    Ok(utf8_paths(output.split(|b| *b == 0)))
+}
+
+/// Lists the tracked files with changes that haven't been staged.
+pub fn list_unstaged_files(git: &GitOptions) -> Result<HashSet<String>, Error> {
+    let output = run_git_bytes(
+        git_command(git)
+            .arg("diff")
+            .arg("--name-only")
+            .arg("-z")
+            .arg("--"),
+        "list unstaged changes",
+    )?;
+    Ok(utf8_paths(output.split(|b| *b == 0)).into_iter().collect())
+}
+
+/// Stages the given files (i.e. `git add`s them).
+pub fn stage_files(git: &GitOptions, paths: &[String]) -> Result<(), Error> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+    run_git(
+        git_command(git).arg("add").arg("--").args(paths),
+        "stage our changes",
+    )
+    .map(|_| ())
+}
+
+/// Finds the repo's Git directory (usually `.git`),
+/// where we can keep things like our cache.
+pub fn git_dir(git: &GitOptions) -> Result<PathBuf, Error> {
+    let output = run_git(
+        git_command(git).arg("rev-parse").arg("--git-dir"),
+        "find the Git directory",
+    )?;
+    Ok(PathBuf::from(output.trim_end_matches('\n')))
+}
+
+/// Finds where Git looks for the repo's hooks
+/// (`.git/hooks`, unless `core.hooksPath` says otherwise).
+pub fn hooks_dir(git: &GitOptions) -> Result<PathBuf, Error> {
+    let output = run_git(
+        git_command(git)
+            .arg("rev-parse")
+            .arg("--git-path")
+            .arg("hooks"),
+        "find the hooks directory",
+    )?;
+    Ok(PathBuf::from(output.trim_end_matches('\n')))
+}
+
+/// Lists the ID of each file's blob in HEAD's tree.
+pub fn list_head_blobs(git: &GitOptions) -> Result<HashMap<String, SHA1>, Error> {
+    let output = run_git_bytes(
+        git_command(git)
+            .arg("ls-tree")
+            .arg("-r")
+            .arg("-z")
+            .arg("--full-tree")
+            .arg("HEAD"),
+        "list the files in HEAD",
+    )?;
+
+    // Each entry is <mode> <type> <object>, a tab, then the path.
+    let mut blobs = HashMap::new();
+    for entry in output.split(|b| *b == 0).filter(|e| !e.is_empty()) {
+        let bad_entry = || unexpected("ls-tree", &String::from_utf8_lossy(entry));
+        let tab = entry
+            .iter()
+            .position(|b| *b == b'\t')
+            .ok_or_else(bad_entry)?;
+        let info = std::str::from_utf8(&entry[..tab]).map_err(|_| bad_entry())?;
+        let object = info.rsplit(' ').next().ok_or_else(bad_entry)?;
+        if let Some(path) = utf8_path_from_bytes(&entry[tab + 1..]) {
+            blobs.insert(path, parse_sha(object)?);
+        }
     }
+    Ok(blobs)
+}
 
-    let sha_slice = str::from_utf8(&output.stdout)
-        .expect("git rev-parse returned invalid UTF-8")
-        .trim();
+/// Lists the files changed by commits reachable from one of the given commits
+/// but not the other (i.e. `git log a...b`), in either direction.
+pub fn list_files_changed_between(
+    git: &GitOptions,
+    a: &SHA1,
+    b: &SHA1,
+) -> Result<HashSet<String>, Error> {
+    let output = run_git_bytes(
+        git_command(git)
+            .arg("log")
+            .arg("--name-only")
+            .arg("--no-renames")
+            .arg("-z")
+            .arg("--format=")
+            .arg(format!("{}...{}", a, b))
+            .arg("--"),
+        "find the files changed since the last run",
+    )?;
 
-    SHA1::parse(sha_slice).expect("git rev-parse didn't return a valid SHA1")
+    // (Each commit's list of files starts with a newline.)
+    let paths = output
+        .split(|b| *b == 0)
+        .map(|f| &f[f.iter().take_while(|b| **b == b'\n').count()..]);
+    Ok(utf8_paths(paths).into_iter().collect())
 }
 
-fn year_from_iso_8601(iso: &str) -> Year {
-    let dash_index = iso.find('-').expect("Didn't find dash in ISO-8601 output");
-    iso[..dash_index]
-        .parse()
-        .expect("Couldn't parse first commit year")
+/// Lists the files with changes that haven't been committed yet:
+/// modified (staged or not), added, or untracked (but not ignored).
+pub fn get_dirty_files(git: &GitOptions) -> Result<HashSet<String>, Error> {
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::get_dirty_files().map_err(Error::libgit2("find uncommitted changes"));
+        }
+    }
+
+    let output = run_git_bytes(
+        git_command(git)
+            .arg("status")
+            .arg("--porcelain")
+            .arg("-z")
+            .arg("--untracked-files=all"),
+        "find uncommitted changes",
+    )?;
+
+    // Each entry is a two-letter status, a space, then the path.
+    let mut entries = output.split(|b| *b == 0).filter(|e| !e.is_empty());
+    let mut dirty = HashSet::new();
+    while let Some(entry) = entries.next() {
+        if entry.len() < 4 {
+            return Err(unexpected("status", &String::from_utf8_lossy(entry)));
+        }
+        let (status, path) = entry.split_at(3);
+        // Renames and copies are followed by the path they came from.
+        if status.starts_with(b"R") || status.starts_with(b"C") {
+            entries.next();
+        }
+        dirty.extend(utf8_path_from_bytes(path));
+    }
+    Ok(dirty)
 }
 
-pub fn get_first_commit_year() -> Year {
-    let output = Command::new("git")
-        .arg("log")
-        .arg("--max-parents=0")
-        .arg("--format=%aI")
+/// Gets every value of the given config key, e.g. `copyrighter.organization`,
+/// or none if it isn't set.
+pub fn get_config_values(git: &GitOptions, key: &str) -> Result<Vec<String>, Error> {
+    let what = format!("read {} from the Git config", key);
+
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::get_config_values(key).map_err(Error::libgit2(what));
+        }
+    }
+
+    let mut cmd = git_command(git);
+    cmd.arg("config").arg("--get-all").arg("-z").arg(key);
+    let output = cmd
         .stdout(Stdio::piped())
         .stderr(Stdio::inherit())
         .output()
-        .expect("Couldn't spawn `git log` to get first commit timestamp");
+        .map_err(|source| Error::GitSpawn {
+            command: subcommand(&cmd),
+            source,
+        })?;
 
-    if !output.status.success() {
-        eprintln!("Error: Couldn't run Git to find the first commit date");
-        exit(1);
+    // git config exits with 1 if the key isn't set, which is fine.
+    match output.status.code() {
+        Some(0) => {}
+        Some(1) => return Ok(Vec::new()),
+        _ => return Err(Error::GitFailed { what }),
     }
 
-    // ISO-8601: The year is everything before the first dash.
-    let date_string = str::from_utf8(&output.stdout)
-        .expect("git log returned invalid UTF-8")
-        .trim()
-        .split('\n')
-        .last()
-        .unwrap();
-
-    // Find the dash
-    year_from_iso_8601(date_string)
+    let output = String::from_utf8(output.stdout)
+        .map_err(|_| Error::GitOutput("`git config` printed invalid UTF-8".to_owned()))?;
+    Ok(output
+        .split('\0')
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_owned())
+        .collect())
 }
 
-fn should_ignore_commit(sha: &str, commits: &HashSet<SHA1>) -> bool {
-    let sha = SHA1::parse(sha).expect("Git provided an invalid hash");
-    commits.contains(&sha)
+/// Finds which of the given files Git's attributes (e.g. `*.png binary`
+/// in `.gitattributes`) say are binary: ones with `binary` set, or `text` unset.
+pub fn get_binary_files(git: &GitOptions, paths: &PathSet) -> Result<HashSet<String>, Error> {
+    let paths: Vec<&String> = paths.iter().collect();
+    let mut binary = HashSet::new();
+
+    // Pass the paths in batches so we don't run into command line length limits.
+    for batch in paths.chunks(1000) {
+        let output = run_git(
+            git_command(git)
+                .arg("check-attr")
+                .arg("-z")
+                .arg("binary")
+                .arg("text")
+                .arg("--")
+                .args(batch),
+            "check which files are binary",
+        )?;
+
+        // Each attribute of each file is listed as <path> NUL <attribute> NUL <value> NUL,
+        // with paths as we gave them.
+        let tokens: Vec<&str> = output.split('\0').collect();
+        for entry in tokens.chunks_exact(3) {
+            if let ("binary", "set") | ("text", "unset") = (entry[1], entry[2]) {
+                binary.insert(entry[0].to_owned());
+            }
+        }
+    }
+
+    Ok(binary)
 }
 
-pub fn get_file_years(path: &str, ignoring_commits: &HashSet<SHA1>) -> Vec<Year> {
-    let output = Command::new("git")
-        .arg("log")
-        .arg("--follow")
-        .arg("-M")
-        .arg("-C")
-        .arg("--format=%H %ai")
-        .arg(path)
+/// Finds which of the given files Git ignores (with `.gitignore`,
+/// `.git/info/exclude`, etc.). Tracked files aren't ignored,
+/// whatever the ignore rules say.
+pub fn get_ignored_files(git: &GitOptions, paths: &PathSet) -> Result<HashSet<String>, Error> {
+    let mut cmd = git_command(git);
+    cmd.arg("check-ignore").arg("--stdin").arg("-z");
+    let mut child = cmd
+        .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::inherit())
-        .output()
-        .expect("Couldn't spawn `git log` to get commit timestamps");
+        .spawn()
+        .map_err(|source| Error::GitSpawn {
+            command: subcommand(&cmd),
+            source,
+        })?;
 
-    if !output.status.success() {
-        eprintln!("Error: Couldn't run Git to find commit timestamps");
-        exit(1);
+    // Feed Git the paths from another thread, so that neither of us
+    // blocks on a full pipe while the other waits.
+    let mut stdin = child.stdin.take().unwrap();
+    let input: Vec<u8> = paths
+        .iter()
+        .flat_map(|p| p.bytes().chain(Some(0)))
+        .collect();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output().map_err(|source| Error::GitSpawn {
+        command: subcommand(&cmd),
+        source,
+    })?;
+    // (If Git stopped reading early, it failed, and we'll say so below.)
+    let _ = writer.join().unwrap();
+
+    // git check-ignore exits with 1 if none of them are ignored, which is fine.
+    match output.status.code() {
+        Some(0) | Some(1) => {}
+        _ => {
+            return Err(Error::GitFailed {
+                what: "check which files are ignored".to_owned(),
+            })
+        }
     }
 
-    let lines = str::from_utf8(&output.stdout)
-        .expect("git log returned invalid UTF-8")
-        .trim()
-        .split('\n');
+    // Ignored files are listed as we gave them.
+    let output = String::from_utf8(output.stdout)
+        .map_err(|_| Error::GitOutput("`git check-ignore` printed invalid UTF-8".to_owned()))?;
+    Ok(output
+        .split('\0')
+        .filter(|p| !p.is_empty())
+        .map(|p| p.to_owned())
+        .collect())
+}
 
-    let mut ret = Vec::<Year>::new();
+/// Returns an error unless we're at the top of the repo.
+/// (Everything else assumes paths are relative to it.)
+pub fn assert_at_repo_top(git: &GitOptions) -> Result<(), Error> {
+    let cwd = env::current_dir().map_err(Error::io("get the current directory"))?;
 
-    for line in lines {
-        let mut space_split = line.split(' ');
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            let workdir = libgit2::workdir().map_err(Error::libgit2("open the Git repository"))?;
+            return match workdir {
+                Some(w) if same_dir(&w, &cwd) => Ok(()),
+                // (Bare repos don't have a top.)
+                _ => Err(Error::NotAtRepoTop),
+            };
+        }
+    }
 
-        let sha = space_split.next().expect("Unexpected `git log` output");
-        let date = space_split.next().expect("Unexpected `git log` output");
+    let tld = run_git(
+        git_command(git).arg("rev-parse").arg("--show-toplevel"),
+        "find the top of the repo",
+    )
+    .map_err(|e| match e {
+        Error::GitFailed { .. } => Error::NotInRepo,
+        e => e,
+    })?;
 
-        if should_ignore_commit(sha, ignoring_commits) {
-            continue;
+    if same_dir(&PathBuf::from(tld.trim()), &cwd) {
+        Ok(())
+    } else {
+        Err(Error::NotAtRepoTop)
+    }
+}
+
+/// Are the two paths the same directory?
+///
+/// Git (even on Windows) gives us paths like `C:/src/repo`,
+/// while the current directory is `C:\src\repo`, maybe in a different case.
+/// Resolving both takes care of that, along with symlinks.
+/// If we can't, compare them as best we can.
+pub(crate) fn same_dir(a: &Path, b: &Path) -> bool {
+    if let (Ok(a), Ok(b)) = (a.canonicalize(), b.canonicalize()) {
+        if a == b {
+            return true;
         }
+    }
 
-        ret.push(year_from_iso_8601(date));
+    let normalize = |p: &Path| {
+        let p = p.to_string_lossy().replace('\\', "/");
+        let p = p.trim_end_matches('/').to_owned();
+        // Windows paths are case-insensitive.
+        if cfg!(windows) {
+            p.to_lowercase()
+        } else {
+            p
+        }
+    };
+    normalize(a) == normalize(b)
+}
+
+pub fn commit_ish_into_sha(git: &GitOptions, commit_ish: &str) -> Result<SHA1, Error> {
+    let what = format!("parse {:?}", commit_ish);
+
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::commit_ish_into_sha(commit_ish).map_err(Error::libgit2(what));
+        }
     }
 
+    let output = run_git(
+        git_command(git)
+            .arg("rev-parse")
+            .arg("--verify")
+            .arg(commit_ish),
+        &what,
+    )?;
+
+    parse_sha(output.trim())
+}
+
+fn year_from_iso_8601(iso: &str) -> Result<Year, Error> {
+    // The year is everything before the first dash.
+    let year = iso
+        .split('-')
+        .next()
+        .and_then(|y| y.parse().ok())
+        .ok_or_else(|| Error::GitOutput(format!("{:?} isn't an ISO 8601 date", iso)))?;
+    plausible_year(year)
+}
+
+fn plausible_year(year: i64) -> Result<Year, Error> {
+    u16::try_from(year)
+        .ok()
+        .and_then(Year::new)
+        .ok_or(Error::ImplausibleYear(year))
+}
+
+/// Finds the commits (reachable from HEAD) whose author, written as
+/// `Name <email>`, matches the given pattern, e.g. for `--ignore-authors`.
+pub fn get_commits_by_authors(git: &GitOptions, pattern: &Regex) -> Result<HashSet<SHA1>, Error> {
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::get_commits_by_authors(pattern)
+                .map_err(Error::libgit2("find commit authors"));
+        }
+    }
+
+    // (%aN and %aE go by .mailmap, so that authors who changed their name
+    // or address are still caught by patterns for either.)
+    let mut commits = HashSet::new();
+    stream_git(
+        git_command(git).arg("log").arg("--format=%H %aN <%aE>"),
+        "find commit authors",
+        |line| {
+            if let Some((sha, author)) = line.split_once(' ') {
+                if pattern.is_match(author) {
+                    commits.insert(parse_sha(sha)?);
+                }
+            }
+            Ok(())
+        },
+    )?;
+    Ok(commits)
+}
+
+/// Finds the commits (reachable from HEAD) whose subject, i.e. the first line
+/// of their message, matches the given pattern, e.g. for `--ignore-message`.
+pub fn get_commits_by_message(git: &GitOptions, pattern: &Regex) -> Result<HashSet<SHA1>, Error> {
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::get_commits_by_message(pattern)
+                .map_err(Error::libgit2("find commit messages"));
+        }
+    }
+
+    let mut commits = HashSet::new();
+    stream_git(
+        git_command(git).arg("log").arg("--format=%H %s"),
+        "find commit messages",
+        |line| {
+            let (sha, subject) = line.split_once(' ').unwrap_or((line, ""));
+            if pattern.is_match(subject) {
+                commits.insert(parse_sha(sha)?);
+            }
+            Ok(())
+        },
+    )?;
+    Ok(commits)
+}
+
+/// Finds the merge commits reachable from HEAD, e.g. for `--no-merges`.
+pub fn get_merge_commits(git: &GitOptions) -> Result<HashSet<SHA1>, Error> {
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::get_merge_commits().map_err(Error::libgit2("find merge commits"));
+        }
+    }
+
+    let mut commits = HashSet::new();
+    stream_git(
+        git_command(git)
+            .arg("log")
+            .arg("--merges")
+            .arg("--format=%H"),
+        "find merge commits",
+        |line| {
+            commits.insert(parse_sha(line)?);
+            Ok(())
+        },
+    )?;
+    Ok(commits)
+}
+
+pub fn get_first_commit_year(git: &GitOptions) -> Result<Year, Error> {
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::get_first_commit_year(git)
+                .map_err(Error::libgit2("find the first commit date"));
+        }
+    }
+
+    let output = run_git(
+        git_command(git)
+            .arg("log")
+            .arg("--max-parents=0")
+            .arg(format!("--format={}", date_placeholder(git))),
+        "find the first commit date",
+    )?;
+
+    // The last root commit listed is the oldest.
+    let date_string = output.trim().split('\n').next_back().unwrap();
+
+    year_from_iso_8601(date_string)
+}
+
+fn should_ignore_commit(sha: &str, commits: &HashSet<SHA1>) -> Result<bool, Error> {
+    Ok(commits.contains(&parse_sha(sha)?))
+}
+
+pub fn get_file_years(
+    git: &GitOptions,
+    path: &str,
+    ignoring_commits: &HashSet<SHA1>,
+) -> Result<Vec<Year>, Error> {
+    let what = format!("find commit timestamps for {}", path);
+
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::get_file_years(git, path, ignoring_commits)
+                .map_err(Error::libgit2(what));
+        }
+    }
+
+    let mut ret: Vec<Year> = log_file(git, path, ignoring_commits, false, &what)?
+        .into_iter()
+        .map(|(year, _)| year)
+        .collect();
+
     // Do some cleanup.
     // (We'll do more later when these are combined with what the file comments
     // claimed, but no reason to hold onto a bunch of duplicates in the meantime.
     ret.sort();
     ret.dedup();
 
-    ret
+    Ok(ret)
+}
+
+/// Like `get_file_years()`, but gives who wrote each commit
+/// (as `Name <email>`, going by any `.mailmap`) along with its year,
+/// oldest first. This always runs `git`, whichever backend was chosen.
+pub fn get_file_author_years(
+    git: &GitOptions,
+    path: &str,
+    ignoring_commits: &HashSet<SHA1>,
+) -> Result<Vec<(String, Year)>, Error> {
+    let what = format!("find commit authors for {}", path);
+    let mut ret = log_file(git, path, ignoring_commits, true, &what)?
+        .into_iter()
+        .map(|(year, author)| (author, year))
+        .collect::<Vec<_>>();
+    ret.reverse();
+    Ok(ret)
+}
+
+/// Runs `git log --follow` on the given file, and returns the year
+/// (and author, if asked for) of each commit that changed it, newest first.
+fn log_file(
+    git: &GitOptions,
+    path: &str,
+    ignoring_commits: &HashSet<SHA1>,
+    authors: bool,
+    what: &str,
+) -> Result<Vec<(Year, String)>, Error> {
+    // As in get_all_file_years(), only --numstat leaves out
+    // whitespace-only changes.
+    let numstat = git.ignore_whitespace;
+
+    let mut cmd = git_command(git);
+    cmd.arg("log").arg("--follow").arg("-M").arg("-C");
+    if numstat {
+        cmd.arg("-w").arg("--numstat");
+    }
+    // (Authors are set off by \x01, since names and dates have spaces in them.)
+    let author = if authors { "%x01%aN <%aE>" } else { "" };
+    cmd.arg(format!("--format=%H {}{}", date_placeholder(git), author))
+        .arg(path);
+
+    let mut ret = Vec::new();
+
+    // With --numstat, the date (and author) of the commit we're on,
+    // until we see that it changed more than whitespace
+    let mut pending: Option<String> = None;
+
+    let entry = |commit: &str| -> Result<(Year, String), Error> {
+        let (date, author) = commit.split_once('\x01').unwrap_or((commit, ""));
+        Ok((year_from_iso_8601(date)?, author.to_owned()))
+    };
+
+    stream_git(&mut cmd, what, |line| {
+        if line.is_empty() {
+            return Ok(());
+        }
+        // (Dates don't have tabs in them, but --numstat lines do.)
+        if line.contains('\t') {
+            if let Some(commit) = pending.take() {
+                ret.push(entry(&commit)?);
+            }
+            return Ok(());
+        }
+
+        let (sha, commit) = line
+            .split_once(' ')
+            .ok_or_else(|| unexpected("log", line))?;
+
+        if should_ignore_commit(sha, ignoring_commits)? {
+            pending = None;
+        } else if numstat {
+            pending = Some(commit.to_owned());
+        } else {
+            ret.push(entry(commit)?);
+        }
+        Ok(())
+    })?;
+
+    Ok(ret)
+}
+
+/// Like `get_file_years()`, but for all the given files at once,
+/// with a single walk through the whole history.
+///
+/// Running `git log --follow` once per file takes ages on big repos.
+/// Instead, we ask Git for the files each commit changed (newest first)
+/// and follow renames and copies ourselves.
+/// `on_commit` is called as we go through each commit.
+pub fn get_all_file_years<F: FnMut()>(
+    git: &GitOptions,
+    paths: &PathSet,
+    ignoring_commits: &HashSet<SHA1>,
+    mut on_commit: F,
+) -> Result<YearMap, Error> {
+    #[cfg(feature = "libgit2")]
+    {
+        if git.backend == Backend::Libgit2 {
+            return libgit2::get_all_file_years(git, paths, ignoring_commits, on_commit)
+                .map_err(Error::libgit2("find commit timestamps"));
+        }
+    }
+
+    // --name-status lists files whose changes were all whitespace,
+    // even with -w, but --numstat doesn't. (It's slower though, since Git
+    // has to diff each file to count lines, so we only use it if we must.)
+    let numstat = git.ignore_whitespace;
+
+    let mut cmd = git_command(git);
+    cmd.arg("log").arg("-z").arg("-M").arg("-C");
+    if numstat {
+        cmd.arg("-w").arg("--numstat");
+    } else {
+        cmd.arg("--name-status");
+    }
+    cmd.arg(format!("--format=%x01%H {}", date_placeholder(git)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+    let mut child = cmd.spawn().map_err(|source| Error::GitSpawn {
+        command: subcommand(&cmd),
+        source,
+    })?;
+
+    let mut years: YearMap = paths.iter().map(|p| (p.clone(), Vec::new())).collect();
+
+    // What each file we're looking for was called as of the commit we're on.
+    // (Git gives paths relative to the top of the repo, without a leading ./)
+    let mut names: HashMap<String, Vec<String>> = paths
+        .iter()
+        .map(|p| (p.trim_start_matches("./").to_owned(), vec![p.clone()]))
+        .collect();
+
+    // With -z, everything Git prints is separated by NULs:
+    // each commit's header (which we start with \x01 to tell it apart),
+    // then a status and the path(s) it applies to for each file it changed.
+    // (Paths that aren't UTF-8 can't be any of the ones we're looking for,
+    // so they can be mangled.)
+    let mut tokens = BufReader::new(child.stdout.take().unwrap())
+        .split(b'\0')
+        .map(|t| {
+            let t = t.map_err(Error::io("read `git log` output"))?;
+            Ok::<_, Error>(String::from_utf8_lossy(&t).into_owned())
+        });
+
+    // The year of the commit we're on, or None if we're ignoring it
+    let mut year: Option<Year> = None;
+
+    while let Some(token) = tokens.next() {
+        let token = token?;
+
+        // (With --numstat, a commit whose changes were all whitespace
+        // lists no files, leaving just the newline before the next header.)
+        if let Some(header) = token.trim_start_matches('\n').strip_prefix('\x01') {
+            let (sha, date) = header
+                .split_once(' ')
+                .ok_or_else(|| unexpected("log", header))?;
+
+            year = if should_ignore_commit(sha, ignoring_commits)? {
+                None
+            } else {
+                Some(year_from_iso_8601(date)?)
+            };
+            on_commit();
+            continue;
+        }
+
+        // A commit's list of files starts with a newline.
+        let status = token.trim_start_matches('\n');
+        if status.is_empty() {
+            continue;
+        }
+
+        let mut next_path = || {
+            tokens
+                .next()
+                .unwrap_or_else(|| Err(unexpected("log", status)))
+        };
+
+        // Renames and copies list the old path, then the new one.
+        // (--numstat lists <added> <deleted> <path>, with an empty path
+        // for renames and copies, without saying which it is.
+        // Either way, the new path didn't exist before this commit.)
+        let (old, new, renamed) = if numstat {
+            let path = status
+                .splitn(3, '\t')
+                .nth(2)
+                .ok_or_else(|| unexpected("log", status))?;
+            if path.is_empty() {
+                let old = next_path()?;
+                (Some(old), next_path()?, true)
+            } else {
+                (None, path.to_owned(), false)
+            }
+        } else if status.starts_with('R') || status.starts_with('C') {
+            let old = next_path()?;
+            (Some(old), next_path()?, status.starts_with('R'))
+        } else {
+            (None, next_path()?, false)
+        };
+
+        let followers = match names.get(&new) {
+            Some(f) => f.clone(),
+            None => continue,
+        };
+
+        if let Some(y) = year {
+            for path in &followers {
+                years.get_mut(path).unwrap().push(y);
+            }
+        }
+
+        // Before this commit, the file went by its old name.
+        if let Some(old) = old {
+            if renamed {
+                names.remove(&new);
+            }
+            names.entry(old).or_default().extend(followers);
+        }
+    }
+
+    if !child.wait().map(|s| s.success()).unwrap_or(false) {
+        return Err(Error::GitFailed {
+            what: "find commit timestamps".to_owned(),
+        });
+    }
+
+    for v in years.values_mut() {
+        v.sort();
+        v.dedup();
+    }
+
+    Ok(years)
+}
+
+/// Converts a Unix timestamp and a Git timezone offset (e.g. `-0500`)
+/// into the year it was local to the author.
+fn year_from_unix_time(timestamp: &str, tz: &str) -> Result<Year, Error> {
+    let bad_time = || Error::GitOutput(format!("{:?} {:?} isn't a timestamp", timestamp, tz));
+
+    let seconds: i64 = timestamp.parse().map_err(|_| bad_time())?;
+
+    if tz.len() < 2 {
+        return Err(bad_time());
+    }
+    let (sign, hhmm) = tz.split_at(1);
+    let hhmm: i64 = hhmm.parse().map_err(|_| bad_time())?;
+    let offset = (hhmm / 100 * 60 + hhmm % 100) * 60;
+    let offset = if sign == "-" { -offset } else { offset };
+
+    year_from_local_time(seconds, offset)
+}
+
+/// Converts a Unix timestamp and a timezone offset (in seconds)
+/// into the year it was local to the author.
+pub(crate) fn year_from_local_time(seconds: i64, offset: i64) -> Result<Year, Error> {
+    let year = i64::from(time::at_utc(time::Timespec::new(seconds + offset, 0)).tm_year) + 1900;
+    plausible_year(year)
+}
+
+/// Like `get_file_years()`, but only considers commits that wrote lines
+/// still present in the file, according to `git blame`.
+///
+/// This gives a tighter set of years than the whole history from `git log`:
+/// a year whose changes have since been entirely deleted or rewritten
+/// doesn't count.
+pub fn get_file_blame_years(
+    git: &GitOptions,
+    path: &str,
+    ignoring_commits: &HashSet<SHA1>,
+) -> Result<Vec<Year>, Error> {
+    let mut cmd = git_command(git);
+    cmd.arg("blame").arg("--line-porcelain").arg("-M").arg("-C");
+    if git.ignore_whitespace {
+        cmd.arg("-w");
+    }
+    cmd.arg("--").arg(path);
+
+    let mut ret = Vec::<Year>::new();
+
+    // Each line of the file gets a block of headers, starting with
+    // <sha> <original line> <final line> [<group size>]
+    // and ending with the line's contents (prefixed by a tab).
+    let mut ignoring_line = false;
+    let (time_key, tz_key) = match git.date_source {
+        DateSource::Author => ("author-time", "author-tz"),
+        DateSource::Committer => ("committer-time", "committer-tz"),
+    };
+    let mut time: Option<String> = None;
+
+    // (Blaming every line of a big file prints a lot, so don't hold onto it.)
+    let what = format!("find line timestamps for {}", path);
+    stream_git(&mut cmd, &what, |line| {
+        let mut space_split = line.splitn(2, ' ');
+        let key = space_split.next().unwrap();
+        let value = space_split.next().unwrap_or("");
+
+        if key.len() == 40 && key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            // Lines not yet committed are blamed on 000...000.
+            ignoring_line =
+                key.bytes().all(|b| b == b'0') || should_ignore_commit(key, ignoring_commits)?;
+        } else if key == time_key {
+            time = Some(value.to_owned());
+        } else if key == tz_key && !ignoring_line {
+            let timestamp = time.as_deref().ok_or_else(|| unexpected("blame", line))?;
+            ret.push(year_from_unix_time(timestamp, value)?);
+        }
+        Ok(())
+    })?;
+
+    // Do some cleanup, as get_file_years() does.
+    ret.sort();
+    ret.dedup();
+
+    Ok(ret)
 }
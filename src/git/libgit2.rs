@@ -0,0 +1,349 @@
+//! The Git operations from `git`, built on libgit2 instead of running
+//! the `git` executable, which is slow to spawn over and over on some
+//! platforms, and might not even be installed (e.g., in containers).
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use git2::{Commit, Delta, DiffFindOptions, DiffOptions, Oid, Repository, Sort, StatusOptions};
+use regex::Regex;
+
+use super::{year_from_local_time, DateSource, GitOptions, SHA1};
+use crate::common::{PathSet, Year, YearMap};
+
+fn open_repo() -> Result<Repository, git2::Error> {
+    Repository::open_from_env()
+}
+
+fn sha_of(oid: Oid) -> SHA1 {
+    let mut bytes = [0; 20];
+    bytes.copy_from_slice(oid.as_bytes());
+    SHA1 { bytes }
+}
+
+/// The year of the given commit, going by the given date of it
+fn commit_year(commit: &Commit, source: DateSource) -> Result<Year, git2::Error> {
+    let when = match source {
+        DateSource::Author => commit.author().when(),
+        DateSource::Committer => commit.committer().when(),
+    };
+    year_from_local_time(when.seconds(), i64::from(when.offset_minutes()) * 60)
+        .map_err(|e| git2::Error::from_str(&e.to_string()))
+}
+
+/// Finds the top of the repo, if it has one. (Bare repos don't.)
+pub fn workdir() -> Result<Option<PathBuf>, git2::Error> {
+    Ok(open_repo()?.workdir().map(|w| w.to_owned()))
+}
+
+pub fn get_dirty_files() -> Result<HashSet<String>, git2::Error> {
+    let repo = open_repo()?;
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+
+    let statuses = repo.statuses(Some(&mut options))?;
+    let dirty = statuses
+        .iter()
+        .filter(|s| !s.status().is_ignored())
+        .filter_map(|s| s.path().map(|p| p.to_owned()))
+        .collect();
+    Ok(dirty)
+}
+
+/// Gets every value of the given config key (from the repo's config,
+/// then the user's, etc.), or none if it isn't set.
+pub fn get_config_values(key: &str) -> Result<Vec<String>, git2::Error> {
+    let config = open_repo()?.config()?;
+    let mut entries = match config.multivar(key, None) {
+        Ok(e) => e,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut values = Vec::new();
+    while let Some(entry) = entries.next() {
+        if let Some(value) = entry?.value() {
+            values.push(value.to_owned());
+        }
+    }
+    Ok(values)
+}
+
+pub fn commit_ish_into_sha(commit_ish: &str) -> Result<SHA1, git2::Error> {
+    let repo = open_repo()?;
+    let commit = repo.revparse_single(commit_ish)?.peel_to_commit()?;
+    Ok(sha_of(commit.id()))
+}
+
+pub fn get_commits_by_authors(pattern: &Regex) -> Result<HashSet<SHA1>, git2::Error> {
+    let repo = open_repo()?;
+    // Go by .mailmap, like `git log --format=%aN <%aE>` does.
+    let mailmap = repo.mailmap()?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+
+    let mut commits = HashSet::new();
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let author = commit.author_with_mailmap(&mailmap)?;
+        let author = format!(
+            "{} <{}>",
+            String::from_utf8_lossy(author.name_bytes()),
+            String::from_utf8_lossy(author.email_bytes())
+        );
+        if pattern.is_match(&author) {
+            commits.insert(sha_of(commit.id()));
+        }
+    }
+    Ok(commits)
+}
+
+pub fn get_commits_by_message(pattern: &Regex) -> Result<HashSet<SHA1>, git2::Error> {
+    let repo = open_repo()?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+
+    let mut commits = HashSet::new();
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let subject = String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default());
+        if pattern.is_match(&subject) {
+            commits.insert(sha_of(commit.id()));
+        }
+    }
+    Ok(commits)
+}
+
+pub fn get_merge_commits() -> Result<HashSet<SHA1>, git2::Error> {
+    let repo = open_repo()?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+
+    let mut merges = HashSet::new();
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() > 1 {
+            merges.insert(sha_of(commit.id()));
+        }
+    }
+    Ok(merges)
+}
+
+/// Finds the year of the oldest root commit reachable from HEAD.
+pub fn get_first_commit_year(git: &GitOptions) -> Result<Year, git2::Error> {
+    let repo = open_repo()?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+    let mut year = None;
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() == 0 {
+            year = Some(commit_year(&commit, git.date_source)?);
+        }
+    }
+
+    year.ok_or_else(|| git2::Error::from_str("no commits found"))
+}
+
+/// Like `git log --follow -M -C <path>`: finds the years of commits
+/// that changed the given file, following it back through renames and copies.
+pub fn get_file_years(
+    git: &GitOptions,
+    path: &str,
+    ignoring_commits: &HashSet<SHA1>,
+) -> Result<Vec<Year>, git2::Error> {
+    let repo = open_repo()?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+    // What the file is called as of the commit we're on
+    let mut path = PathBuf::from(path.trim_start_matches("./"));
+    let mut years = Vec::new();
+
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+
+        let blob = match blob_at(&commit, &path)? {
+            Some(b) => b,
+            // The file doesn't exist here, at least not by this name.
+            None => continue,
+        };
+
+        // Like Git, a commit only counts if it changed the file relative to
+        // every parent. (Merges that just brought in a side's version don't.)
+        let parents: Vec<Commit> = commit.parents().collect();
+        let mut parent_blobs = Vec::with_capacity(parents.len());
+        for parent in &parents {
+            parent_blobs.push(blob_at(parent, &path)?);
+        }
+        if parent_blobs.contains(&Some(blob)) {
+            continue;
+        }
+
+        // Like `git log -w`, changes that only touch whitespace can be left out.
+        let whitespace_only = match parent_blobs.as_slice() {
+            [Some(old)] if git.ignore_whitespace => !differs_beyond_whitespace(&repo, *old, blob)?,
+            _ => false,
+        };
+
+        if !whitespace_only && !ignoring_commits.contains(&sha_of(commit.id())) {
+            years.push(commit_year(&commit, git.date_source)?);
+        }
+
+        // If the file is new here, it might have come from somewhere else.
+        if parents.len() == 1 && parent_blobs[0].is_none() {
+            if let Some(old) = rename_source(&repo, &parents[0], &commit, &path)? {
+                path = old;
+            }
+        }
+    }
+
+    years.sort();
+    years.dedup();
+    Ok(years)
+}
+
+/// Like `get_file_years()`, but for all the given files at once,
+/// with a single walk through the history (see `git::get_all_file_years()`).
+///
+/// For each commit, we diff its tree against its parent's and follow renames
+/// and copies ourselves, the same way the `git log` version does.
+/// (Merges are skipped, as `git log` doesn't list what they changed.)
+pub fn get_all_file_years<F: FnMut()>(
+    git: &GitOptions,
+    paths: &PathSet,
+    ignoring_commits: &HashSet<SHA1>,
+    mut on_commit: F,
+) -> Result<YearMap, git2::Error> {
+    let repo = open_repo()?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+    let mut years: YearMap = paths.iter().map(|p| (p.clone(), Vec::new())).collect();
+
+    // What each file we're looking for was called as of the commit we're on
+    let mut names: HashMap<String, Vec<String>> = paths
+        .iter()
+        .map(|p| (p.trim_start_matches("./").to_owned(), vec![p.clone()]))
+        .collect();
+
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        on_commit();
+
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+
+        let year = if ignoring_commits.contains(&sha_of(commit.id())) {
+            None
+        } else {
+            Some(commit_year(&commit, git.date_source)?)
+        };
+
+        let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        diff.find_similar(Some(DiffFindOptions::new().renames(true).copies(true)))?;
+
+        for delta in diff.deltas() {
+            // (Paths that aren't UTF-8 can't be any of the ones we're looking for.)
+            let new = match delta.new_file().path().and_then(|p| p.to_str()) {
+                Some(p) => p,
+                None => continue,
+            };
+            let followers = match names.get(new) {
+                Some(f) => f.clone(),
+                None => continue,
+            };
+
+            // Like `git log -w`, changes that only touch whitespace can be left out.
+            let whitespace_only = delta.status() == Delta::Modified
+                && git.ignore_whitespace
+                && !differs_beyond_whitespace(&repo, delta.old_file().id(), delta.new_file().id())?;
+
+            if let (Some(y), false) = (year, whitespace_only) {
+                for path in &followers {
+                    years.get_mut(path).unwrap().push(y);
+                }
+            }
+
+            // Before this commit, the file went by its old name.
+            let renamed = delta.status() == Delta::Renamed;
+            if renamed || delta.status() == Delta::Copied {
+                let old = match delta.old_file().path().and_then(|p| p.to_str()) {
+                    Some(p) => p.to_owned(),
+                    None => continue,
+                };
+                if renamed {
+                    names.remove(new);
+                }
+                names.entry(old).or_default().extend(followers);
+            }
+        }
+    }
+
+    for v in years.values_mut() {
+        v.sort();
+        v.dedup();
+    }
+
+    Ok(years)
+}
+
+/// Gets the ID of the blob at the given path in the given commit, if any.
+fn blob_at(commit: &Commit, path: &Path) -> Result<Option<Oid>, git2::Error> {
+    match commit.tree()?.get_path(path) {
+        Ok(entry) => Ok(Some(entry.id())),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Do the given blobs differ in more than whitespace?
+fn differs_beyond_whitespace(repo: &Repository, old: Oid, new: Oid) -> Result<bool, git2::Error> {
+    let mut options = DiffOptions::new();
+    options.ignore_whitespace(true);
+
+    let mut differs = false;
+    repo.diff_blobs(
+        Some(&repo.find_blob(old)?),
+        None,
+        Some(&repo.find_blob(new)?),
+        None,
+        Some(&mut options),
+        None,
+        None,
+        Some(&mut |_, _| {
+            differs = true;
+            true
+        }),
+        None,
+    )?;
+    Ok(differs)
+}
+
+/// If `path` was renamed or copied from another file between `parent` and
+/// `commit`, returns that file's path.
+fn rename_source(
+    repo: &Repository,
+    parent: &Commit,
+    commit: &Commit,
+    path: &Path,
+) -> Result<Option<PathBuf>, git2::Error> {
+    let mut diff = repo.diff_tree_to_tree(Some(&parent.tree()?), Some(&commit.tree()?), None)?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true).copies(true)))?;
+
+    let source = diff
+        .deltas()
+        .filter(|d| d.status() == Delta::Renamed || d.status() == Delta::Copied)
+        .find(|d| d.new_file().path() == Some(path))
+        .and_then(|d| d.old_file().path().map(|p| p.to_owned()));
+    Ok(source)
+}
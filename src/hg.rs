@@ -0,0 +1,671 @@
+//! The Mercurial counterpart of `git`: finds the years files were changed
+//! (and the rest of what `vcs::Vcs` needs) by running `hg`.
+//!
+//! Mercurial changeset IDs are 40-character SHA-1 hashes too,
+//! so they're kept as `SHA1`s, just like Git's commits.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use rayon::prelude::*;
+use regex::Regex;
+
+use crate::common::{utf8_paths, AuthorMap, PathSet, Year, YearMap};
+use crate::error::Error;
+use crate::git::{same_dir, year_from_local_time, SHA1};
+use crate::history::Method;
+use crate::progress;
+use crate::vcs::{Kind, Vcs};
+
+/// Talks to Mercurial by running `hg`
+pub struct Mercurial;
+
+/// Starts an `hg` command, configured so that user settings (aliases,
+/// localized messages, pagers, etc.) don't change what it prints.
+fn hg_command() -> Command {
+    let mut cmd = Command::new("hg");
+    cmd.env("HGPLAIN", "1")
+        .env("HGENCODING", "utf-8")
+        .stdin(Stdio::null());
+    cmd
+}
+
+/// Runs the given `hg` command and returns what it printed,
+/// or an error saying it couldn't do `what`.
+/// (Mercurial prints the details of what went wrong to stderr itself.)
+fn run_hg(cmd: &mut Command, what: &str) -> Result<String, Error> {
+    let output = run_hg_bytes(cmd, what)?;
+    String::from_utf8(output)
+        .map_err(|_| Error::HgOutput(format!("`hg {}` printed invalid UTF-8", subcommand(cmd))))
+}
+
+/// Like `run_hg()`, but returns what Mercurial printed as it is,
+/// e.g. for paths that might not be UTF-8.
+fn run_hg_bytes(cmd: &mut Command, what: &str) -> Result<Vec<u8>, Error> {
+    let output = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|source| Error::HgSpawn {
+            command: subcommand(cmd),
+            source,
+        })?;
+
+    if !output.status.success() {
+        return Err(Error::HgFailed {
+            what: what.to_owned(),
+        });
+    }
+
+    Ok(output.stdout)
+}
+
+/// The Mercurial subcommand (e.g. `log`) the given command runs
+fn subcommand(cmd: &Command) -> String {
+    cmd.get_args()
+        .next()
+        .map_or_else(String::new, |a| a.to_string_lossy().into_owned())
+}
+
+/// Complains about a line of output from `hg <command>` we didn't expect.
+fn unexpected(command: &str, line: &str) -> Error {
+    Error::HgOutput(format!("`hg {}` printed {:?}", command, line))
+}
+
+fn parse_node(node: &str) -> Result<SHA1, Error> {
+    SHA1::parse(node).map_err(|e| Error::HgOutput(format!("{:?}: {}", node, e)))
+}
+
+/// Parses a date formatted with `{date|hgdate}`: a Unix timestamp,
+/// then the timezone's offset in seconds *west* of UTC.
+fn year_from_hgdate(date: &str) -> Result<Year, Error> {
+    let bad_date = || Error::HgOutput(format!("{:?} isn't a date", date));
+
+    let (seconds, offset) = date.split_once(' ').ok_or_else(bad_date)?;
+    let seconds: i64 = seconds.parse().map_err(|_| bad_date())?;
+    let offset: i64 = offset.parse().map_err(|_| bad_date())?;
+
+    year_from_local_time(seconds, -offset)
+}
+
+/// Splits the output of `hg status --print0` into paths.
+fn split_nul(output: &str) -> impl Iterator<Item = String> + '_ {
+    output
+        .split('\0')
+        .filter(|f| !f.is_empty())
+        .map(|f| f.to_owned())
+}
+
+impl Vcs for Mercurial {
+    fn kind(&self) -> Kind {
+        Kind::Mercurial
+    }
+
+    fn assert_at_repo_top(&self) -> Result<(), Error> {
+        let cwd = env::current_dir().map_err(Error::io("get the current directory"))?;
+
+        let root =
+            run_hg(hg_command().arg("root"), "find the top of the repo").map_err(|e| match e {
+                Error::HgFailed { .. } => Error::NotInRepo,
+                e => e,
+            })?;
+
+        if same_dir(&PathBuf::from(root.trim()), &cwd) {
+            Ok(())
+        } else {
+            Err(Error::NotAtRepoTop)
+        }
+    }
+
+    fn first_commit_year(&self) -> Result<Year, Error> {
+        let output = run_hg(
+            hg_command()
+                .arg("log")
+                .arg("--rev")
+                .arg("roots(::.)")
+                .arg("--template")
+                .arg("{date|hgdate}\\n"),
+            "find the first commit date",
+        )?;
+
+        let mut years = Vec::new();
+        for line in output.lines() {
+            years.push(year_from_hgdate(line)?);
+        }
+        years
+            .into_iter()
+            .min()
+            .ok_or_else(|| Error::HgOutput("`hg log` found no commits".to_owned()))
+    }
+
+    fn file_years(
+        &self,
+        paths: PathSet,
+        ignoring_commits: &HashSet<SHA1>,
+        method: Method,
+        show_progress: bool,
+    ) -> Result<YearMap, Error> {
+        match method {
+            Method::Batched => {
+                let spinner = progress::commit_spinner("Reading history", show_progress);
+                let ret = get_all_file_years(&paths, ignoring_commits, || spinner.inc(1));
+                spinner.finish_and_clear();
+                ret
+            }
+            Method::PerFile => {
+                let bar = progress::file_bar(paths.len(), "Reading history", show_progress);
+                let ret = paths
+                    .into_par_iter()
+                    .map(|path| {
+                        let years = get_file_years(&path, ignoring_commits);
+                        bar.inc(1);
+                        Ok((path, years?))
+                    })
+                    .collect();
+                bar.finish_and_clear();
+                ret
+            }
+            Method::Blame => Err(Error::Unsupported {
+                what: "--by-blame".to_owned(),
+                vcs: Kind::Mercurial.name(),
+            }),
+        }
+    }
+
+    fn file_authors(
+        &self,
+        paths: PathSet,
+        ignoring_commits: &HashSet<SHA1>,
+        show_progress: bool,
+    ) -> Result<AuthorMap, Error> {
+        let bar = progress::file_bar(paths.len(), "Reading authors", show_progress);
+        let ret = paths
+            .into_par_iter()
+            .map(|path| {
+                let authors = get_file_author_years(&path, ignoring_commits);
+                bar.inc(1);
+                Ok((path, authors?))
+            })
+            .collect();
+        bar.finish_and_clear();
+        ret
+    }
+
+    fn resolve_revision(&self, revision: &str) -> Result<SHA1, Error> {
+        let output = run_hg(
+            hg_command()
+                .arg("log")
+                .arg("--rev")
+                .arg(revision)
+                .arg("--limit")
+                .arg("1")
+                .arg("--template")
+                .arg("{node}"),
+            &format!("parse {:?}", revision),
+        )?;
+
+        parse_node(output.trim())
+    }
+
+    fn commits_by_authors(&self, pattern: &Regex) -> Result<HashSet<SHA1>, Error> {
+        // (Like Git, Mercurial can canonicalize authors with a .mailmap.)
+        let output = run_hg(
+            hg_command()
+                .arg("log")
+                .arg("--rev")
+                .arg("::.")
+                .arg("--template")
+                .arg("{node} {mailmap(author)}\\n"),
+            "find commit authors",
+        )?;
+
+        output
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter(|(_, author)| pattern.is_match(author))
+            .map(|(node, _)| parse_node(node))
+            .collect()
+    }
+
+    fn commits_by_message(&self, pattern: &Regex) -> Result<HashSet<SHA1>, Error> {
+        let output = run_hg(
+            hg_command()
+                .arg("log")
+                .arg("--rev")
+                .arg("::.")
+                .arg("--template")
+                .arg("{node} {desc|firstline}\\n"),
+            "find commit messages",
+        )?;
+
+        output
+            .lines()
+            .map(|line| line.split_once(' ').unwrap_or((line, "")))
+            .filter(|(_, subject)| pattern.is_match(subject))
+            .map(|(node, _)| parse_node(node))
+            .collect()
+    }
+
+    fn merge_commits(&self) -> Result<HashSet<SHA1>, Error> {
+        let output = run_hg(
+            hg_command()
+                .arg("log")
+                .arg("--rev")
+                .arg("merge() and ::.")
+                .arg("--template")
+                .arg("{node}\\n"),
+            "find merge commits",
+        )?;
+
+        output.lines().map(parse_node).collect()
+    }
+
+    fn dirty_files(&self) -> Result<HashSet<String>, Error> {
+        let output = run_hg_bytes(
+            hg_command()
+                .arg("status")
+                .arg("--modified")
+                .arg("--added")
+                .arg("--unknown")
+                .arg("--no-status")
+                .arg("--print0"),
+            "find uncommitted changes",
+        )?;
+        Ok(utf8_paths(output.split(|b| *b == 0)).into_iter().collect())
+    }
+
+    fn list_files(&self) -> Result<Vec<String>, Error> {
+        // Everything but removed, missing, and ignored files
+        let output = run_hg_bytes(
+            hg_command()
+                .arg("status")
+                .arg("--modified")
+                .arg("--added")
+                .arg("--clean")
+                .arg("--unknown")
+                .arg("--no-status")
+                .arg("--print0"),
+            "list files",
+        )?;
+
+        Ok(utf8_paths(output.split(|b| *b == 0)))
+    }
+
+    fn binary_files(&self, _paths: &PathSet) -> Result<HashSet<String>, Error> {
+        // Mercurial has no way to mark files binary,
+        // so we go by their contents alone.
+        Ok(HashSet::new())
+    }
+
+    fn ignored_files(&self, paths: &PathSet) -> Result<HashSet<String>, Error> {
+        let paths: Vec<&String> = paths.iter().collect();
+        let mut ignored = HashSet::new();
+
+        // Pass the paths in batches so we don't run into command line length limits.
+        for batch in paths.chunks(1000) {
+            let output = run_hg(
+                hg_command()
+                    .arg("status")
+                    .arg("--ignored")
+                    .arg("--no-status")
+                    .arg("--print0")
+                    .arg("--")
+                    .args(batch),
+                "check which files are ignored",
+            )?;
+            ignored.extend(split_nul(&output));
+        }
+
+        Ok(ignored)
+    }
+
+    fn config_values(&self, key: &str) -> Result<Vec<String>, Error> {
+        let what = format!("read {} from the Mercurial config", key);
+
+        let mut cmd = hg_command();
+        cmd.arg("config").arg(key);
+        let output = cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|source| Error::HgSpawn {
+                command: subcommand(&cmd),
+                source,
+            })?;
+
+        // hg config exits with 1 if the key isn't set, which is fine.
+        match output.status.code() {
+            Some(0) => {}
+            Some(1) => return Ok(Vec::new()),
+            _ => return Err(Error::HgFailed { what }),
+        }
+
+        let output = String::from_utf8(output.stdout)
+            .map_err(|_| Error::HgOutput("`hg config` printed invalid UTF-8".to_owned()))?;
+        let value = output.trim();
+        Ok(if value.is_empty() {
+            Vec::new()
+        } else {
+            vec![value.to_owned()]
+        })
+    }
+}
+
+/// Like `git::get_file_years()`: runs `hg log --follow` on the given file.
+fn get_file_years(path: &str, ignoring_commits: &HashSet<SHA1>) -> Result<Vec<Year>, Error> {
+    let output = run_hg(
+        hg_command()
+            .arg("log")
+            .arg("--follow")
+            .arg("--template")
+            .arg("{node} {date|hgdate}\\n")
+            .arg("--")
+            .arg(path),
+        &format!("find commit timestamps for {}", path),
+    )?;
+    parse_file_years(&output, ignoring_commits)
+}
+
+/// Parses the `{node} {date|hgdate}` lines `get_file_years()` gets
+/// from `hg log` into the years of the changesets we aren't ignoring.
+fn parse_file_years(output: &str, ignoring_commits: &HashSet<SHA1>) -> Result<Vec<Year>, Error> {
+    let mut ret = Vec::<Year>::new();
+
+    for line in output.lines() {
+        let (node, date) = line
+            .split_once(' ')
+            .ok_or_else(|| unexpected("log", line))?;
+
+        if !ignoring_commits.contains(&parse_node(node)?) {
+            ret.push(year_from_hgdate(date)?);
+        }
+    }
+
+    ret.sort();
+    ret.dedup();
+
+    Ok(ret)
+}
+
+/// Like `git::get_file_author_years()`: runs `hg log --follow` on the given
+/// file, and returns who wrote each changeset (and when), oldest first.
+fn get_file_author_years(
+    path: &str,
+    ignoring_commits: &HashSet<SHA1>,
+) -> Result<Vec<(String, Year)>, Error> {
+    let output = run_hg(
+        hg_command()
+            .arg("log")
+            .arg("--follow")
+            .arg("--template")
+            .arg("{node} {date|hgdate} {mailmap(author)}\\n")
+            .arg("--")
+            .arg(path),
+        &format!("find commit authors for {}", path),
+    )?;
+    parse_file_author_years(&output, ignoring_commits)
+}
+
+/// Parses the `{node} {date|hgdate} {author}` lines `get_file_author_years()`
+/// gets from `hg log` (newest first) into who wrote each changeset we aren't
+/// ignoring, and when, oldest first.
+fn parse_file_author_years(
+    output: &str,
+    ignoring_commits: &HashSet<SHA1>,
+) -> Result<Vec<(String, Year)>, Error> {
+    let mut ret = Vec::new();
+
+    for line in output.lines() {
+        // (hgdate is two numbers, separated by a space.)
+        let mut fields = line.splitn(4, ' ');
+        let (node, seconds, offset, author) =
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(n), Some(s), Some(o), Some(a)) => (n, s, o, a),
+                _ => return Err(unexpected("log", line)),
+            };
+
+        if !ignoring_commits.contains(&parse_node(node)?) {
+            let year = year_from_hgdate(&format!("{} {}", seconds, offset))?;
+            ret.push((author.to_owned(), year));
+        }
+    }
+
+    ret.reverse();
+    Ok(ret)
+}
+
+/// Like `git::get_all_file_years()`: finds the years each of the given files
+/// was changed with a single `hg log` over the whole history (newest first),
+/// following copies and renames ourselves.
+///
+/// Mercurial doesn't allow newlines in paths, so for each changeset we ask
+/// for a line with its ID and date, then a line for each file it changed,
+/// then a pair of lines for each file it copied (or renamed) from another.
+fn get_all_file_years<F: FnMut()>(
+    paths: &PathSet,
+    ignoring_commits: &HashSet<SHA1>,
+    on_commit: F,
+) -> Result<YearMap, Error> {
+    const TEMPLATE: &str = "commit {node} {date|hgdate}\\n\
+                            {files % 'file {file}\\n'}\
+                            {file_copies % 'copy {name}\\nfrom {source}\\n'}";
+
+    let mut cmd = hg_command();
+    cmd.arg("log")
+        .arg("--rev")
+        .arg("reverse(::.)")
+        .arg("--template")
+        .arg(TEMPLATE)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit());
+    let mut child = cmd.spawn().map_err(|source| Error::HgSpawn {
+        command: subcommand(&cmd),
+        source,
+    })?;
+
+    let years = read_all_file_years(
+        BufReader::new(child.stdout.take().unwrap()),
+        paths,
+        ignoring_commits,
+        on_commit,
+    )?;
+
+    if !child.wait().map(|s| s.success()).unwrap_or(false) {
+        return Err(Error::HgFailed {
+            what: "find commit timestamps".to_owned(),
+        });
+    }
+
+    Ok(years)
+}
+
+/// Reads the output of the `hg log` that `get_all_file_years()` runs
+/// into the years each of the given files was changed.
+fn read_all_file_years<R: BufRead, F: FnMut()>(
+    reader: R,
+    paths: &PathSet,
+    ignoring_commits: &HashSet<SHA1>,
+    mut on_commit: F,
+) -> Result<YearMap, Error> {
+    let mut years: YearMap = paths.iter().map(|p| (p.clone(), Vec::new())).collect();
+
+    // What each file we're looking for was called as of the changeset we're on
+    let mut names: HashMap<String, Vec<String>> = paths
+        .iter()
+        .map(|p| (p.trim_start_matches("./").to_owned(), vec![p.clone()]))
+        .collect();
+
+    // The year of the changeset we're on, or None if we're ignoring it
+    let mut year: Option<Year> = None;
+
+    // The file being copied, between its "copy" and "from" lines
+    let mut copy: Option<String> = None;
+
+    for line in reader.lines() {
+        let line = line.map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidData => {
+                Error::HgOutput("`hg log` printed invalid UTF-8".to_owned())
+            }
+            _ => Error::io("read `hg log` output")(e),
+        })?;
+
+        let (key, value) = line
+            .split_once(' ')
+            .ok_or_else(|| unexpected("log", &line))?;
+
+        match key {
+            "commit" => {
+                let (node, date) = value
+                    .split_once(' ')
+                    .ok_or_else(|| unexpected("log", &line))?;
+
+                year = if ignoring_commits.contains(&parse_node(node)?) {
+                    None
+                } else {
+                    Some(year_from_hgdate(date)?)
+                };
+                on_commit();
+            }
+            "file" => {
+                if let (Some(y), Some(followers)) = (year, names.get(value)) {
+                    for path in followers {
+                        years.get_mut(path).unwrap().push(y);
+                    }
+                }
+            }
+            "copy" => copy = Some(value.to_owned()),
+            "from" => {
+                let new = copy.take().ok_or_else(|| unexpected("log", &line))?;
+                // Before this changeset, the file went by its old name.
+                if let Some(followers) = names.remove(&new) {
+                    names.entry(value.to_owned()).or_default().extend(followers);
+                }
+            }
+            _ => return Err(unexpected("log", &line)),
+        }
+    }
+
+    for v in years.values_mut() {
+        v.sort();
+        v.dedup();
+    }
+
+    Ok(years)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(digit: char) -> String {
+        digit.to_string().repeat(40)
+    }
+
+    fn ignoring(digit: char) -> HashSet<SHA1> {
+        std::iter::once(SHA1::parse(&node(digit)).unwrap()).collect()
+    }
+
+    fn year(y: u16) -> Year {
+        Year::new(y).unwrap()
+    }
+
+    fn years(ys: &[u16]) -> Vec<Year> {
+        ys.iter().copied().map(year).collect()
+    }
+
+    #[test]
+    fn hgdates_are_local_times() {
+        // 2019-12-31T23:59:59Z is already 2020 an hour east of UTC,
+        // which hgdate writes as -3600 (seconds *west*).
+        assert_eq!(year_from_hgdate("1577836799 0").unwrap(), year(2019));
+        assert_eq!(year_from_hgdate("1577836799 -3600").unwrap(), year(2020));
+        assert!(year_from_hgdate("1577836799").is_err());
+        assert!(year_from_hgdate("yesterday 0").is_err());
+    }
+
+    #[test]
+    fn file_years_skip_ignored_changesets() {
+        let output = format!(
+            "{} 1600000000 0\n{} 1500000000 0\n{} 1400000000 0\n{} 1400000000 0\n",
+            node('a'),
+            node('b'),
+            node('c'),
+            node('d')
+        );
+        assert_eq!(
+            parse_file_years(&output, &ignoring('b')).unwrap(),
+            years(&[2014, 2020])
+        );
+        assert!(parse_file_years("not a changeset\n", &HashSet::new()).is_err());
+    }
+
+    #[test]
+    fn file_authors_are_oldest_first() {
+        let output = format!(
+            "{} 1600000000 0 Jane Doe <jane@example.com>\n\
+             {} 1500000000 0 Bot <bot@example.com>\n\
+             {} 1400000000 0 John Smith <john@example.com>\n",
+            node('a'),
+            node('b'),
+            node('c')
+        );
+        let authors = parse_file_author_years(&output, &ignoring('b')).unwrap();
+        assert_eq!(
+            authors,
+            [
+                ("John Smith <john@example.com>".to_owned(), year(2014)),
+                ("Jane Doe <jane@example.com>".to_owned(), year(2020)),
+            ]
+        );
+        let output = format!("{} 1600000000\n", node('a'));
+        assert!(parse_file_author_years(&output, &HashSet::new()).is_err());
+    }
+
+    #[test]
+    fn all_file_years_follow_renames() {
+        // (Newest first, like `hg log --rev 'reverse(::.)'` prints it.)
+        let output = format!(
+            "commit {} 1600000000 0\n\
+             file new.c\n\
+             copy new.c\n\
+             from old.c\n\
+             commit {} 1500000000 0\n\
+             file old.c\n\
+             commit {} 1400000000 0\n\
+             file old.c\n\
+             file other.c\n\
+             file unrelated.c\n",
+            node('a'),
+            node('b'),
+            node('c')
+        );
+        let paths: PathSet = ["new.c", "./other.c", "untouched.c"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+
+        let mut commits = 0;
+        let found = read_all_file_years(output.as_bytes(), &paths, &ignoring('b'), || commits += 1)
+            .unwrap();
+        assert_eq!(commits, 3);
+        assert_eq!(found["new.c"], years(&[2014, 2020]));
+        assert_eq!(found["./other.c"], years(&[2014]));
+        assert_eq!(found["untouched.c"], years(&[]));
+        assert_eq!(found.len(), 3);
+    }
+
+    #[test]
+    fn unexpected_log_lines_are_errors() {
+        let paths = PathSet::default();
+        for output in ["what 1\n", "nonsense\n", "from old.c\n"] {
+            assert!(
+                read_all_file_years(output.as_bytes(), &paths, &HashSet::new(), || {}).is_err(),
+                "{:?}",
+                output
+            );
+        }
+    }
+}
@@ -1,5 +1,14 @@
-//! Use git-historian to find the years in which our files were changed
-//! according to Git history. See that library for details of how it works.
+//! Finds the years in which our files were changed according to Git history,
+//! using the helpers in `git.rs` (which run `git`, or use libgit2 with
+//! `--git-backend=libgit2`). This is how the Git `Vcs` (see `vcs.rs`)
+//! answers `file_years()`, behind the cache in `cache.rs`.
+//! (Mercurial's `Vcs` has its own, in `hg.rs`, and takes the same `Method`.)
+//!
+//! By default, every commit that touched a file counts, and we find them
+//! with a single walk through the whole history.
+//! With `--per-file-history`, we instead run `git log --follow` on each file,
+//! which can be slow on big repos but follows renames exactly as Git does.
+//! With `--by-blame`, only commits that wrote lines still in the file count.
 
 extern crate time;
 
@@ -9,18 +18,55 @@ use rayon::prelude::*;
 
 use crate::git::*;
 use crate::common::*;
+use crate::error::Error;
+use crate::progress;
+
+/// How we go through history to find the years files were changed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// One `git log` over the whole repo
+    Batched,
+
+    /// `git log --follow` for each file
+    PerFile,
+
+    /// `git blame` for each file
+    Blame,
+}
+
+pub fn get_year_map(
+    git: &GitOptions,
+    paths: PathSet,
+    ignore_commits: &HashSet<SHA1>,
+    method: Method,
+    show_progress: bool,
+) -> Result<YearMap, Error> {
+    if method == Method::Batched {
+        let spinner = progress::commit_spinner("Reading history", show_progress);
+        let ret = get_all_file_years(git, &paths, ignore_commits, || spinner.inc(1));
+        spinner.finish_and_clear();
+        return ret;
+    }
+
+    let bar = progress::file_bar(paths.len(), "Reading history", show_progress);
 
-pub fn get_year_map(paths: PathSet, ignore_commits: &HashSet<SHA1>) -> YearMap {
     // Let's paralellize! I'm assuming this process will be largely bottlenecked
     // by the I/O of actually reading the files, but we can let the OS'es I/O
     // scheduler figure that out.
-    let ret: YearMap = paths
+    let ret: Result<YearMap, Error> = paths
         .into_par_iter()
         .map(|path| {
-            let file_history = get_file_years(&path, &ignore_commits);
-            (path, file_history)
+            let file_history = if method == Method::Blame {
+                get_file_blame_years(git, &path, ignore_commits)
+            } else {
+                get_file_years(git, &path, ignore_commits)
+            };
+            bar.inc(1);
+            Ok((path, file_history?))
         })
         .collect();
 
+    bar.finish_and_clear();
+
     ret
 }
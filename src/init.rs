@@ -0,0 +1,156 @@
+//! Looks a repo over for `copyrighter init`, so it can suggest settings
+//! that fit, and installs a pre-commit hook that keeps notices current.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::comment;
+use crate::common::Year;
+use crate::config;
+use crate::encoding;
+use crate::error::Error;
+use crate::git::{self, GitOptions};
+use crate::notice::{find_notice, ScanOptions};
+use crate::vcs::{Kind, Vcs};
+
+/// How many of the most common extensions we suggest updating
+const SUGGESTED_EXTENSIONS: usize = 5;
+
+/// What the pre-commit hook runs
+const HOOK: &str = "#!/bin/sh\n\
+                    # Installed by `copyrighter init`: updates the notices\n\
+                    # of the files being committed, and stages the changes.\n\
+                    exec copyrighter update --staged\n";
+
+/// What we found in a repo
+#[derive(Debug)]
+pub struct Survey {
+    /// How many files we know how to comment
+    pub files: usize,
+
+    /// Those files' extensions, most common first,
+    /// with how many files have each
+    pub extensions: Vec<(String, usize)>,
+
+    /// How many of those files have notices already
+    pub with_notices: usize,
+
+    /// The holders those notices name, most common first,
+    /// with how many files name each
+    pub holders: Vec<(String, usize)>,
+
+    /// The year of the first commit, if there are any commits yet
+    pub first_year: Option<Year>,
+}
+
+impl Survey {
+    /// The extensions we'd suggest updating: the most common ones
+    pub fn suggested_extensions(&self) -> Vec<String> {
+        self.extensions
+            .iter()
+            .take(SUGGESTED_EXTENSIONS)
+            .map(|(e, _)| e.clone())
+            .collect()
+    }
+
+    /// The holder we'd suggest: whoever most existing notices name
+    pub fn suggested_holder(&self) -> Option<&str> {
+        self.holders.first().map(|(h, _)| h.as_str())
+    }
+}
+
+/// Looks over the files in the repo and its history.
+pub fn survey(vcs: &dyn Vcs) -> Result<Survey, Error> {
+    let files: Vec<String> = vcs
+        .list_files()?
+        .into_iter()
+        // (Leave out a config file we're about to replace.)
+        .filter(|f| f != config::CONFIG_PATH)
+        .filter(|f| comment::from_extension(f).is_some() && Path::new(f).is_file())
+        .collect();
+
+    let mut extensions = HashMap::new();
+    for ext in files
+        .iter()
+        .filter_map(|f| Path::new(f).extension()?.to_str())
+    {
+        *extensions.entry(ext.to_owned()).or_insert(0) += 1;
+    }
+
+    // (Files we can't read just don't count.)
+    let scan = ScanOptions::new(comment::StyleRegistry::new(&comment::DOUBLE_SLASH));
+    let notices: Vec<Vec<String>> = files
+        .par_iter()
+        .filter_map(|f| {
+            let fh = encoding::open(f).ok()?;
+            find_notice(BufReader::new(fh), &scan.for_file(f))
+                .ok()?
+                .map(|n| n.holders)
+        })
+        .collect();
+
+    let mut holders = HashMap::new();
+    for holder in notices.iter().flatten().map(|h| h.trim()) {
+        if !holder.is_empty() {
+            *holders.entry(holder.to_owned()).or_insert(0) += 1;
+        }
+    }
+
+    Ok(Survey {
+        files: files.len(),
+        extensions: by_count(extensions),
+        with_notices: notices.len(),
+        holders: by_count(holders),
+        // A brand new repo doesn't have a first commit (yet).
+        first_year: vcs.first_commit_year().ok(),
+    })
+}
+
+/// Sorts counts from most to least common,
+/// breaking ties alphabetically so that we suggest the same thing every time.
+fn by_count(counts: HashMap<String, usize>) -> Vec<(String, usize)> {
+    let mut sorted: Vec<(String, usize)> = counts.into_iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sorted
+}
+
+/// Installs a Git pre-commit hook that runs `copyrighter update --staged`,
+/// replacing one that's already there only if `replace` is set.
+/// (`git` says how to ask Git where hooks go.) Returns where it went.
+pub fn install_hook(vcs: &dyn Vcs, git: &GitOptions, replace: bool) -> Result<PathBuf, Error> {
+    if vcs.kind() != Kind::Git {
+        return Err(Error::Unsupported {
+            what: "--hook".to_owned(),
+            vcs: vcs.kind().name(),
+        });
+    }
+
+    let dir = git::hooks_dir(git)?;
+    let path = dir.join("pre-commit");
+    if !replace && path.exists() {
+        return Err(Error::AlreadyExists(path.display().to_string()));
+    }
+
+    fs::create_dir_all(&dir).map_err(Error::io(format!("create {}", dir.display())))?;
+    fs::write(&path, HOOK).map_err(Error::io(format!("write {}", path.display())))?;
+    make_executable(&path)?;
+    Ok(path)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+        .map_err(Error::io(format!("make {} executable", path.display())))
+}
+
+/// (Git for Windows runs hooks whatever their permissions.)
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), Error> {
+    Ok(())
+}
@@ -0,0 +1,252 @@
+//! Writes what we found and did as JSON (`--report-json`),
+//! for tools that want it without scraping our output.
+//!
+//! The report looks like
+//!
+//! ```json
+//! {
+//!   "files": [
+//!     { "path": "src/main.rs", "years": [2019, 2020], "action": "updated" }
+//!   ]
+//! }
+//! ```
+//!
+//! where `action` is one of `added`, `updated`, `removed`, `current`,
+//! `skipped`, or `failed`.
+//!
+//! `--output-format jsonl` instead prints a line of JSON for each file
+//! (see `write_lines()`), e.g.
+//!
+//! ```json
+//! {"path":"src/main.rs","action":"updated","old_years":[2019],"new_years":[2019,2020],"holder":"Fluke Corporation","error":null}
+//! ```
+//!
+//! and `--output-format sarif` prints a SARIF 2.1.0 log (see `write_sarif()`)
+//! for code scanning tools.
+
+use std::fs::File;
+use std::io::{self, Write};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::common::{Year, YearMap};
+use crate::error::Error;
+use crate::update::{self, Action, NoticeOptions, Report};
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    files: Vec<FileEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct FileEntry<'a> {
+    path: &'a str,
+    years: Vec<u16>,
+    action: Action,
+}
+
+#[derive(Serialize)]
+struct FileLine<'a> {
+    path: &'a str,
+    action: Action,
+
+    /// The years the file's notice listed before we got to it
+    old_years: Vec<u16>,
+
+    /// The years its notice lists now (or would, for dry runs and checks),
+    /// or none if we left it alone
+    new_years: Option<Vec<u16>>,
+
+    /// Who the notice names: us (the first of our organizations for the file),
+    /// or whoever it names that made us skip it
+    holder: Option<&'a str>,
+
+    /// What went wrong, if we couldn't read or write the file
+    error: Option<String>,
+}
+
+fn years_json(years: &[Year]) -> Vec<u16> {
+    years.iter().map(|y| y.get()).collect()
+}
+
+/// Prints a line of JSON to stdout for each file, sorted by path,
+/// given the years each file's notice listed beforehand (`old_years`)
+/// and the ones we found for it (`new_years`).
+/// Without `options` (e.g. when stripping notices), no holders are listed
+/// besides those of skipped files.
+pub fn write_lines(
+    old_years: &YearMap,
+    new_years: &YearMap,
+    report: &Report,
+    options: Option<&NoticeOptions>,
+) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    for (path, action) in &report.actions {
+        let ours = || options.and_then(|o| o.organizations_for(path).first());
+        let (new, holder) = match action {
+            Action::Added | Action::Updated | Action::Current => (
+                Some(new_years.get(path).map_or_else(Vec::new, |y| years_json(y))),
+                ours().map(|h| h.as_str()),
+            ),
+            Action::Removed => (Some(Vec::new()), None),
+            Action::Skipped => (
+                None,
+                report
+                    .skipped
+                    .iter()
+                    .find(|(p, _)| p == path)
+                    .map(|(_, h)| h.as_str()),
+            ),
+            Action::Failed => (None, None),
+        };
+        let line = FileLine {
+            path,
+            action: *action,
+            old_years: old_years.get(path).map_or_else(Vec::new, |y| years_json(y)),
+            new_years: new,
+            holder,
+            error: report
+                .failures
+                .iter()
+                .find(|(p, _)| p == path)
+                .map(|(_, e)| e.to_string()),
+        };
+
+        serde_json::to_writer(&mut out, &line)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(out))
+            .map_err(Error::io("write JSON lines to stdout"))?;
+    }
+    Ok(())
+}
+
+/// Writes the report to the given path, or stdout if it's `-`.
+/// Files missing from `years` are listed without any.
+pub fn write(dest: &str, years: &YearMap, report: &Report) -> Result<(), Error> {
+    let files = report
+        .actions
+        .iter()
+        .map(|(path, action)| FileEntry {
+            path,
+            years: years
+                .get(path)
+                .map_or_else(Vec::new, |ys| ys.iter().map(|y| y.get()).collect()),
+            action: *action,
+        })
+        .collect();
+    let json = JsonReport { files };
+
+    let what = format!("write the JSON report to {}", dest);
+    let mut out: Box<dyn Write> = if dest == "-" {
+        Box::new(io::stdout())
+    } else {
+        Box::new(File::create(dest).map_err(Error::io(what.clone()))?)
+    };
+    serde_json::to_writer_pretty(&mut out, &json)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(out))
+        .map_err(Error::io(what))
+}
+
+/// Describes each problem SARIF results can point out:
+/// (rule ID, short description, the action that fixes it)
+const SARIF_RULES: &[(&str, &str, Action)] = &[
+    (
+        "missing-notice",
+        "File has no copyright notice",
+        Action::Added,
+    ),
+    (
+        "stale-notice",
+        "Copyright notice is out of date",
+        Action::Updated,
+    ),
+];
+
+/// Prints a SARIF log to stdout with a result for each file whose notice
+/// is missing or out of date (i.e., that we added or updated one for,
+/// or would have), and a notification for each file we couldn't handle.
+pub fn write_sarif(years: &YearMap, report: &Report) -> Result<(), Error> {
+    let rules: Vec<serde_json::Value> = SARIF_RULES
+        .iter()
+        .map(|(id, description, _)| {
+            json!({
+                "id": id,
+                "shortDescription": { "text": description },
+                "defaultConfiguration": { "level": "warning" },
+            })
+        })
+        .collect();
+
+    let results: Vec<serde_json::Value> = report
+        .actions
+        .iter()
+        .filter_map(|(path, action)| {
+            let (index, (id, _, _)) = SARIF_RULES
+                .iter()
+                .enumerate()
+                .find(|(_, (_, _, a))| a == action)?;
+            let listed = years
+                .get(path)
+                .map_or_else(String::new, |y| update::format_year_list(y, ","));
+            let text = match action {
+                Action::Added => format!(
+                    "{} has no copyright notice (it should list {})",
+                    path, listed
+                ),
+                _ => format!("{}'s copyright notice should list {}", path, listed),
+            };
+            Some(json!({
+                "ruleId": id,
+                "ruleIndex": index,
+                "level": "warning",
+                "message": { "text": text },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {
+                            "uri": path.trim_start_matches("./"),
+                            "uriBaseId": "%SRCROOT%",
+                        },
+                        // (Notices go at the top of the file.)
+                        "region": { "startLine": 1 },
+                    }
+                }],
+            }))
+        })
+        .collect();
+
+    let notifications: Vec<serde_json::Value> = report
+        .failures
+        .iter()
+        .map(|(_, e)| json!({ "level": "error", "message": { "text": e.to_string() } }))
+        .collect();
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "copyrighter",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "invocations": [{
+                "executionSuccessful": report.failures.is_empty(),
+                "toolExecutionNotifications": notifications,
+            }],
+            "results": results,
+        }],
+    });
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    serde_json::to_writer_pretty(&mut out, &log)
+        .map_err(io::Error::from)
+        .and_then(|()| writeln!(out))
+        .map_err(Error::io("write the SARIF log to stdout"))
+}
@@ -0,0 +1,181 @@
+//! The guts of copyrighter, for tools that want to find and update
+//! copyright notices without shelling out to the binary.
+//!
+//! The usual flow is:
+//!
+//! 1. Find the years files were changed with `collect_git_years()`.
+//! 2. Find the years their existing notices list with `scan_existing_headers()`.
+//! 3. Drop the notices' years that history should account for
+//!    (those after `vcs::Vcs::first_commit_year()`)
+//!    with `common::trim_header_years()`,
+//!    then combine them with `common::combine_year_maps()`.
+//! 4. Write notices with `update::update_headers()`, or render them yourself
+//!    with `render_header()`.
+//!
+//! Git (or Mercurial) commands are run in the current directory, which should
+//! be the top of the repo. (See `vcs::Vcs::assert_at_repo_top()`.)
+//!
+//! Anything that can fail returns an `error::Error` instead of exiting,
+//! and per-file failures while rewriting are collected in `update::Report`.
+//! Warnings, and what we decided about each file, go through the `log` crate.
+
+pub mod authors;
+pub mod backup;
+pub mod cache;
+pub mod comment;
+pub mod common;
+pub mod config;
+pub mod dep5;
+pub mod diff;
+pub mod discover;
+pub mod encoding;
+pub mod error;
+pub mod existing;
+pub mod external;
+pub mod git;
+pub mod hg;
+pub mod history;
+pub mod init;
+pub mod json_report;
+pub mod notice;
+pub mod notice_file;
+pub mod plan;
+pub mod progress;
+pub mod stats;
+pub mod template;
+pub mod update;
+pub mod vcs;
+pub mod watch;
+
+use std::collections::HashSet;
+
+use crate::comment::CommentStyle;
+use crate::common::{clamp_year_list, PathSet, Year, YearMap};
+use crate::error::Error;
+use crate::git::{GitOptions, SHA1};
+use crate::notice::ScanOptions;
+
+/// How to go through Git history in `collect_git_years()`
+#[derive(Debug)]
+pub struct HistoryOptions {
+    /// Commits that don't count
+    pub ignore_commits: HashSet<SHA1>,
+
+    pub method: history::Method,
+
+    /// Draw a progress bar on stderr (if it's a terminal)
+    pub show_progress: bool,
+
+    /// How to run Git, and which changes and dates count
+    pub git: GitOptions,
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        HistoryOptions {
+            ignore_commits: HashSet::new(),
+            method: history::Method::Batched,
+            show_progress: false,
+            git: GitOptions::default(),
+        }
+    }
+}
+
+/// How `render_header()` writes a notice
+#[derive(Debug)]
+pub struct RenderOptions {
+    /// What the notice starts with, after the comment opener
+    pub lead: String,
+
+    /// What goes between listed years
+    pub year_separator: String,
+
+    /// Years before this are moved up to it (like `--min-year`)
+    pub min_year: Option<Year>,
+
+    /// Years after this are moved down to it (like `--max-year`)
+    pub max_year: Option<Year>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            lead: notice::DEFAULT_LEAD.to_owned(),
+            year_separator: ",".to_owned(),
+            min_year: None,
+            max_year: None,
+        }
+    }
+}
+
+/// Finds the years each of the given files was changed, according to Git.
+pub fn collect_git_years(paths: PathSet, opts: &HistoryOptions) -> Result<YearMap, Error> {
+    history::get_year_map(
+        &opts.git,
+        paths,
+        &opts.ignore_commits,
+        opts.method,
+        opts.show_progress,
+    )
+}
+
+/// Finds the years listed in each of the given files' existing notices,
+/// looking for them as `scan` says to.
+/// Files without notices map to no years; files we can't read are left out.
+pub fn scan_existing_headers(paths: PathSet, scan: &ScanOptions) -> YearMap {
+    existing::get_year_map(paths, scan)
+}
+
+/// Renders a notice for the given years and organization in the given style,
+/// like `// Copyright © 2019,2020 Fluke Corporation`, without a trailing newline.
+/// Its years are clamped, sorted, and deduplicated the way the binary does
+/// (see `common::clamp_year_list()`).
+pub fn render_header(
+    years: &[Year],
+    org: &str,
+    style: &CommentStyle,
+    options: &RenderOptions,
+) -> String {
+    let mut years = years.to_vec();
+    clamp_year_list(&mut years, options.min_year, options.max_year);
+    let lead = format!("{} {}", style.opener, options.lead);
+    let body = format!(
+        "{} {}",
+        update::format_year_list(&years, &options.year_separator),
+        org
+    );
+    update::comment_line(&lead, &body, style.closer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn years(ys: &[u16]) -> Vec<Year> {
+        ys.iter().map(|&y| Year::new(y).unwrap()).collect()
+    }
+
+    #[test]
+    fn rendered_years_are_normalized() {
+        let style = comment::DOUBLE_SLASH;
+        assert_eq!(
+            render_header(&years(&[2020, 2019]), "Acme", &style, &Default::default()),
+            "// Copyright © 2019,2020 Acme"
+        );
+
+        let options = RenderOptions {
+            year_separator: ", ".to_owned(),
+            min_year: Year::new(2015),
+            max_year: Year::new(2020),
+            ..Default::default()
+        };
+        assert_eq!(
+            render_header(&years(&[2010, 2014, 2018, 2023]), "Acme", &style, &options),
+            "// Copyright © 2015, 2018, 2020 Acme"
+        );
+        assert_eq!(
+            render_header(&years(&[2018]), "Acme", &comment::SLASH_STAR, &options),
+            "/* Copyright © 2018 Acme */"
+        );
+    }
+}
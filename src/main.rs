@@ -1,23 +1,481 @@
-//! Copyrighter uses Git history and existing copyright notices to generate updated
-//! ones for files.
+//! Copyrighter uses Git (or Mercurial) history and existing copyright notices
+//! to generate updated ones for files.
 //!
 //! # Usage:
 //!
 //! ```text
-//! copyrighter -o <organization> -i <commits>
+//! copyrighter [update] -o <organization> -i <commits> <files>
+//! copyrighter [update] -o <organization> -i <commits> --ext <ext1[,ext2,...]>
+//! copyrighter check [options] <files>
+//! copyrighter report [options] <files>
+//! copyrighter analyze --out <plan.json> [options] <files>
+//! copyrighter apply <plan.json>
+//! copyrighter filter --path <file> [options] < contents
+//! copyrighter watch --ext <ext1[,ext2,...]> [options]
+//! copyrighter notice [--out <file|->] [--title <text>] [options] <files>
+//! copyrighter dep5 [--out <file|->] [--upstream-name <name>] [options] <files>
+//! copyrighter restore
+//! copyrighter init [-o <organization>] [--hook] [--yes] [--force]
+//! copyrighter completions <bash|zsh|fish|elvish|powershell>
 //! ```
 //!
-//! where
+//! - `update` (the default, if no subcommand is given) updates or adds notices.
+//! - `check` doesn't modify anything. Instead, it lists files whose notices are
+//!   out of date and exits with 0 if everything is current, 1 if some files
+//!   need updates, or 2 if some files couldn't be checked.
+//! - `report` doesn't modify anything either. Instead, it lists each file's
+//!   years and what we'd do to its notice. With `--stats`, it sums them up
+//!   instead: how many files were first changed in each year, how many are
+//!   missing notices or have out-of-date ones, who their notices name,
+//!   and the years they span.
+//! - `analyze` doesn't modify anything either. Instead, it reads history
+//!   and works out each file's edit, like `update --dry-run`, but saves them
+//!   (and each file's years) to the plan given with `--out`.
+//! - `apply` makes the edits in a plan from `analyze`, without looking at
+//!   history again, e.g. once the plan has been reviewed, or on a checkout
+//!   that doesn't have the history. Files that have changed since the plan
+//!   was made are left alone (and listed as errors). It only takes
+//!   `--write-strategy`, `--backup`, `--concurrency-safe`, `--preserve-mtime`,
+//!   `--allow-chmod`, and `--jobs`.
+//! - `filter` reads a file's contents from stdin, updates (or adds) their
+//!   notice as if they were the file given with `--path`, and prints the
+//!   result, e.g. for editors, or as a Git clean filter:
+//!   `git config filter.copyright.clean "copyrighter filter --include-dirty --path %f"`
+//!   with `* filter=copyright` in `.gitattributes`. Contents we'd leave alone
+//!   (like a generated file's) come back unchanged. It takes the same options
+//!   as `update`, besides the ones that pick files or say how to write them
+//!   (like `--ext`, `--strip`, or `--backup`).
+//! - `watch` keeps an eye on the working tree, and updates (or adds) the
+//!   notices of files as they're saved, counting this year for them if they
+//!   differ from what's committed. It only updates files that `--ext`,
+//!   `--include`, and `--exclude` (one of the first two is required) pick out,
+//!   and that aren't ignored by Git (or Mercurial), `.copyrighterignore`,
+//!   or `--skip-vendored`. It runs until it's killed. It takes the same options
+//!   as `update`, besides the ones that pick files or say what to do
+//!   to them (like `--staged`, `--strip`, or `--backup`).
+//! - `notice` doesn't modify any of the files either. Instead, it gathers
+//!   every holder their notices will name (once updated, so with the years
+//!   history gives them) into a NOTICE file, like Apache-2.0 distributions
+//!   ship: a `Copyright <years> <holder>` line for each, earliest first,
+//!   with runs of years written as ranges. Files whose notices name someone
+//!   else count for them, with the years those notices list. It's written to
+//!   `NOTICE` at the top of the repo, or wherever `--out` says (- for stdout),
+//!   after the line given with `--title` (e.g. the product's name), if any.
+//! - `dep5` doesn't modify any of the files either. Instead, it writes
+//!   a machine-readable Debian copyright file (DEP-5) for them, grouping them
+//!   by holders (with years, as `notice` finds them) and license: the one
+//!   given with `--spdx`, or whatever their `SPDX-License-Identifier:` says.
+//!   The biggest group becomes the `Files: *` paragraph, and the others list
+//!   their files, or `dir/*` for directories whose files are all in them.
+//!   It's written to `debian/copyright`, or wherever `--out` says
+//!   (- for stdout), with the `--upstream-name` and `--source` given, if any.
+//! - `restore` undoes the last run made with `--backup`, putting back the
+//!   files it changed and removing the ones it created.
+//! - `init` looks the repo over (which languages it's in, what its existing
+//!   notices say, and when its history starts), asks a few questions, and
+//!   writes a starter `.copyrighter.toml` at the top of the repo. With `--hook`
+//!   (or if you say so), it also installs a Git pre-commit hook that runs
+//!   `copyrighter update --staged`. It only asks when stdin is a terminal
+//!   and `--yes` isn't given; otherwise it goes with what it found.
+//!   It won't replace an existing config file or hook unless given `--force`.
+//! - `completions` prints a completion script for the given shell, e.g.
+//!   `copyrighter completions bash > /etc/bash_completion.d/copyrighter`
+//!
+//! All of them (besides `apply`, `restore`, `init`, and `completions`)
+//! take the same options:
 //!
 //! ```
 //! --organization, -o
-//!   The organization claiming the copyright, and any following text
+//!   The organization claiming the copyright, and any following text.
+//!   Repeat to list multiple holders, each on its own line.
+//!   Existing notices keep their holders; only their years are updated,
+//!   and the text after our organizations (see --organization-suffix).
+//!   Files whose notices name some other holder are skipped (and listed).
+//!   Multiple notice lines for the same organization (within --scan-lines)
+//!   are merged into one, listing all of their years.
+//!   If not given, we use $COPYRIGHTER_ORG, then the config file (see below),
+//!   then `git config copyrighter.organization` (which can be repeated),
+//!   or `hg config copyrighter.organization` in Mercurial repos.
+//!
+//! --force-org
+//!   Replace the holders of existing notices with the given organization(s)
+//!   (and suffix), instead of keeping them.
+//!
+//! --allow-holder <name>
+//!   Update the years of notices naming the given holder too, instead of
+//!   skipping those files as someone else's. Can be repeated.
+//!   On notices that name one of ours as well (as joint holders),
+//!   their lines keep the years they list, since our history can't say
+//!   which years are theirs.
+//!
+//! --organization-suffix <text>
+//!   Text appended after the organization, e.g. "All rights reserved."
+//!   Existing notice lines naming one of our organizations get it too,
+//!   replacing whatever followed the organization there.
+//!
+//! Organizations and suffixes can use the placeholder {now_year},
+//! which is replaced with the current year.
+//!
+//! --sort-holders
+//!   Sort multiple holders alphabetically instead of keeping the order given.
+//!
+//! --per-author <file>
+//!   Instead of giving each file the organization's notice, give each
+//!   author's employer a notice line listing the years their authors changed
+//!   the file, in the order they first did. The given file says who works
+//!   where, like a .mailmap: each line gives an employer, then the addresses
+//!   of the authors who work there, e.g.
+//!   `Acme Corporation <alice@example.com> <*@acme.example>`,
+//!   where <*@domain> stands for everyone with an address there.
+//!   Authors who aren't listed hold their own work, by their (mailmapped) name.
+//!   Files no one has committed yet go to the organization, as usual.
+//!
+//! --year-separator <sep>
+//!   What to put between listed years, e.g. ", " (Default: ",")
+//!   Existing notices separated by commas, semicolons, or spaces are understood.
+//!
+//! --gap-tolerance <years>
+//!   List years as ranges, and take gaps of up to the given number of years
+//!   into them, so 2010-2014,2016,2018-2024 becomes 2010-2024 with 1 (or more).
+//!   With 0, runs of years become ranges, but gaps stay.
+//!   Notices in NOTICE and debian/copyright files list the same years.
+//!
+//! --min-year <year>, --max-year <year|now>
+//!   Never list years before (or after) the given year, whether they came from
+//!   history or existing notices. Earlier (or later) years are replaced with it,
+//!   so a file last changed before --min-year still lists that year.
+//!   "now" is the current year.
+//!
+//! --symbol <©|(c)|(C)|none>
+//!   The copyright symbol notices use. (Default: ©)
+//!
+//! --format <full|symbol-only>
+//!   Whether notices say "Copyright" before the symbol (full),
+//!   or just have the symbol. (Default: full)
+//!   Notices we rewrite are put in this format too.
+//!
+//! --normalize
+//!   Rewrite notices in our format even if they already list
+//!   the right years and holders. (By default, we leave them be.)
+//!
+//! --spdx <license-id>
+//!   Add an SPDX-License-Identifier line with the given license after the
+//!   notice, or update the existing one. (SPDX-FileCopyrightText notices
+//!   are understood and kept in that format regardless.)
+//!
+//! --template <file>
+//!   Stamp the given multi-line template (e.g. license boilerplate) instead
+//!   of single notice lines, in a comment of each file's style.
+//!   {years}, {organization} (with the suffix), {filename}, {spdx},
+//!   and {now_year} are filled in. Blocks rendered from the template before are updated;
+//!   files with some other notice just have it updated as usual.
+//!
+//! --default-comment-style <//|/*|#|--|;|<!--|{#|<%#>
+//!   The comment style for new notices in files whose style can't be
+//!   guessed from their extension, shebang, or contents. (Default: //)
+//!
+//! --comment-style <ext>=<style>
+//!   Use the given comment style for new notices in files with the given
+//!   extension, e.g. "tpl={#". Can be repeated.
+//!
+//! --placement <top|after-shebang|after-comment>
+//!   Where new notices go. top puts them on the very first line, even before
+//!   a shebang (which stops working there). after-shebang puts them after
+//!   a shebang or XML declaration, if the file starts with one.
+//!   after-comment also puts them after the comment the file starts with
+//!   (e.g. a description of the file, or a license stub), unless it's too long
+//!   for --scan-lines to find the notice there. (Default: after-shebang)
+//!
+//! --add-missing-only
+//!   Only add notices to files without one, leaving existing notices be,
+//!   e.g. to roll notices out without touching the ones people wrote by hand.
+//!
+//! --update-only
+//!   Only update existing notices, never adding new ones.
+//!
+//! --scan-lines <N>
+//!   How many lines at the top of each file to look through for an existing
+//!   notice, past banners, blank lines, and other comments. (Default: 10)
+//!
+//! --max-line-length <bytes>
+//!   How long a line can be before we stop looking through it (and any after
+//!   it) for a notice, so that we don't read all of a file that's one huge
+//!   line, like minified code. Files that start with such a line are treated
+//!   as not having a notice. (Default: 65536)
+//!
+//! --write-strategy <in-place|atomic>
+//!   How to rewrite files. in-place slides their contents around to make room
+//!   for the new notice, which is fast but could mangle a file if we're killed
+//!   partway through. atomic writes a new copy next to the file, then renames it
+//!   over the original. (Default: in-place)
+//!
+//! --backup[=<suffix>]
+//!   Copy each file to <file><suffix> (<file>.bak by default) before changing
+//!   it, so that `copyrighter restore` can undo the run. If some other file
+//!   is already there, the copy goes to <file><suffix>.1 (or .2...) instead.
+//!
+//! --concurrency-safe
+//!   Lock each file while rewriting it, in case something else
+//!   (like another copyrighter run) is modifying it at the same time.
+//!
+//! --preserve-mtime
+//!   Put each file's modification time back after rewriting it,
+//!   so incremental builds don't rebuild everything after a sweep.
+//!
+//! --allow-chmod
+//!   Make read-only files that need changes writable while rewriting them,
+//!   then put their permissions back. Otherwise they're listed as errors.
+//!
+//! --jobs, -j <N>
+//!   Run at most N Git processes or file rewrites at once.
+//!   (Default: one per CPU)
+//!
+//! --count-only
+//!   Just count how many files have copyright notices and exit.
+//!   (Doesn't look at Git history or modify anything.)
+//!
+//! --ext <ext1[,ext2,...]>
+//!   Find files with the given extensions in the repo ourselves
+//!   (skipping anything Git ignores), instead of only using the files given.
+//!
+//! --staged
+//!   Only work on files with staged changes, counting this year for them,
+//!   then stage our changes to them too. Made for pre-commit hooks.
+//!   (Files with unstaged changes as well are refused, since we'd be
+//!   updating what isn't being committed.)
+//!   --ext, --include, and --exclude narrow these down further.
+//!
+//! --changed-since <ref>
+//!   Only work on files changed between (the merge base of) the given ref
+//!   and HEAD, e.g. "origin/main" for the files a branch touched.
+//!   --ext, --include, and --exclude narrow these down further.
+//!
+//! --include <glob>
+//!   Find files matching the given glob (e.g. "src/**/*.c") in the repo
+//!   ourselves. Can be repeated. Combined with --ext, files must match both.
+//!
+//! --exclude <glob>
+//!   Skip found files matching the given glob (e.g. "vendor/**").
+//!   Can be repeated.
+//!
+//! --skip-vendored
+//!   Skip files that are probably someone else's code, found or given:
+//!   ones Git (or Mercurial) ignores, and ones under a third_party,
+//!   third-party, node_modules, or vendor directory, unless an --include glob
+//!   names them.
+//!
+//! --files-from <file|->
+//!   Also examine the paths listed in the given file (or stdin, for -),
+//!   one per line. Handy for file lists too long for the command line.
+//!   (Files whose names aren't UTF-8, given or found, are skipped
+//!   with a warning.)
+//!
+//! -0
+//!   Paths given with --files-from are separated by NUL characters instead of
+//!   newlines, like the output of `find -print0` or `git ls-files -z`.
+//!
+//! --strip
+//!   Remove existing copyright notices instead of updating them.
+//!
+//! --reuse
+//!   Instead of putting notices in files, write REUSE-style `<file>.license`
+//!   companions next to them, with SPDX-FileCopyrightText lines (and an
+//!   SPDX-License-Identifier line, with --spdx). For images, test fixtures,
+//!   and other files that can't hold a notice.
+//!
+//! --force
+//!   Update (or strip) files even if they look binary, i.e. they have a NUL
+//!   byte in their first 8 KB, or Git attributes say they're binary.
+//!   By default, they're skipped with a warning. (UTF-16 files, which start
+//!   with a byte order mark, are read as text, and written back in UTF-16.
+//!   Files that aren't UTF-8 otherwise are taken to be Latin-1.)
+//!
+//! --include-generated
+//!   Update (or strip) files even if they say they're generated, i.e. their
+//!   first --scan-lines lines say `@generated`, `DO NOT EDIT`, or
+//!   `Code generated by`. By default, they're skipped with a warning,
+//!   since their generator would just overwrite our notices.
+//!
+//! --dry-run (update only)
+//!   Don't modify anything. Instead, print a unified diff of the changes
+//!   we would make, which `git apply` can make later.
+//!
+//! --color <auto|always|never> (update only)
+//!   Whether to color --dry-run's diff (removed lines in red, added ones in
+//!   green, and so on). auto colors it if stdout is a terminal and $NO_COLOR
+//!   isn't set. (Default: auto)
+//!
+//! --emit-patch <file|-> (update only)
+//!   Like --dry-run, but write the diff to the given file (or stdout, for -),
+//!   e.g. to send the changes through code review as a single patch.
+//!
+//! --check (update only)
+//!   The same as the `check` subcommand.
+//!
+//! --interactive (update only)
+//!   Show the change we'd make to each file (like --dry-run) and ask
+//!   whether to make it (y), leave the file alone (n), make it and the rest
+//!   of them (a), or stop there (q), like `git add -p`.
+//!
+//! --report-json <path|->
+//!   Write each file's years and what we did to its notice (added, updated,
+//!   removed, current, skipped, or failed) as JSON to the given file,
+//!   or stdout for -.
+//!
+//! --out <plan.json> (analyze only)
+//!   Where to save the plan.
+//!
+//! --output-format <text|jsonl|sarif>
+//!   What to print on stdout, instead of the usual list of files
+//!   (or --dry-run's diff), for tools that would otherwise scrape our output.
+//!   jsonl prints a JSON object for each file, one per line, with its path,
+//!   action, old_years (from its notice), new_years, holder, and error.
+//!   sarif prints a SARIF log with a result for each file whose notice is
+//!   missing or out of date, e.g. for GitHub code scanning.
+//!   (Default: text)
+//!
+//! --quiet, -q
+//!   Don't show progress bars (they're only shown on a terminal anyway)
+//!   or warnings, like which files were skipped. Errors are still shown.
+//!
+//! --verbose, -v
+//!   Say more about what we're doing, like how many files we found.
+//!   Give it twice (-vv) to hear about each file: why it was skipped,
+//!   and which of its years came from history and which from its notice.
+//!   $COPYRIGHTER_LOG overrides these, as a list of env_logger-style
+//!   directives like `debug` or `copyrighter::history=trace`.
+//!
+//! -C <path>
+//!   Run as if copyrighter was started in the given directory.
+//!   We work from the top of the repo regardless, so files given from a
+//!   subdirectory are found relative to it, then reported relative to the top.
+//!
+//! --vcs <git|hg>
+//!   The kind of repo we're in. (Default: whichever of .git or .hg
+//!   is found first, going up from the current directory.)
+//!   Mercurial repos are read by running `hg`. --staged, --changed-since,
+//!   --by-blame, --ignore-whitespace, and --date-source committer
+//!   only work in Git repos.
+//!
+//! --years-from <file>
+//!   Don't look at history at all. Instead, take the years each file was
+//!   changed from the given JSON file, which maps paths (relative to the top
+//!   of the repo) to lists of years, e.g. {"src/main.rs": [2019, 2020]}.
+//!   For builds that already track when files change. The files it lists are
+//!   examined along with any others given or found, and the options below
+//!   that pick which history counts don't apply.
+//!
+//! --bump-current-year
+//!   Don't look at history at all. Instead, just make sure each file's notice
+//!   lists the current year, along with the years it already does. Notices
+//!   that list years as ranges keep doing so, so 2018-2024 becomes 2018-2025.
+//!   For the yearly bump in repos whose history doesn't tell us much (or takes
+//!   too long to read). Files without notices get ones listing just this year,
+//!   so pair it with --update-only to leave them alone.
 //!
 //! --ignore-commits, -i <commit1[,commit2,...]>
 //!   Ignore the listed commits when examining history.
 //!   Commits are looked up using git rev-parse
+//!
+//! --ignore-revs-file <path>
+//!   Also ignore the commits listed in the given file, one per line,
+//!   like Git's blame.ignoreRevsFile (e.g. .git-blame-ignore-revs).
+//!
+//! --ignore-authors <regex>
+//!   Ignore commits whose author, written as "Name <email>", matches the given
+//!   regular expression, e.g. "dependabot|<bot@example\.com>".
+//!   Authors are written as the repo's .mailmap says, if it has one, both here
+//!   and for --per-author, so a pattern for someone's canonical name
+//!   or address catches the commits they made under old ones.
+//!
+//! --ignore-message <regex>
+//!   Ignore commits whose subject (the first line of their message) matches
+//!   the given regular expression, e.g. "^chore\(deps\)" or "\[bot\]".
+//!   Can be repeated.
+//!
+//! --include-dirty
+//!   Count the current year for files with changes that haven't been
+//!   committed yet (staged or not), and for untracked files.
+//!
+//! --strict-history
+//!   Only count the current year for files that history (or --include-dirty)
+//!   says were changed this year. It's taken off the years of any others,
+//!   even if their notices already list it (e.g. after a blanket bump)
+//!   or --min-year would move their years up to it. With `check`, this
+//!   catches notices claiming years their files weren't changed in.
+//!
+//! --git-binary <path>
+//!   The Git executable to use, if not `git` from the PATH
+//!
+//! --git-backend <cli|libgit2>
+//!   How to read Git history: by running the `git` executable,
+//!   or with libgit2. (Default: cli)
+//!   Listing files to find (--ext, etc.) and --by-blame always run `git`.
+//!
+//! --per-file-history
+//!   Run `git log --follow` on each file instead of walking the whole history
+//!   once. Much slower on big repos, but follows renames exactly as Git does.
+//!
+//! --by-blame
+//!   Only count years of commits whose lines are still in the file
+//!   (via git blame), not every commit that ever touched it (via git log).
+//!   A year whose changes have all since been deleted won't be listed.
+//!
+//! --no-merges
+//!   Don't count merge commits (e.g. their conflict resolutions) as changes
+//!   to the files they touch.
+//!
+//! --ignore-whitespace
+//!   Don't count changes that only touch whitespace (like reformatting),
+//!   as in `git log -w` or `git blame -w`. (Slower, since Git has to diff
+//!   each change.) Only works in Git repos.
+//!
+//! --date-source <author|committer>
+//!   Which date of each commit to count: when the change was written (author),
+//!   or when it landed (committer). Rebases and cherry-picks update committer
+//!   dates, so they can add years nobody wrote anything in, but some
+//!   organizations go by when code landed. (Default: author)
+//!   Only works in Git repos.
+//!
+//! --no-cache
+//!   Don't use (or update) the cache of each file's years from earlier runs,
+//!   kept in .git/copyrighter-cache. Files whose history hasn't changed
+//!   since the last run are otherwise looked up there instead of in Git.
 //! ```
 //!
+//! Any of these (besides the ones that pick a mode, like --check)
+//! can also be set in a `.copyrighter.toml` at the top of the repo,
+//! e.g. `organization = "Fluke Corporation"`.
+//! Options given on the command line override it, and flags it turns on
+//! can be turned back off with `--no-<flag>` (or `--merges` and `--cache`,
+//! for `--no-merges` and `--no-cache`). Files it says to look for
+//! (with `ext`, `include`, or `changed-since`) are only looked for
+//! when none are given on the command line.
+//!
+//! The config file can also give different organizations for files matching
+//! certain globs (the most specific glob wins), like
+//!
+//! ```toml
+//! [organization-by-path]
+//! "legacy/**" = "OldCo Inc."
+//! ```
+//!
+//! If the organization was renamed, the names it held the copyright under
+//! before (or after) can be given the years they apply to, like
+//!
+//! ```toml
+//! [organization-years]
+//! "OldCo Inc." = "2012-2018"
+//! ```
+//!
+//! so that notices list each name's years, e.g.
+//! `Copyright © 2012-2018 OldCo Inc., 2019-2025 NewCo`.
+//! (Years that none of them cover go to the organization's current name.)
+//!
+//! Files matching the patterns in a `.copyrighterignore` at the top of the repo
+//! (written like a `.gitignore`) are never touched, however they were given,
+//! e.g. `*.pb.go` for generated code or `tests/golden/` for expected outputs.
+//!
 //!
 //! # Example
 //!
@@ -25,162 +483,2724 @@
 //!
 //! ```sh
 //! $ cd my_project
-//! $ find -type f \( -name '*.cpp' -or -name '*.h'\) \
-//!     -exec copyrighter --organization "Fluke Corporation. All rights reserved." {} +
+//! $ copyrighter --organization "Fluke Corporation. All rights reserved." --ext cpp,h
 //! ```
 
-mod common;
-mod existing;
-mod git;
-mod history;
-mod update;
-
-use std::borrow::Borrow;
 use std::collections::HashSet;
 use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Component, Path, PathBuf};
 use std::process::exit;
 use std::thread;
 
-use getopts::Options;
+use clap::builder::StyledStr;
+use clap::parser::ValueSource;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use clap_complete::Shell;
+use itertools::Itertools;
+use log::{debug, error, info, log_enabled, warn, Level, LevelFilter};
+use regex::Regex;
+
+use copyrighter::common::*;
+use copyrighter::error::Error;
+use copyrighter::git::*;
+use copyrighter::template::Template;
+use copyrighter::vcs::{self, Vcs};
+use copyrighter::{
+    authors, backup, comment, config, dep5, diff, discover, encoding, existing, external, history,
+    init, json_report, notice, notice_file, plan, progress, stats, update, watch,
+};
+
+/// The environment variable we read the organization from, if -o isn't given
+const ORGANIZATION_VAR: &str = "COPYRIGHTER_ORG";
+
+/// The Git (or Mercurial) config key we read the organization from as a last resort
+const ORGANIZATION_CONFIG_KEY: &str = "copyrighter.organization";
 
-use crate::common::*;
-use crate::git::*;
+/// What we print on stdout (see --output-format)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Jsonl,
+    Sarif,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Sarif => "sarif",
+        })
+    }
+}
+
+/// The environment variable that overrides how much we log (see init_logging())
+const LOG_VAR: &str = "COPYRIGHTER_LOG";
+
+/// Where `notice` writes the NOTICE file if --out isn't given
+const NOTICE_PATH: &str = "NOTICE";
+
+/// Where `dep5` writes the copyright file if --out isn't given
+const DEP5_PATH: &str = "debian/copyright";
 
-// Print our usage string and exit the program with the given code.
-// (This never returns.)
-fn print_usage(opts: &Options, code: i32) -> ! {
-    println!("{}", opts.usage("Usage: copyrighter [options] <file>"));
+/// What notice and dep5 can't be given, since they don't change any of the files
+const SUMMARY_CONFLICTS: &[&str] = &[
+    "count-only",
+    "strip",
+    "reuse",
+    "report-json",
+    "output-format",
+    "write-strategy",
+    "backup",
+    "concurrency-safe",
+    "preserve-mtime",
+    "allow-chmod",
+];
+
+/// What watch can't be given, since it finds files itself and only updates them
+const WATCH_CONFLICTS: &[&str] = &[
+    "paths",
+    "files-from",
+    "count-only",
+    "staged",
+    "changed-since",
+    "strip",
+    "reuse",
+    "report-json",
+    "output-format",
+    "backup",
+    "per-author",
+    // (Editors wouldn't notice we changed the file.)
+    "preserve-mtime",
+];
+
+/// Where we get the years files were changed
+enum YearSource {
+    /// History, not counting the given commits
+    History {
+        ignores: HashSet<SHA1>,
+        method: history::Method,
+        first_year: Year,
+    },
+
+    /// Someone else's records (--years-from)
+    Given(YearMap),
+
+    /// Just the calendar (--bump-current-year)
+    CurrentYear,
+}
+
+/// What the command we were given does to files, and how it reports that
+struct Job<'a> {
+    command: &'a str,
+    matches: &'a ArgMatches,
+    usage: &'a StyledStr,
+    mode: update::Mode,
+    /// What we do before asking about each change (see --interactive)
+    first_pass: update::Mode,
+    interactive: bool,
+    write_strategy: update::WriteStrategy,
+    files: update::FileOptions,
+    /// Where --emit-patch writes its patch
+    patch_dest: Option<String>,
+    color: bool,
+    output_format: OutputFormat,
+    show_progress: bool,
+}
+
+/// The path a filter was given, and the contents it read from stdin
+type FilterInput = (String, Vec<u8>);
+
+/// The files we're going to look at, and how we found them
+struct Selection {
+    paths: PathSet,
+    filter: discover::FileFilter,
+    skip_vendored: bool,
+    staged: bool,
+}
+
+/// How we work out the years files were changed
+struct Dating<'a> {
+    vcs: &'a dyn Vcs,
+    source: YearSource,
+    /// What a filter was given, in place of the file on disk
+    filter_contents: Option<Vec<u8>>,
+    include_dirty: bool,
+    strict_history: bool,
+    min_year: Option<Year>,
+    max_year: Option<Year>,
+    show_progress: bool,
+    /// Whether to hang on to the years notices listed to begin with
+    /// (for --output-format jsonl)
+    keep_notice_years: bool,
+}
+
+// Print our usage string (and where to find more) and exit the program
+// with the given code. (This never returns.)
+fn print_usage(usage: &StyledStr, code: i32) -> ! {
+    eprintln!("\n{}\n\nFor more information, try '--help'.", usage);
     exit(code);
 }
 
+// An option that's either given or not
+fn flag(name: &'static str, help: &'static str) -> Arg {
+    Arg::new(name)
+        .long(name)
+        .action(ArgAction::SetTrue)
+        .help(help)
+}
+
+// A flag the config file can turn on too, along with the one that turns it
+// back off for a run (e.g. --no-normalize, or --cache for --no-cache).
+// Whichever of them comes last wins. (See `switched_on()`.)
+fn switch(on: Arg, off: &'static str) -> [Arg; 2] {
+    let help = format!("Undo --{} (e.g. if the config file sets it)", on.get_id());
+    [on.overrides_with(off), flag(off, "").help(help)]
+}
+
+// An option that takes a value
+fn option(name: &'static str, value_name: &'static str, help: &'static str) -> Arg {
+    Arg::new(name).long(name).value_name(value_name).help(help)
+}
+
+// An option that takes a value, and can be given more than once
+fn repeated(name: &'static str, value_name: &'static str, help: &'static str) -> Arg {
+    option(name, value_name, help).action(ArgAction::Append)
+}
+
+// How we rewrite files, for everything that does
+fn write_args(cmd: Command) -> Command {
+    cmd.arg(option(
+        "write-strategy",
+        "in-place|atomic",
+        "How to rewrite files. in-place slides their contents around to make room \
+         for the new notice, which is fast but could mangle a file if we're killed \
+         partway through. atomic writes a new copy next to the file, then renames it \
+         over the original. (Default: in-place)",
+    ))
+    .arg(
+        option(
+            "backup",
+            "suffix",
+            "Copy each file to <file><suffix> (<file>.bak by default) before changing it, \
+             so that `copyrighter restore` can undo the run. \
+             If some other file is already there, the copy goes to <file><suffix>.1 instead.",
+        )
+        .num_args(0..=1)
+        .require_equals(true)
+        .default_missing_value(backup::DEFAULT_SUFFIX),
+    )
+    .arg(flag(
+        "concurrency-safe",
+        "Lock each file while rewriting it, in case something else \
+         (like another copyrighter run) is modifying it at the same time.",
+    ))
+    .args(switch(
+        flag(
+            "preserve-mtime",
+            "Put each file's modification time back after rewriting it, \
+         so incremental builds don't rebuild everything.",
+        ),
+        "no-preserve-mtime",
+    ))
+    .args(switch(
+        flag(
+            "allow-chmod",
+            "Make read-only files that need changes writable while rewriting them, \
+         then put their permissions back, instead of failing.",
+        ),
+        "no-allow-chmod",
+    ))
+    .arg(
+        option(
+            "jobs",
+            "N",
+            "Run at most N Git processes or file rewrites at once. (Default: one per CPU)",
+        )
+        .short('j')
+        .value_parser(value_parser!(usize)),
+    )
+}
+
+// Everything update, check, report, and analyze take
+fn notice_args(cmd: Command) -> Command {
+    write_args(
+        cmd.arg(
+            Arg::new("paths")
+                .value_name("FILE")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(OsString))
+                .help("Files to examine"),
+        ),
+    )
+    .arg(flag(
+        "count-only",
+        "Just count how many files have copyright notices and exit. \
+         (Doesn't look at Git history or modify anything.)",
+    ))
+    .arg(option(
+        "ext",
+        "ext1[,ext2,...]",
+        "Find files with the given extensions in the repo ourselves \
+         (skipping anything Git ignores), instead of only using the files given.",
+    ))
+    .arg(flag(
+        "staged",
+        "Only work on files with staged changes, then stage our changes \
+         to them too. Made for pre-commit hooks.",
+    ))
+    .arg(option(
+        "changed-since",
+        "ref",
+        "Only work on files changed between (the merge base of) the given ref \
+         and HEAD, e.g. \"origin/main\" for the files a branch touched.",
+    ))
+    .arg(repeated(
+        "include",
+        "glob",
+        "Find files matching the given glob (e.g. \"src/**/*.c\") in the repo \
+         ourselves. Can be repeated. Combined with --ext, files must match both.",
+    ))
+    .arg(repeated(
+        "exclude",
+        "glob",
+        "Skip found files matching the given glob (e.g. \"vendor/**\"). Can be repeated.",
+    ))
+    .args(switch(
+        flag(
+            "skip-vendored",
+            "Skip files (found or given) that Git ignores, or that are under a third_party, \
+         third-party, node_modules, or vendor directory, unless an --include glob names them.",
+        ),
+        "no-skip-vendored",
+    ))
+    .arg(option(
+        "files-from",
+        "file|-",
+        "Also examine the paths listed in the given file (or stdin, for -), one per line.",
+    ))
+    .arg(
+        Arg::new("null")
+            .short('0')
+            .action(ArgAction::SetTrue)
+            .requires("files-from")
+            .help(
+                "Paths given with --files-from are separated by NUL characters \
+                 instead of newlines.",
+            ),
+    )
+    .arg(flag(
+        "strip",
+        "Remove existing copyright notices instead of updating them.",
+    ))
+    .arg(flag(
+        "reuse",
+        "Write REUSE-style <file>.license companions instead of putting \
+         notices in files (e.g. for images).",
+    ))
+    .arg(flag(
+        "force",
+        "Update files even if they look binary, instead of skipping them.",
+    ))
+    .args(switch(
+        flag(
+            "include-generated",
+            "Update files even if they say they're generated (e.g. \"DO NOT EDIT\"), \
+         instead of skipping them.",
+        ),
+        "no-include-generated",
+    ))
+    .arg(option(
+        "report-json",
+        "path|-",
+        "Write each file's years and what we did to its notice \
+         as JSON to the given file, or stdout for -.",
+    ))
+    .arg(option(
+        "output-format",
+        "text|jsonl|sarif",
+        "What to print on stdout: the usual, a line of JSON for each file \
+         (with its path, action, old and new years, holder, and any error), \
+         or a SARIF log of missing and out-of-date notices. (Default: text)",
+    ))
+    .arg(
+        repeated(
+            "organization",
+            "org",
+            "The organization claiming the copyright, and any following text. \
+             Repeat to list multiple holders, each on its own line. \
+             Existing notices keep their holders; only their years are updated, \
+             and the text after our organizations (see --organization-suffix). \
+             Files whose notices name some other holder are skipped. \
+             Defaults to $COPYRIGHTER_ORG or `git config copyrighter.organization`.",
+        )
+        .short('o'),
+    )
+    .args(switch(
+        flag(
+            "force-org",
+            "Replace the holders of existing notices with the given organization(s) \
+         (and suffix), instead of keeping them.",
+        ),
+        "no-force-org",
+    ))
+    .arg(repeated(
+        "allow-holder",
+        "name",
+        "Update the years of notices naming the given holder too, \
+         instead of skipping those files as someone else's. \
+         On notices that name one of ours too, their lines keep their years.",
+    ))
+    .arg(option(
+        "organization-suffix",
+        "text",
+        "Text appended after the organization, e.g. \"All rights reserved.\" \
+         Here and in --organization, {now_year} becomes the current year.",
+    ))
+    .args(switch(
+        flag(
+            "sort-holders",
+            "Sort multiple holders alphabetically instead of keeping the order given.",
+        ),
+        "no-sort-holders",
+    ))
+    .arg(option(
+        "per-author",
+        "file",
+        "Give each author's employer (from the given .mailmap-like file of \
+         `Employer <address>...` lines) a notice line with the years their authors \
+         changed the file, instead of giving it to the organization.",
+    ))
+    .arg(option(
+        "year-separator",
+        "sep",
+        "What to put between listed years, e.g. \", \" (Default: \",\")",
+    ))
+    .arg(
+        option(
+            "gap-tolerance",
+            "years",
+            "List years as ranges, taking gaps of up to the given number of years \
+             into them (e.g. with 1, 2010,2011,2013 becomes 2010-2013)",
+        )
+        .value_parser(value_parser!(u16)),
+    )
+    .arg(option(
+        "min-year",
+        "year",
+        "Never list years before the given one. Earlier years are replaced with it.",
+    ))
+    .arg(option(
+        "max-year",
+        "year|now",
+        "Never list years after the given one (or the current year, for now). \
+         Later years are replaced with it.",
+    ))
+    .arg(option(
+        "symbol",
+        "©|(c)|(C)|none",
+        "The copyright symbol notices use. (Default: ©)",
+    ))
+    .arg(option(
+        "format",
+        "full|symbol-only",
+        "Whether notices say \"Copyright\" before the symbol (full), \
+         or just have the symbol. (Default: full)",
+    ))
+    .args(switch(
+        flag(
+            "normalize",
+            "Rewrite notices in our format even if they already list \
+         the right years and holders.",
+        ),
+        "no-normalize",
+    ))
+    .arg(option(
+        "spdx",
+        "license-id",
+        "Add an SPDX-License-Identifier line with the given license after the \
+         notice, or update the existing one.",
+    ))
+    .arg(option(
+        "template",
+        "file",
+        "Stamp the given multi-line template (e.g. license boilerplate) instead \
+         of single notice lines. {years}, {organization}, {filename}, {spdx}, \
+         and {now_year} are filled in.",
+    ))
+    .arg(option(
+        "default-comment-style",
+        "//|/*|#|--|;|<!--|{#|<%#",
+        "The comment style for new notices in files whose style can't be \
+         guessed from their extension, shebang, or contents. (Default: //)",
+    ))
+    .arg(repeated(
+        "comment-style",
+        "ext>=<style",
+        "Use the given comment style for new notices in files with the given \
+         extension, e.g. \"tpl={#\". Can be repeated.",
+    ))
+    .arg(option(
+        "placement",
+        "top|after-shebang|after-comment",
+        "Where new notices go: on the very first line, after any shebang, \
+         or after the comment the file starts with. (Default: after-shebang)",
+    ))
+    .args(switch(
+        flag(
+            "add-missing-only",
+            "Only add notices to files without one, leaving existing notices be.",
+        ),
+        "no-add-missing-only",
+    ))
+    .args(switch(
+        flag(
+            "update-only",
+            "Only update existing notices, never adding new ones.",
+        )
+        .conflicts_with("add-missing-only"),
+        "no-update-only",
+    ))
+    .arg(
+        option(
+            "scan-lines",
+            "N",
+            "How many lines at the top of each file to look through for an existing \
+             notice, past banners, blank lines, and other comments. (Default: 10)",
+        )
+        .value_parser(value_parser!(usize)),
+    )
+    .arg(
+        option(
+            "max-line-length",
+            "bytes",
+            "How long a line can be before we stop looking through it (and any after \
+             it) for a notice, so that we don't read all of a file that's one huge \
+             line, like minified code. Files that start with such a line are treated \
+             as not having a notice. (Default: 65536)",
+        )
+        .value_parser(value_parser!(usize)),
+    )
+    .arg(option(
+        "years-from",
+        "file",
+        "Don't look at history. Instead, take the years each file was changed from \
+         the given JSON file, like {\"src/main.rs\": [2019, 2020]}.",
+    ))
+    .arg(
+        flag(
+            "bump-current-year",
+            "Don't look at history. Instead, just add the current year to each file's \
+             notice, extending ranges like 2018-2024 to it.",
+        )
+        .conflicts_with("years-from"),
+    )
+    .arg(
+        option(
+            "ignore-commits",
+            "commit1[,commit2,...]",
+            "Ignore the listed commits when examining history",
+        )
+        .short('i'),
+    )
+    .arg(option(
+        "ignore-revs-file",
+        "path",
+        "Also ignore the commits listed in the given file, one per line, \
+         like Git's blame.ignoreRevsFile (e.g. .git-blame-ignore-revs).",
+    ))
+    .arg(option(
+        "ignore-authors",
+        "regex",
+        "Ignore commits whose author, written as \"Name <email>\" (after any .mailmap), \
+         matches the given regular expression, e.g. \"dependabot|<bot@example\\.com>\".",
+    ))
+    .arg(repeated(
+        "ignore-message",
+        "regex",
+        "Ignore commits whose subject (the first line of their message) matches \
+         the given regular expression, e.g. \"^chore\\(deps\\)\". Can be repeated.",
+    ))
+    .args(switch(
+        flag(
+            "include-dirty",
+            "Count the current year for files with changes that haven't been \
+         committed yet (staged or not), and for untracked files.",
+        ),
+        "no-include-dirty",
+    ))
+    .args(switch(
+        flag(
+            "strict-history",
+            "Only count the current year for files that history (or --include-dirty) \
+             says were changed this year, even if their notices list it.",
+        )
+        .conflicts_with("bump-current-year"),
+        "no-strict-history",
+    ))
+    .arg(option(
+        "git-binary",
+        "path",
+        "The Git executable to use, if not `git` from the PATH",
+    ))
+    .arg(option(
+        "git-backend",
+        "cli|libgit2",
+        "How to read Git history: by running the `git` executable, \
+         or with libgit2. (Default: cli)",
+    ))
+    .arg(flag(
+        "per-file-history",
+        "Run `git log --follow` on each file instead of walking the whole history \
+         once. Much slower on big repos, but follows renames exactly as Git does.",
+    ))
+    .arg(flag(
+        "by-blame",
+        "Only count years of commits whose lines are still in the file \
+         (via git blame), not every commit that ever touched it (via git log).",
+    ))
+    .args(switch(
+        flag(
+            "no-merges",
+            "Don't count merge commits, like conflict resolutions, as changes.",
+        ),
+        "merges",
+    ))
+    .args(switch(
+        flag(
+            "ignore-whitespace",
+            "Don't count changes that only touch whitespace, like reformatting, \
+         as in `git log -w`.",
+        ),
+        "no-ignore-whitespace",
+    ))
+    .arg(option(
+        "date-source",
+        "author|committer",
+        "Which date of each commit to count: when the change was written, \
+         or when it landed. (Default: author)",
+    ))
+    .args(switch(
+        flag(
+            "no-cache",
+            "Don't use (or update) the cache of each file's years from earlier runs, \
+         kept in .git/copyrighter-cache.",
+        ),
+        "cache",
+    ))
+}
+
+fn cli() -> Command {
+    Command::new("copyrighter")
+        .about("Updates copyright notices using Git (or Mercurial) history")
+        .subcommand_required(true)
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help_heading("Global options")
+                .help("Don't show progress bars or warnings."),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .action(ArgAction::Count)
+                .conflicts_with("quiet")
+                .help_heading("Global options")
+                .help("Say more about what we're doing. (-vv for each file)"),
+        )
+        .arg(
+            Arg::new("directory")
+                .short('C')
+                .value_name("path")
+                .global(true)
+                .help_heading("Global options")
+                .help("Run as if copyrighter was started in the given directory."),
+        )
+        .arg(
+            Arg::new("vcs")
+                .long("vcs")
+                .value_name("git|hg")
+                .value_parser(["git", "hg"])
+                .global(true)
+                .help_heading("Global options")
+                .help(
+                    "The kind of repo we're in. \
+                     (Default: whichever of .git or .hg is found first)",
+                ),
+        )
+        .subcommand(
+            notice_args(Command::new("update"))
+                .about("Update (or add) copyright notices. (The default)")
+                .arg(flag(
+                    "dry-run",
+                    "Don't modify anything. Instead, print a unified diff of the changes \
+                     we would make, which `git apply` can make later.",
+                ))
+                .arg(option(
+                    "color",
+                    "auto|always|never",
+                    "Whether to color --dry-run's diff. auto colors it if stdout \
+                     is a terminal. (Default: auto)",
+                ))
+                .arg(
+                    option(
+                        "emit-patch",
+                        "file|-",
+                        "Don't modify anything. Instead, write a unified diff of the changes \
+                         we would make to the given file (or stdout, for -).",
+                    )
+                    .conflicts_with_all(["dry-run", "color"]),
+                )
+                .arg(
+                    flag("check", "Same as the check subcommand")
+                        .conflicts_with_all(["dry-run", "emit-patch"]),
+                )
+                .arg(
+                    flag(
+                        "interactive",
+                        "Show the change we'd make to each file, and ask whether to make it \
+                         (y/n), make it and the rest (a), or stop (q).",
+                    )
+                    .conflicts_with_all([
+                        "dry-run",
+                        "emit-patch",
+                        "check",
+                        "reuse",
+                    ]),
+                ),
+        )
+        .subcommand(
+            notice_args(Command::new("check"))
+                .about("List files whose notices are out of date, without changing anything")
+                .long_about(
+                    "Don't modify anything. Instead, list files whose notices are out of date \
+                     and exit with 0 if everything is current, 1 if some files need updates, \
+                     or 2 if some files couldn't be checked.",
+                ),
+        )
+        .subcommand(
+            notice_args(Command::new("report"))
+                .about(
+                    "List each file's years and what we'd do to its notice, \
+                     without changing anything",
+                )
+                .arg(
+                    flag(
+                        "stats",
+                        "Instead of listing each file, sum them up: how many were first \
+                         changed in each year, how many are missing notices or have \
+                         out-of-date ones, who their notices name, and the years they span",
+                    )
+                    .conflicts_with("output-format"),
+                ),
+        )
+        .subcommand(
+            notice_args(Command::new("analyze"))
+                .about("Work out the changes update would make, and save them to a plan")
+                .arg(
+                    option("out", "plan.json", "Where to save the plan")
+                        .required(true)
+                        .conflicts_with("reuse"),
+                ),
+        )
+        .subcommand(
+            write_args(Command::new("apply"))
+                .about("Make the changes in a plan saved by analyze")
+                .arg(
+                    Arg::new("plan")
+                        .value_name("plan.json")
+                        .required(true)
+                        .help("The plan to apply"),
+                ),
+        )
+        .subcommand(
+            notice_args(Command::new("filter"))
+                .about("Update (or add) the notice in a file's contents from stdin, and print them")
+                .arg(
+                    option(
+                        "path",
+                        "file",
+                        "The file the contents are from, which picks their history, \
+                         comment style, and so on",
+                    )
+                    .required(true)
+                    .conflicts_with_all([
+                        "paths",
+                        "files-from",
+                        "count-only",
+                        "ext",
+                        "staged",
+                        "changed-since",
+                        "include",
+                        "exclude",
+                        "strip",
+                        "reuse",
+                        "report-json",
+                        "output-format",
+                        "write-strategy",
+                        "backup",
+                        "concurrency-safe",
+                        "preserve-mtime",
+                        "allow-chmod",
+                    ]),
+                ),
+        )
+        .subcommand(
+            notice_args(Command::new("watch"))
+                .about("Update (or add) the notices of files as they're saved, until killed"),
+        )
+        .subcommand(
+            notice_args(Command::new("notice"))
+                .about("Gather every holder (and their years) into a NOTICE file")
+                .arg(option(
+                    "out",
+                    "file|-",
+                    "Where to write it (or stdout, for -). (Default: NOTICE)",
+                ))
+                .arg(option(
+                    "title",
+                    "text",
+                    "A line to start it with, e.g. the product's name",
+                )),
+        )
+        .subcommand(
+            notice_args(Command::new("dep5"))
+                .about("Write a machine-readable debian/copyright (DEP-5) file")
+                .arg(option(
+                    "out",
+                    "file|-",
+                    "Where to write it (or stdout, for -). (Default: debian/copyright)",
+                ))
+                .arg(option(
+                    "upstream-name",
+                    "name",
+                    "The name of the upstream project, for its Upstream-Name field",
+                ))
+                .arg(option(
+                    "source",
+                    "url",
+                    "Where to get the upstream source, for its Source field",
+                )),
+        )
+        .subcommand(Command::new("restore").about("Undo the last run made with --backup"))
+        .subcommand(
+            Command::new("init")
+                .about("Look the repo over and write a starter .copyrighter.toml at the top of it")
+                .arg(
+                    repeated(
+                        "organization",
+                        "org",
+                        "The organization(s) to put in it. \
+                         Defaults to $COPYRIGHTER_ORG, `git config copyrighter.organization`, \
+                         or whoever existing notices name most.",
+                    )
+                    .short('o'),
+                )
+                .arg(flag(
+                    "hook",
+                    "Also install a pre-commit hook that runs `copyrighter update --staged`",
+                ))
+                .arg(flag("yes", "Don't ask any questions; go with what we found").short('y'))
+                .arg(flag("force", "Replace an existing config file (and hook)")),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a completion script for the given shell")
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(value_parser!(Shell)),
+                ),
+        )
+}
+
+// Older scripts (and fingers) run copyrighter without a subcommand,
+// which means update.
+fn with_default_subcommand(mut args: Vec<OsString>, cli: &Command) -> Vec<OsString> {
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].to_str() {
+            // Skip past global options.
+            Some("-C") => i += 2,
+            Some(a)
+                if a.starts_with("-C")
+                    || a == "-q"
+                    || a == "--quiet"
+                    || a == "--verbose"
+                    || (a.len() > 1 && a.starts_with('-') && a[1..].chars().all(|c| c == 'v')) =>
+            {
+                i += 1
+            }
+            Some(a)
+                if a == "-h"
+                    || a == "--help"
+                    || a == "help"
+                    || cli.find_subcommand(a).is_some() =>
+            {
+                return args;
+            }
+            _ => break,
+        }
+    }
+    args.insert(1, OsString::from("update"));
+    args
+}
+
+// Logs warnings and errors to stderr, plus more with -v (or -vv, etc.),
+// or just errors with -q. $COPYRIGHTER_LOG overrides these.
+fn init_logging(quiet: bool, verbosity: u8) {
+    let level = match (quiet, verbosity) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_env(env_logger::Env::new().filter(LOG_VAR))
+        .format(|buf, record| match record.level() {
+            Level::Error => writeln!(buf, "Error: {}", record.args()),
+            Level::Warn => writeln!(buf, "Warning: {}", record.args()),
+            Level::Info => writeln!(buf, "{}", record.args()),
+            Level::Debug | Level::Trace => writeln!(
+                buf,
+                "{} ({}): {}",
+                record.level().as_str().to_lowercase(),
+                record.target(),
+                record.args()
+            ),
+        })
+        .init();
+}
+
+// The value given for an option, if any
+fn string(matches: &ArgMatches, id: &str) -> Option<String> {
+    matches.get_one::<String>(id).cloned()
+}
+
+// All the values given for a (repeatable) option
+fn strings(matches: &ArgMatches, id: &str) -> Vec<String> {
+    matches
+        .get_many::<String>(id)
+        .map_or_else(Vec::new, |v| v.cloned().collect())
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    // Args parsing via getopts
-    let mut opts = Options::new();
-    opts.optflag("h", "help", "Print this help text.");
-    opts.optopt(
-        "o",
-        "organization",
-        "The organization claiming the copyright, and any following text",
-        "<org>",
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        exit(ERROR_EXIT_CODE);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let mut cli = cli();
+    let args = with_default_subcommand(env::args_os().collect(), &cli);
+    let top_matches = match cli.try_get_matches_from_mut(args) {
+        Ok(m) => m,
+        Err(e) => {
+            // --help goes to stdout and exits 0; mistakes exit 1.
+            let _ = e.print();
+            exit(if e.use_stderr() { 1 } else { 0 });
+        }
+    };
+    let (command, matches) = top_matches
+        .subcommand()
+        .expect("clap should require a subcommand");
+
+    init_logging(matches.get_flag("quiet"), matches.get_count("verbose"));
+
+    if command == "completions" {
+        let shell = *matches.get_one::<Shell>("shell").unwrap();
+        clap_complete::generate(shell, &mut cli, "copyrighter", &mut io::stdout());
+        return Ok(());
+    }
+    let usage = cli.find_subcommand_mut(command).unwrap().render_usage();
+
+    if let Some(dir) = string(matches, "directory") {
+        env::set_current_dir(&dir).map_err(Error::io(format!("change to {}", dir)))?;
+    }
+
+    // Work from the top of the repo, so that paths mean the same thing
+    // to us, to Git, and to the config file there. Paths we're given
+    // from further down need to be adjusted to match.
+    // (If we're not in a repo, we'll complain once we ask Git anything.)
+    let cwd = env::current_dir().map_err(Error::io("get the current directory"))?;
+    let kind = match string(matches, "vcs").as_deref() {
+        Some("hg") => Some(vcs::Kind::Mercurial),
+        Some(_) => Some(vcs::Kind::Git),
+        None => None,
+    };
+    let (kind, top) = match vcs::find_repo_top(&cwd, kind) {
+        Some((k, t)) => (k, Some(t.to_owned())),
+        None => (kind.unwrap_or(vcs::Kind::Git), None),
+    };
+    if let Some(top) = &top {
+        env::set_current_dir(top).map_err(Error::io("change to the top of the repo"))?;
+    }
+
+    // (Don't trip over a config file we're being asked to replace.)
+    match command {
+        "init" => return init(kind, matches),
+        "restore" => return restore(),
+        _ => {}
+    }
+
+    // Options on the command line override ones in the config file.
+    let config = config::load()?;
+
+    // (Filters and watches only handle a few files at a time,
+    // and shouldn't clutter up editors or terminals.)
+    let show_progress = progress::should_show(matches.get_flag("quiet"))
+        && command != "filter"
+        && command != "watch";
+    let files = update::FileOptions {
+        lock: matches.get_flag("concurrency-safe"),
+        preserve_mtimes: switched_on(
+            matches,
+            "preserve-mtime",
+            "no-preserve-mtime",
+            config.preserve_mtime,
+        ),
+        allow_chmod: switched_on(matches, "allow-chmod", "no-allow-chmod", config.allow_chmod),
+        ..Default::default()
+    };
+
+    // Everything we do in parallel goes through Rayon's global pool,
+    // so its size bounds how many Git processes and rewrites we run at once.
+    if let Some(jobs) = matches.get_one::<usize>("jobs").copied().or(config.jobs) {
+        if jobs == 0 {
+            eprintln!("--jobs must be at least 1");
+            print_usage(&usage, 1);
+        }
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .expect("Rayon's thread pool was already started");
+    }
+
+    let write_strategy = match string(matches, "write-strategy")
+        .or(config.write_strategy.clone())
+        .as_deref()
+    {
+        None | Some("in-place") => update::WriteStrategy::InPlace,
+        Some("atomic") => update::WriteStrategy::Atomic,
+        Some(s) => {
+            eprintln!("Unknown write strategy {:?}", s);
+            print_usage(&usage, 1);
+        }
+    };
+
+    // Plans don't need anything else.
+    if command == "apply" {
+        let backup = string(matches, "backup").or(config.backup);
+        return apply(
+            matches,
+            backup,
+            write_strategy,
+            files,
+            show_progress,
+            &usage,
+        );
+    }
+
+    let mut job = Job::new(
+        command,
+        matches,
+        &usage,
+        &config,
+        write_strategy,
+        files,
+        show_progress,
+    );
+    let git = git_options(matches, &config, &usage);
+    let scan = scan_options(matches, &config, &usage);
+    let (paths, filter_input) = given_paths(&job, &cwd, top.as_deref())?;
+
+    // Someone else might have already worked out the files' years.
+    // (With --bump-current-year, we don't care when they were,
+    // whatever the config file says.)
+    let bump = matches.get_flag("bump-current-year");
+    let external_years = match string(matches, "years-from") {
+        Some(source) => Some(cwd.join(source).to_string_lossy().into_owned()),
+        None => config.years_from.clone(),
+    }
+    .filter(|_| !bump)
+    .map(|source| external::read_year_map(&source))
+    .transpose()?;
+
+    // Who works where, if files' holders go by who wrote them
+    let employers = match string(matches, "per-author") {
+        Some(file) => Some(cwd.join(file).to_string_lossy().into_owned()),
+        None => config.per_author.clone(),
+    }
+    .map(|file| authors::read_employers(&file))
+    .transpose()?;
+    if employers.is_some() && external_years.is_some() {
+        eprintln!("--per-author goes by history, so it can't be used with --years-from");
+        print_usage(&usage, 1);
+    }
+    if employers.is_some() && bump {
+        eprintln!("--per-author goes by history, so it can't be used with --bump-current-year");
+        print_usage(&usage, 1);
+    }
+
+    let vcs = vcs::open(kind, git.clone());
+    let vcs = vcs.as_ref();
+    let listed = match (&external_years, &filter_input) {
+        (Some(years), None) => years.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+    let selection = select_files(&job, &config, vcs, &git, paths, listed)?;
+    let paths = selection.paths.clone();
+
+    // Contents of files we leave alone pass through a filter untouched.
+    if let (Some((_, contents)), true) = (&filter_input, paths.is_empty()) {
+        return io::stdout()
+            .write_all(contents)
+            .map_err(Error::io("write stdout"));
+    }
+    info!("Examining {} files", paths.len());
+
+    // Neither counting nor stripping notices needs any history or organization.
+    if matches.get_flag("count-only") {
+        return count_notices(paths, &scan);
+    }
+
+    // Companions are meant for binary files, but nothing else is.
+    if !matches.get_flag("force") && !matches.get_flag("reuse") {
+        // (Without history, there might not be a repo to ask.)
+        let known = if external_years.is_some() || bump {
+            Default::default()
+        } else {
+            vcs.binary_files(&paths)?
+        };
+        job.files.skip_binary = Some(known);
+    }
+    job.files.skip_generated = !switched_on(
+        matches,
+        "include-generated",
+        "no-include-generated",
+        config.include_generated,
     );
-    opts.optopt(
-        "i",
-        "ignore-commits",
-        "Ignore the listed commits when examining history",
-        "<commit1[,commit2,...]>",
+
+    if matches.get_flag("strip") {
+        return strip(&job, &paths, &scan);
+    }
+
+    // Holders are emitted in a deterministic order (the order given, unless
+    // we're asked to sort them) so reruns don't shuffle notice lines around.
+    let sort_holders = switched_on(
+        matches,
+        "sort-holders",
+        "no-sort-holders",
+        config.sort_holders,
     );
+    let mut notice_options = notice_options(&job, &config, vcs, scan, sort_holders, bump)?;
+    let (min_year, max_year) = year_bounds(matches, &config, &usage);
 
-    let matches = match opts.parse(&args[1..]) {
-        Ok(m) => m,
+    // With --years-from, someone else already worked out when each file
+    // was changed, so history (and the repo) can be left out of it.
+    // With --bump-current-year, we don't care when they were.
+    let source = match external_years {
+        Some(years) => YearSource::Given(years),
+        None if bump => YearSource::CurrentYear,
+        None => history_source(matches, &config, &usage, vcs)?,
+    };
+    let dating = Dating {
+        vcs,
+        source,
+        filter_contents: filter_input.as_ref().map(|(_, c)| c.clone()),
+        // (Files being saved as we watch are being changed right now.)
+        include_dirty: selection.staged
+            || command == "watch"
+            || switched_on(
+                matches,
+                "include-dirty",
+                "no-include-dirty",
+                config.include_dirty,
+            ),
+        // (--bump-current-year wins over a config file asking for this.)
+        strict_history: switched_on(
+            matches,
+            "strict-history",
+            "no-strict-history",
+            config.strict_history,
+        ) && !bump,
+        min_year,
+        max_year,
+        show_progress,
+        keep_notice_years: job.output_format == OutputFormat::Jsonl,
+    };
+
+    if command == "watch" {
+        return watch(&job, &selection, &dating, &notice_options);
+    }
+
+    let (old_years, all_years) = dating.find_years(paths, &notice_options.scan)?;
+
+    // Give each author's employer their own notice lines, if asked.
+    if let (Some(employers), YearSource::History { ignores, .. }) = (&employers, &dating.source) {
+        let authors =
+            vcs.file_authors(all_years.keys().cloned().collect(), ignores, show_progress)?;
+        notice_options.authorship = authors::attribute(authors, employers, sort_holders);
+        for authorship in notice_options.authorship.values_mut() {
+            for years in &mut authorship.years {
+                clamp_year_list(years, min_year, max_year);
+            }
+        }
+    }
+
+    match (command, filter_input) {
+        ("notice", _) => write_notice_file(matches, &all_years, &notice_options),
+        ("dep5", _) => write_dep5(matches, &all_years, &notice_options),
+        (_, Some((path, contents))) => filter(&job, &path, contents, &all_years, &notice_options),
+        _ => {
+            let staged = selection.staged.then_some(&git);
+            update(&job, staged, &old_years, &all_years, &notice_options)
+        }
+    }
+}
+
+// Undoes the last run made with --backup, saying what it put back.
+fn restore() -> Result<(), Error> {
+    for done in backup::restore()? {
+        println!("{}", done);
+    }
+    Ok(())
+}
+
+impl<'a> Job<'a> {
+    // Works out what the given command does to files, and how it reports that.
+    fn new(
+        command: &'a str,
+        matches: &'a ArgMatches,
+        usage: &'a StyledStr,
+        config: &config::Config,
+        write_strategy: update::WriteStrategy,
+        mut files: update::FileOptions,
+        show_progress: bool,
+    ) -> Self {
+        // Patches are just dry runs written somewhere else.
+        let patch_dest = match command {
+            "update" => string(matches, "emit-patch"),
+            _ => None,
+        };
+
+        // Patches meant for `git apply` can't have escape codes in them.
+        let color = match command {
+            "update" if patch_dest.is_none() => string(matches, "color").or(config.color.clone()),
+            _ => None,
+        };
+        let color = match color.as_deref() {
+            None | Some("auto") => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            Some("always") => true,
+            Some("never") => false,
+            Some(c) => {
+                eprintln!("Unknown color setting {:?}", c);
+                print_usage(usage, 1);
+            }
+        };
+
+        let output_format = match string(matches, "output-format")
+            .or(config.output_format.clone())
+            .as_deref()
+        {
+            // (A filter's stdout is the file's contents, and watches never finish.)
+            _ if command == "filter" || command == "watch" => OutputFormat::Text,
+            None | Some("text") => OutputFormat::Text,
+            Some("jsonl") => OutputFormat::Jsonl,
+            Some("sarif") => OutputFormat::Sarif,
+            Some(f) => {
+                eprintln!("Unknown output format {:?}", f);
+                print_usage(usage, 1);
+            }
+        };
+        let interactive = command == "update" && matches.get_flag("interactive");
+        let to_stdout = |dest: Option<String>| dest.as_deref() == Some("-");
+        if output_format != OutputFormat::Text
+            && (interactive
+                || to_stdout(patch_dest.clone())
+                || to_stdout(string(matches, "report-json")))
+        {
+            eprintln!("--output-format {} needs stdout to itself", output_format);
+            print_usage(usage, 1);
+        }
+
+        // (clap won't let --dry-run, --emit-patch, and --check be used together.)
+        let mode = match command {
+            "check" | "report" | "notice" | "dep5" => update::Mode::Check,
+            "analyze" => update::Mode::Plan,
+            // (Filters don't touch files either; they print what they'd be.)
+            "filter" => update::Mode::DryRun,
+            "watch" => update::Mode::Write(write_strategy),
+            _ if matches.get_flag("dry-run") || patch_dest.is_some() => update::Mode::DryRun,
+            _ if matches.get_flag("check") => update::Mode::Check,
+            _ => update::Mode::Write(write_strategy),
+        };
+        // --interactive works out its changes first, then makes the ones it's told to.
+        let first_pass = if interactive {
+            update::Mode::Plan
+        } else {
+            mode
+        };
+
+        // (There's nothing to back up if we aren't writing anything.)
+        if let Some(suffix) = string(matches, "backup").or(config.backup.clone()) {
+            if suffix.is_empty() {
+                eprintln!("--backup needs a suffix, so backups don't overwrite their files");
+                print_usage(usage, 1);
+            }
+            if matches!(mode, update::Mode::Write(_)) {
+                files.backups = Some(backup::Backups::new(suffix));
+            }
+        }
+
+        Job {
+            command,
+            matches,
+            usage,
+            mode,
+            first_pass,
+            interactive,
+            write_strategy,
+            files,
+            patch_dest,
+            color,
+            output_format,
+            show_progress,
+        }
+    }
+
+    fn machine_output(&self) -> bool {
+        self.output_format != OutputFormat::Text
+    }
+}
+
+// Works out how to talk to Git, and which of its changes and dates count.
+fn git_options(matches: &ArgMatches, config: &config::Config, usage: &StyledStr) -> GitOptions {
+    let mut git = GitOptions {
+        binary: string(matches, "git-binary"),
+        ignore_whitespace: switched_on(
+            matches,
+            "ignore-whitespace",
+            "no-ignore-whitespace",
+            config.ignore_whitespace,
+        ),
+        cache: !switched_on(matches, "no-cache", "cache", config.no_cache),
+        ..Default::default()
+    };
+    match string(matches, "git-backend")
+        .or(config.git_backend.clone())
+        .as_deref()
+    {
+        None | Some("cli") => {}
+        #[cfg(feature = "libgit2")]
+        Some("libgit2") => git.backend = Backend::Libgit2,
+        #[cfg(not(feature = "libgit2"))]
+        Some("libgit2") => {
+            eprintln!("This copyrighter was built without libgit2 support.");
+            exit(ERROR_EXIT_CODE);
+        }
+        Some(b) => {
+            eprintln!("Unknown Git backend {:?}", b);
+            print_usage(usage, 1);
+        }
+    }
+    git.date_source = match string(matches, "date-source")
+        .or(config.date_source.clone())
+        .as_deref()
+    {
+        None | Some("author") => DateSource::Author,
+        Some("committer") => DateSource::Committer,
+        Some(s) => {
+            eprintln!("Unknown date source {:?}", s);
+            print_usage(usage, 1);
+        }
+    };
+    git
+}
+
+// Works out where notices are, what they start with, and the comment style
+// new ones go in.
+fn scan_options(
+    matches: &ArgMatches,
+    config: &config::Config,
+    usage: &StyledStr,
+) -> notice::ScanOptions {
+    let lines = matches
+        .get_one::<usize>("scan-lines")
+        .copied()
+        .or(config.scan_lines)
+        .unwrap_or(notice::DEFAULT_SCAN_LINES);
+    let max_line_length = matches
+        .get_one::<usize>("max-line-length")
+        .copied()
+        .or(config.max_line_length)
+        .unwrap_or(notice::DEFAULT_MAX_LINE_LENGTH);
+
+    // Some codebases have to stay pure ASCII, or just prefer (c).
+    let symbol = match string(matches, "symbol")
+        .or(config.symbol.clone())
+        .as_deref()
+    {
+        None | Some("©") => Some("©"),
+        Some("(c)") => Some("(c)"),
+        Some("(C)") => Some("(C)"),
+        Some("none") => None,
+        Some(s) => {
+            eprintln!("Unknown copyright symbol {:?}", s);
+            print_usage(usage, 1);
+        }
+    };
+    let lead = match (
+        string(matches, "format")
+            .or(config.format.clone())
+            .as_deref(),
+        symbol,
+    ) {
+        (None, Some(s)) | (Some("full"), Some(s)) => format!("Copyright {}", s),
+        (None, None) | (Some("full"), None) => "Copyright".to_owned(),
+        (Some("symbol-only"), Some(s)) => s.to_owned(),
+        (Some("symbol-only"), None) => {
+            eprintln!("--format symbol-only needs a --symbol besides none");
+            print_usage(usage, 1);
+        }
+        (Some(f), _) => {
+            eprintln!("Unknown notice format {:?}", f);
+            print_usage(usage, 1);
+        }
+    };
+
+    let default_style =
+        match string(matches, "default-comment-style").or(config.default_comment_style.clone()) {
+            None => &comment::DOUBLE_SLASH,
+            Some(s) => match comment::from_opener(&s) {
+                Some(style) => style,
+                None => {
+                    eprintln!("Unknown comment style {:?}", s);
+                    print_usage(usage, 1);
+                }
+            },
+        };
+
+    // Mappings from the command line come last so they replace
+    // any from the config file for the same extension.
+    let command_line_mappings = strings(matches, "comment-style")
+        .into_iter()
+        .map(|mapping| match mapping.split_once('=') {
+            Some((ext, opener)) => (ext.to_owned(), opener.to_owned()),
+            None => {
+                eprintln!("Invalid comment style mapping {:?}", mapping);
+                print_usage(usage, 1);
+            }
+        });
+
+    let mut styles = comment::StyleRegistry::new(default_style);
+    for (ext, opener) in config
+        .comment_style
+        .clone()
+        .into_iter()
+        .chain(command_line_mappings)
+    {
+        match comment::from_opener(&opener) {
+            Some(style) => styles.add_override(ext.trim_start_matches('.'), style),
+            None => {
+                eprintln!("Unknown comment style {:?} for .{} files", opener, ext);
+                print_usage(usage, 1);
+            }
+        }
+    }
+
+    notice::ScanOptions {
+        styles,
+        lead,
+        lines,
+        max_line_length,
+    }
+}
+
+// The paths given on the command line (and with --files-from), from the top
+// of the repo, and with a filter, the path and contents it was given.
+fn given_paths(
+    job: &Job,
+    cwd: &Path,
+    top: Option<&Path>,
+) -> Result<(PathSet, Option<FilterInput>), Error> {
+    let matches = job.matches;
+    let relative = |path: String| match top {
+        Some(top) if top != cwd => from_top(top, cwd, &path),
+        _ => path,
+    };
+
+    // Assume free arguments are paths we want to examine,
+    // along with any listed in --files-from.
+    let mut given: Vec<String> = matches
+        .get_many::<OsString>("paths")
+        .map(|ps| ps.filter_map(|p| utf8_path(p)).collect())
+        .unwrap_or_default();
+    if let Some(source) = string(matches, "files-from") {
+        given.extend(read_path_list(&source, cwd, matches.get_flag("null"))?);
+    } else if matches.get_flag("null") {
+        eprintln!("-0 only makes sense with --files-from");
+        print_usage(job.usage, 1);
+    }
+
+    let mut paths = PathSet::with_capacity(given.len());
+    for path in given {
+        paths.insert(relative(path));
+    }
+
+    // A filter works on the contents it's given, as if they were the file
+    // at --path.
+    let filter_input = if job.command == "filter" {
+        let path = relative(string(matches, "path").unwrap());
+        let mut contents = Vec::new();
+        io::stdin()
+            .read_to_end(&mut contents)
+            .map_err(Error::io("read stdin"))?;
+        paths.insert(path.clone());
+        Some((path, contents))
+    } else {
+        None
+    };
+    Ok((paths, filter_input))
+}
+
+// Adds the files we find ourselves (if we're asked to, or the config file
+// says to and we're given none) and the ones listed in --years-from
+// to the given ones, then drops the ones we're told to skip.
+fn select_files(
+    job: &Job,
+    config: &config::Config,
+    vcs: &dyn Vcs,
+    git: &GitOptions,
+    mut paths: PathSet,
+    listed: Vec<String>,
+) -> Result<Selection, Error> {
+    let (matches, usage) = (job.matches, job.usage);
+    paths.extend(listed);
+
+    // Find more of them ourselves if asked.
+    let extensions: Vec<String> = match string(matches, "ext") {
+        Some(e) => e.split(',').map(|e| e.to_owned()).collect(),
+        None => config.ext.clone(),
+    };
+    let includes = or_config(strings(matches, "include"), config.include.clone());
+    let excludes = or_config(strings(matches, "exclude"), config.exclude.clone());
+    let changed_since = string(matches, "changed-since").or(config.changed_since.clone());
+    let staged = matches.get_flag("staged");
+    if staged && changed_since.is_some() {
+        eprintln!("--staged and --changed-since can't be used together.");
+        print_usage(usage, 1);
+    }
+    if vcs.kind() != vcs::Kind::Git {
+        let git_only = if staged {
+            Some("--staged")
+        } else if changed_since.is_some() {
+            Some("--changed-since")
+        } else if git.ignore_whitespace {
+            Some("--ignore-whitespace")
+        } else if git.date_source == DateSource::Committer {
+            Some("--date-source committer")
+        } else {
+            None
+        };
+        if let Some(what) = git_only {
+            return Err(Error::Unsupported {
+                what: what.to_owned(),
+                vcs: vcs.kind().name(),
+            });
+        }
+    }
+    let watching = job.command == "watch";
+    if watching {
+        // (There's no point watching for files we'll never update.)
+        if extensions.is_empty() && includes.is_empty() {
+            eprintln!("watch needs --ext or --include to know which files to update");
+            print_usage(usage, 1);
+        }
+        if let Some(id) = WATCH_CONFLICTS
+            .iter()
+            .find(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        {
+            let what = match *id {
+                "paths" => "files".to_owned(),
+                _ => format!("--{}", id),
+            };
+            eprintln!("watch finds its own files, so it can't be given {}", what);
+            print_usage(usage, 1);
+        }
+    }
+    if job.command == "notice" || job.command == "dep5" {
+        if let Some(id) = SUMMARY_CONFLICTS
+            .iter()
+            .find(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        {
+            eprintln!(
+                "{} doesn't change any files, so it can't be given --{}",
+                job.command, id
+            );
+            print_usage(usage, 1);
+        }
+    }
+
+    // We look for files if the command line says to, or if it doesn't give
+    // any and the config file says which ones to look for.
+    let asked_to_discover = staged
+        || ["ext", "include", "changed-since"]
+            .iter()
+            .any(|id| matches.value_source(id) == Some(ValueSource::CommandLine));
+    let configured_to_discover =
+        changed_since.is_some() || !extensions.is_empty() || !includes.is_empty();
+    let discovering = job.command != "filter"
+        && !watching
+        && (asked_to_discover || (paths.is_empty() && configured_to_discover));
+    let extensions = extensions
+        .iter()
+        .map(|e| e.trim().trim_start_matches('.'))
+        .filter(|e| !e.is_empty())
+        .map(|e| e.to_owned())
+        .collect();
+    let filter = match discover::FileFilter::new(extensions, &includes, &excludes) {
+        Ok(f) => f,
         Err(e) => {
-            // If the user messes up the args, print the error and usage string.
-            eprintln!("{}", e.to_string());
-            print_usage(&opts, 1);
+            eprintln!("{}", e);
+            print_usage(usage, 1);
         }
     };
+    if discovering {
+        // With --staged or --changed-since, only the files changed
+        // (since then) are candidates.
+        let found = match &changed_since {
+            _ if staged => discover::find_staged_files(git, &filter)?,
+            Some(since) => discover::find_changed_files(git, since, &filter)?,
+            None => discover::find_files(vcs, &filter)?,
+        };
+        paths.extend(found);
+    }
+    let skip_vendored = switched_on(
+        matches,
+        "skip-vendored",
+        "no-skip-vendored",
+        config.skip_vendored,
+    );
+    if skip_vendored {
+        paths = discover::skip_vendored(vcs, paths, &filter)?;
+    }
+    paths = discover::skip_ignored(paths)?;
 
-    if matches.opt_present("h") {
-        // Print help as-desired.
-        print_usage(&opts, 0);
+    // We update the working tree and stage that, so if it has changes
+    // besides what's staged, we'd commit those too.
+    if staged {
+        let unstaged = list_unstaged_files(git)?;
+        let partial: Vec<&str> = paths
+            .iter()
+            .map(|p| p.trim_start_matches("./"))
+            .filter(|p| unstaged.contains(*p))
+            .sorted();
+        if !partial.is_empty() {
+            return Err(Error::PartiallyStaged(partial.join(", ")));
+        }
     }
 
-    let organization = match matches.opt_str("o") {
-        Some(o) => o,
-        None => {
-            // -o is mandatory.
-            eprintln!("Required option 'organization' is missing.");
-            print_usage(&opts, 1);
+    Ok(Selection {
+        paths,
+        filter,
+        skip_vendored,
+        staged,
+    })
+}
+
+// Prints how many of the given files have notices (--count-only).
+fn count_notices(paths: PathSet, scan: &notice::ScanOptions) -> Result<(), Error> {
+    let total = paths.len();
+    let header_years = existing::get_year_map(paths, scan);
+    let with_notices = header_years.values().filter(|y| !y.is_empty()).count();
+    println!("{} with copyright notices", count_files(with_notices));
+    println!("{} without", count_files(header_years.len() - with_notices));
+    if header_years.len() < total {
+        println!(
+            "{} couldn't be read",
+            count_files(total - header_years.len())
+        );
+    }
+    Ok(())
+}
+
+// Removes the notices from the given files (--strip), then says how it went.
+fn strip(job: &Job, paths: &PathSet, scan: &notice::ScanOptions) -> Result<(), Error> {
+    let old_years = if job.output_format == OutputFormat::Jsonl {
+        existing::get_year_map(paths.clone(), scan)
+    } else {
+        YearMap::default()
+    };
+    let mut report =
+        update::strip_headers(paths, scan, job.first_pass, &job.files, job.show_progress);
+    if job.interactive {
+        report = confirm_edits(
+            report,
+            job.write_strategy,
+            &job.files,
+            job.show_progress,
+            job.color,
+        )?;
+    }
+    write_patch(
+        job.patch_dest.as_deref(),
+        &report,
+        job.color,
+        job.machine_output(),
+    )?;
+    if job.command == "analyze" {
+        plan::write(
+            &string(job.matches, "out").unwrap(),
+            &YearMap::default(),
+            &report,
+        )?;
+    }
+    if let Some(dest) = string(job.matches, "report-json") {
+        json_report::write(&dest, &YearMap::default(), &report)?;
+    }
+    match job.output_format {
+        OutputFormat::Text => {}
+        OutputFormat::Jsonl => {
+            json_report::write_lines(&old_years, &YearMap::default(), &report, None)?
+        }
+        OutputFormat::Sarif => json_report::write_sarif(&YearMap::default(), &report)?,
+    }
+    finish(
+        job.command,
+        job.mode,
+        job.machine_output(),
+        &YearMap::default(),
+        &report,
+    );
+    Ok(())
+}
+
+// Works out who holds the copyright to each file (and how their notices
+// should look) from the command line, config file, and repo.
+fn notice_options(
+    job: &Job,
+    config: &config::Config,
+    vcs: &dyn Vcs,
+    scan: notice::ScanOptions,
+    sort_holders: bool,
+    bump: bool,
+) -> Result<update::NoticeOptions, Error> {
+    let (matches, usage) = (job.matches, job.usage);
+
+    let organizations = find_organizations(
+        vcs,
+        strings(matches, "organization"),
+        config.organization.clone().into_vec(),
+    )?;
+    if organizations.is_empty() {
+        // We need some organization, one way or another.
+        eprintln!(
+            "Required option 'organization' is missing.\n\
+             (Give it with -o, ${}, or `{} config {}`.)",
+            ORGANIZATION_VAR,
+            vcs.kind().command(),
+            ORGANIZATION_CONFIG_KEY
+        );
+        print_usage(usage, 1);
+    }
+
+    let tidy_holders = |mut holders: Vec<String>| -> Vec<String> {
+        if sort_holders {
+            holders.sort();
         }
+        // Listing a holder twice shouldn't give them two lines.
+        holders
+            .into_iter()
+            .unique()
+            .map(|o| expand_placeholders(&o))
+            .collect()
     };
+    let organizations = tidy_holders(organizations);
+
+    // (These are sorted by glob, so that equally-specific ones are picked
+    // between the same way every run. See NoticeOptions::organizations_for())
+    let mut path_organizations = Vec::with_capacity(config.organization_by_path.len());
+    for (pattern, holders) in &config.organization_by_path {
+        let glob = match discover::glob(pattern) {
+            Ok(g) => g.compile_matcher(),
+            Err(e) => {
+                eprintln!("{} (in {})", e, config::CONFIG_PATH);
+                exit(ERROR_EXIT_CODE);
+            }
+        };
+        path_organizations.push(update::PathOrganizations {
+            glob,
+            organizations: tidy_holders(holders.clone().into_vec()),
+        });
+    }
+
+    let mut organization_years = Vec::with_capacity(config.organization_years.len());
+    for (name, range) in &config.organization_years {
+        match parse_year_range(range) {
+            Some((first, last)) => organization_years.push(update::OrganizationYears {
+                name: expand_placeholders(name),
+                first,
+                last,
+            }),
+            None => {
+                eprintln!(
+                    "organization-years for {:?} must be a range of years like 2012-2018, not {:?} (in {})",
+                    name,
+                    range,
+                    config::CONFIG_PATH
+                );
+                exit(ERROR_EXIT_CODE);
+            }
+        }
+    }
 
-    assert_at_repo_top();
+    let suffix = string(matches, "organization-suffix")
+        .or(config.organization_suffix.clone())
+        .map(|s| expand_placeholders(&s));
+
+    let year_separator = string(matches, "year-separator")
+        .or(config.year_separator.clone())
+        .unwrap_or_else(|| ",".to_string());
+
+    let placement = match string(matches, "placement")
+        .or(config.placement.clone())
+        .as_deref()
+    {
+        Some("top") => update::Placement::Top,
+        None | Some("after-shebang") => update::Placement::AfterShebang,
+        Some("after-comment") => update::Placement::AfterComment,
+        Some(p) => {
+            eprintln!("Unknown placement {:?}", p);
+            print_usage(usage, 1);
+        }
+    };
+
+    let add_missing_only = switched_on(
+        matches,
+        "add-missing-only",
+        "no-add-missing-only",
+        config.add_missing_only,
+    );
+    let update_only = switched_on(matches, "update-only", "no-update-only", config.update_only);
+    let scope = match (add_missing_only, update_only) {
+        (false, false) => update::Scope::All,
+        (true, false) => update::Scope::AddMissing,
+        (false, true) => update::Scope::UpdateExisting,
+        (true, true) => {
+            eprintln!("--add-missing-only and --update-only can't both be given");
+            print_usage(usage, 1);
+        }
+    };
+
+    let template = string(matches, "template")
+        .or(config.template.clone())
+        .map(|path| Template::load(&path).map_err(Error::io(format!("read template {}", path))))
+        .transpose()?;
+
+    Ok(update::NoticeOptions {
+        organizations,
+        path_organizations,
+        authorship: Default::default(),
+        organization_years,
+        suffix,
+        year_separator,
+        scan,
+        normalize: switched_on(matches, "normalize", "no-normalize", config.normalize),
+        force_organization: switched_on(matches, "force-org", "no-force-org", config.force_org),
+        allowed_holders: or_config(
+            strings(matches, "allow-holder"),
+            config.allow_holder.clone(),
+        )
+        .iter()
+        .map(|h| expand_placeholders(h))
+        .collect(),
+        license: string(matches, "spdx").or(config.spdx.clone()),
+        template,
+        placement,
+        scope,
+        keep_ranges: bump,
+        gap_tolerance: matches
+            .get_one::<u16>("gap-tolerance")
+            .copied()
+            .or(config.gap_tolerance),
+    })
+}
+
+// The earliest and latest years notices can list (--min-year and --max-year).
+fn year_bounds(
+    matches: &ArgMatches,
+    config: &config::Config,
+    usage: &StyledStr,
+) -> (Option<Year>, Option<Year>) {
+    let year_bound = |option: &str, config: Option<config::YearSetting>| {
+        let given = string(matches, option).or(config.map(|y| y.into_string()))?;
+        if given == "now" {
+            return Some(current_year());
+        }
+        match given.parse().ok().and_then(Year::new) {
+            Some(y) => Some(y),
+            None => {
+                eprintln!("--{} must be a year, not {:?}", option, given);
+                print_usage(usage, 1);
+            }
+        }
+    };
+    let min_year = year_bound("min-year", config.min_year.clone());
+    let max_year = year_bound("max-year", config.max_year.clone());
+    if let (Some(min), Some(max)) = (min_year, max_year) {
+        if min > max {
+            eprintln!("--min-year can't be after --max-year");
+            print_usage(usage, 1);
+        }
+    }
+    (min_year, max_year)
+}
+
+// Works out which commits in the repo's history count,
+// and how we go through it to find them.
+fn history_source(
+    matches: &ArgMatches,
+    config: &config::Config,
+    usage: &StyledStr,
+    vcs: &dyn Vcs,
+) -> Result<YearSource, Error> {
+    vcs.assert_at_repo_top()?;
 
     // Get the SHAs of commits we want to ignore
-    let ignores = get_commits_to_ignore(matches.opt_str("i"));
+    let config_ignores = config.ignore_commits.join(",");
+    let mut ignores = vcs::get_commits_to_ignore(
+        vcs,
+        Some(string(matches, "ignore-commits").unwrap_or(config_ignores)),
+    )?;
+
+    if let Some(file) = string(matches, "ignore-revs-file").or(config.ignore_revs_file.clone()) {
+        ignores.extend(vcs::read_ignore_revs_file(vcs, &file)?);
+    }
+
+    // Along with ones by authors (like bots) whose changes don't count
+    if let Some(authors) = string(matches, "ignore-authors").or(config.ignore_authors.clone()) {
+        match Regex::new(&authors) {
+            Ok(re) => ignores.extend(vcs.commits_by_authors(&re)?),
+            Err(e) => {
+                eprintln!("Invalid --ignore-authors pattern: {}", e);
+                print_usage(usage, 1);
+            }
+        }
+    }
+
+    // And ones whose messages say they don't (like dependency bumps).
+    let messages = or_config(
+        strings(matches, "ignore-message"),
+        config.ignore_message.clone(),
+    );
+    if !messages.is_empty() {
+        for message in &messages {
+            if let Err(e) = Regex::new(message) {
+                eprintln!("Invalid --ignore-message pattern: {}", e);
+                print_usage(usage, 1);
+            }
+        }
+        // One pattern matching any of them lets us look through history once.
+        let any = messages.iter().map(|m| format!("(?:{})", m)).join("|");
+        ignores.extend(vcs.commits_by_message(&Regex::new(&any).unwrap())?);
+    }
+
+    // Merges just bring together changes from other commits,
+    // which count on their own.
+    if switched_on(matches, "no-merges", "merges", config.no_merges) {
+        ignores.extend(vcs.merge_commits()?);
+    }
+    if !ignores.is_empty() {
+        info!("Ignoring {} commits", ignores.len());
+    }
 
     // Grab the first year of the commit so we can use it later.
     // (If we do it now, we can skip all the work below if it fails).
-    let first_git_year = get_first_commit_year();
+    let first_git_year = vcs.first_commit_year()?;
+    info!("History starts in {}", first_git_year);
 
-    // Assume free arguments are paths we want to examine
-    let mut paths = PathSet::with_capacity(matches.free.len());
-    for path in matches.free {
-        paths.insert(path);
-    }
+    let method = if matches.get_flag("by-blame") {
+        history::Method::Blame
+    } else if matches.get_flag("per-file-history") {
+        history::Method::PerFile
+    } else {
+        history::Method::Batched
+    };
+    Ok(YearSource::History {
+        ignores,
+        method,
+        first_year: first_git_year,
+    })
+}
+
+impl<'a> Dating<'a> {
+    // Finds the years each of the given files was changed, along with the years
+    // their notices list, and returns those along with the years their notices
+    // listed to begin with (for --output-format jsonl).
+    fn find_years(
+        &self,
+        paths: PathSet,
+        scan: &notice::ScanOptions,
+    ) -> Result<(YearMap, YearMap), Error> {
+        let contents = self.filter_contents.clone();
+        let (mut header_years, git_years, first_year) = match &self.source {
+            YearSource::Given(years) => {
+                let git_years = paths
+                    .iter()
+                    .filter_map(|p| {
+                        Some((p.clone(), years.get(p.trim_start_matches("./"))?.clone()))
+                    })
+                    .collect();
+                let first_year = years.values().filter_map(|y| y.first()).min().copied();
+                (notice_years(paths, contents, scan), git_years, first_year)
+            }
+            YearSource::CurrentYear => {
+                let now = current_year();
+                let git_years = paths.iter().map(|p| (p.clone(), vec![now])).collect();
+                (notice_years(paths, contents, scan), git_years, None)
+            }
+            YearSource::History {
+                ignores,
+                method,
+                first_year,
+            } => {
+                // Kick off two threads: one gets when files were modified via Git history,
+                // and the other searches the files themselves for existing copyright info.
+                let pc = paths.clone();
+                let (header_years, git_years) = thread::scope(|s| {
+                    let git_years_handle = s.spawn(|| {
+                        self.vcs
+                            .file_years(pc, ignores, *method, self.show_progress)
+                    });
+                    let header_years_handle = s.spawn(|| notice_years(paths, contents, scan));
+
+                    // Let them finish.
+                    (
+                        header_years_handle.join().unwrap(),
+                        git_years_handle.join().unwrap(),
+                    )
+                });
+                (header_years, git_years?, Some(*first_year))
+            }
+        };
+        let old_years = if self.keep_notice_years {
+            header_years.clone()
+        } else {
+            YearMap::default()
+        };
+
+        // Strip header-provided years that overlap with Git history
+        // (or the years we were given).
+        if let Some(first_year) = first_year {
+            trim_header_years(&mut header_years, first_year);
+        }
+
+        if log_enabled!(Level::Debug) {
+            let list = |years: Option<&Vec<Year>>| match years {
+                Some(y) if !y.is_empty() => update::format_year_list(y, ","),
+                _ => "no years".to_owned(),
+            };
+            for path in git_years
+                .keys()
+                .chain(header_years.keys())
+                .unique()
+                .sorted()
+            {
+                debug!(
+                    "{}: {} from history, {} from its notice",
+                    path,
+                    list(git_years.get(path)),
+                    list(header_years.get(path))
+                );
+            }
+        }
 
-    // Kick off two threads: one gets when files were modified via Git history,
-    // and the other searches the files themselves for existing copyright info.
-    let pc = paths.clone();
-    let git_years_handle = thread::spawn(move || history::get_year_map(pc, &ignores));
-    let header_years_handle = thread::spawn(|| existing::get_year_map(paths));
+        // Which files we know were changed this year, for --strict-history
+        let now = current_year();
+        let mut changed_now: HashSet<String> = git_years
+            .iter()
+            .filter(|(_, years)| self.strict_history && years.contains(&now))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        let mut all_years = combine_year_maps(header_years, git_years);
+
+        // Files with changes that haven't been committed were changed this year,
+        // even if history doesn't know that yet. (That's all of them with --staged.)
+        if self.include_dirty {
+            let dirty = self.vcs.dirty_files()?;
+            for (path, years) in all_years.iter_mut() {
+                if dirty.contains(path.trim_start_matches("./")) {
+                    changed_now.insert(path.clone());
+                    if !years.contains(&now) {
+                        years.push(now);
+                        years.sort();
+                    }
+                }
+            }
+        }
+
+        clamp_years(&mut all_years, self.min_year, self.max_year);
+
+        // Nothing else gets to say files were changed this year.
+        if self.strict_history {
+            for (path, years) in all_years.iter_mut() {
+                if !changed_now.contains(path) {
+                    years.retain(|&y| y != now);
+                }
+            }
+        }
+        Ok((old_years, all_years))
+    }
+}
 
-    // Let them finish.
-    let mut header_years: YearMap = header_years_handle.join().unwrap();
-    let git_years: YearMap = git_years_handle.join().unwrap();
+// Updates the notices of the files we're watching for as they're saved.
+// (This only returns if watching fails.)
+fn watch(
+    job: &Job,
+    selection: &Selection,
+    dating: &Dating,
+    notice_options: &update::NoticeOptions,
+) -> Result<(), Error> {
+    watch::watch(
+        dating.vcs,
+        &selection.filter,
+        selection.skip_vendored,
+        |paths| {
+            let (_, years) = dating.find_years(paths, &notice_options.scan)?;
+            let report = update::update_headers(
+                &years,
+                notice_options,
+                job.mode,
+                &job.files,
+                job.show_progress,
+            );
+            for (path, action) in &report.actions {
+                if matches!(action, update::Action::Added | update::Action::Updated) {
+                    println!("{:<8} {}", action, path);
+                }
+            }
+            log_problems(&report);
+            Ok(())
+        },
+    )
+}
 
-    // Strip header-provided years that overlap with Git history.
-    trim_header_years(&mut header_years, first_git_year);
+// Writes a NOTICE file listing each holder and the years they hold
+// the copyright to any of the files.
+fn write_notice_file(
+    matches: &ArgMatches,
+    all_years: &YearMap,
+    notice_options: &update::NoticeOptions,
+) -> Result<(), Error> {
+    let holders = notice_file::collect_holders(all_years, notice_options);
+    let text = notice_file::render(
+        string(matches, "title").as_deref(),
+        &holders,
+        &notice_options.scan.lead,
+    );
+    match string(matches, "out").as_deref() {
+        Some("-") => print!("{}", text),
+        out => {
+            let out = out.unwrap_or(NOTICE_PATH);
+            fs::write(out, text).map_err(Error::io(format!("write {}", out)))?;
+            println!("Wrote {} holders to {}", holders.len(), out);
+        }
+    }
+    Ok(())
+}
 
-    let all_years = combine_year_maps(header_years, git_years);
+// Writes a Debian copyright file (see dep5::render()) for the files.
+fn write_dep5(
+    matches: &ArgMatches,
+    all_years: &YearMap,
+    notice_options: &update::NoticeOptions,
+) -> Result<(), Error> {
+    let header = dep5::Header {
+        upstream_name: string(matches, "upstream-name"),
+        source: string(matches, "source"),
+    };
+    let text = dep5::render(&header, all_years, notice_options);
+    match string(matches, "out").as_deref() {
+        Some("-") => print!("{}", text),
+        out => {
+            let out = out.unwrap_or(DEP5_PATH);
+            if let Some(dir) = Path::new(out)
+                .parent()
+                .filter(|d| !d.as_os_str().is_empty())
+            {
+                fs::create_dir_all(dir).map_err(Error::io(format!("create {}", dir.display())))?;
+            }
+            fs::write(out, text).map_err(Error::io(format!("write {}", out)))?;
+            println!("Wrote {}", out);
+        }
+    }
+    Ok(())
+}
 
-    // Take all the info we've learned, and update (or create) copyright headers.
-    update::update_headers(&all_years, &organization);
+// Prints the given contents of the file at `path` with its notice updated.
+fn filter(
+    job: &Job,
+    path: &str,
+    contents: Vec<u8>,
+    all_years: &YearMap,
+    notice_options: &update::NoticeOptions,
+) -> Result<(), Error> {
+    let years = all_years.get(path).map_or(&[][..], |y| y);
+    let (contents, report) =
+        update::update_contents(path, years, notice_options, &job.files, contents);
+    io::stdout()
+        .write_all(&contents)
+        .map_err(Error::io("write stdout"))?;
+    finish(job.command, job.mode, false, all_years, &report);
+    Ok(())
 }
 
-fn get_commits_to_ignore<S: Borrow<str>>(ignore_arg: Option<S>) -> HashSet<SHA1> {
-    let ignore_arg = match ignore_arg {
-        Some(a) => a,
-        None => return HashSet::new(),
+// Takes all the info we've learned, and updates (or creates) copyright headers,
+// or the companion files that stand in for them, then says how it went.
+// With --staged, `staged` says how to stage what we changed.
+fn update(
+    job: &Job,
+    staged: Option<&GitOptions>,
+    old_years: &YearMap,
+    all_years: &YearMap,
+    notice_options: &update::NoticeOptions,
+) -> Result<(), Error> {
+    let (command, matches) = (job.command, job.matches);
+    let reuse = matches.get_flag("reuse");
+    let mut report = if reuse {
+        update::update_companions(
+            all_years,
+            notice_options,
+            job.mode,
+            &job.files,
+            job.show_progress,
+        )
+    } else {
+        update::update_headers(
+            all_years,
+            notice_options,
+            job.first_pass,
+            &job.files,
+            job.show_progress,
+        )
     };
+    if job.interactive {
+        report = confirm_edits(
+            report,
+            job.write_strategy,
+            &job.files,
+            job.show_progress,
+            job.color,
+        )?;
+    }
+    write_patch(
+        job.patch_dest.as_deref(),
+        &report,
+        job.color,
+        job.machine_output(),
+    )?;
+    if command == "analyze" {
+        plan::write(&string(matches, "out").unwrap(), all_years, &report)?;
+    }
+    if let Some(dest) = string(matches, "report-json") {
+        json_report::write(&dest, all_years, &report)?;
+    }
+
+    // Put our changes in the commit being made.
+    if let (Some(git), update::Mode::Write(_)) = (staged, job.mode) {
+        let changed: Vec<String> = if reuse {
+            report
+                .changed
+                .iter()
+                .map(|p| update::companion_path(p))
+                .collect()
+        } else {
+            report.changed.clone()
+        };
+        stage_files(git, &changed)?;
+    }
+
+    match job.output_format {
+        OutputFormat::Text => {}
+        OutputFormat::Jsonl => {
+            json_report::write_lines(old_years, all_years, &report, Some(notice_options))?
+        }
+        OutputFormat::Sarif => json_report::write_sarif(all_years, &report)?,
+    }
+    // (The stats stand in for report's list of files.)
+    let summed_up = command == "report" && matches.get_flag("stats");
+    if summed_up {
+        print!(
+            "{}",
+            stats::render(&stats::collect(all_years, &report, &notice_options.scan))
+        );
+    }
+    finish(
+        command,
+        job.mode,
+        job.machine_output() || summed_up,
+        all_years,
+        &report,
+    );
+    Ok(())
+}
+
+// Shows the change planned for each file (see update::Mode::Plan) and asks
+// whether to make it, like `git add -p`, then makes the ones we're told to.
+fn confirm_edits(
+    plan: update::Report,
+    strategy: update::WriteStrategy,
+    files: &update::FileOptions,
+    show_progress: bool,
+    color: bool,
+) -> Result<update::Report, Error> {
+    let mut chosen = update::PlannedEdits::new();
+    let mut all = false;
+    for (path, action, planned) in &plan.planned {
+        if !all {
+            let contents = encoding::read(path).map_err(Error::io(format!("read {}", path)))?;
+            let edit = update::Edit {
+                start: planned.start,
+                old_length: planned.old_length,
+                text: planned.text.clone(),
+            };
+            let diff = diff::unified_diff(path, &contents, &edit).unwrap_or_default();
+            if color {
+                print!("{}", diff::colorize(&diff));
+            } else {
+                print!("{}", diff);
+            }
+
+            let question = match action {
+                update::Action::Added => format!("Add a notice to {}?", path),
+                update::Action::Removed => format!("Remove the notice from {}?", path),
+                _ => format!("Update the notice in {}?", path),
+            };
+            match ask_about_change(&question)? {
+                'y' => {}
+                'a' => all = true,
+                'q' => break,
+                _ => continue,
+            }
+        }
+        chosen.push((path.clone(), *action, planned.clone()));
+    }
 
-    ignore_arg
-        .borrow()
-        .split(',')
-        .filter(|s| !s.is_empty())
-        .map(|c| commit_ish_into_sha(c.trim()))
+    let applied = update::apply_planned_edits(&chosen, strategy, files, show_progress);
+    Ok(plan.settle(applied))
+}
+
+// Asks whether to make a change until we get an answer we understand,
+// which is one of y, n, a, or q. (Running out of input means q.)
+fn ask_about_change(question: &str) -> Result<char, Error> {
+    loop {
+        eprint!("{} [y,n,a,q,?] ", question);
+        let mut answer = String::new();
+        let read = io::stdin()
+            .read_line(&mut answer)
+            .map_err(Error::io("read an answer"))?;
+        if read == 0 {
+            eprintln!();
+            return Ok('q');
+        }
+        match answer.trim().to_lowercase().chars().next() {
+            Some(c) if "ynaq".contains(c) && answer.trim().len() == 1 => return Ok(c),
+            _ => eprintln!(
+                "y - make this change\n\
+                 n - don't make this change\n\
+                 a - make this change and all the rest\n\
+                 q - don't make this change or any of the rest"
+            ),
+        }
+    }
+}
+
+// Makes the edits in a plan saved by `analyze` (see plan::read()),
+// then says how it went, like update would.
+fn apply(
+    matches: &ArgMatches,
+    backup: Option<String>,
+    strategy: update::WriteStrategy,
+    mut files: update::FileOptions,
+    show_progress: bool,
+    usage: &StyledStr,
+) -> Result<(), Error> {
+    if let Some(suffix) = backup {
+        if suffix.is_empty() {
+            eprintln!("--backup needs a suffix, so backups don't overwrite their files");
+            print_usage(usage, 1);
+        }
+        files.backups = Some(backup::Backups::new(suffix));
+    }
+
+    let (years, planned) = plan::read(&string(matches, "plan").unwrap())?;
+    let report = update::apply_planned_edits(&planned, strategy, &files, show_progress);
+    finish(
+        "apply",
+        update::Mode::Write(strategy),
+        false,
+        &years,
+        &report,
+    );
+    Ok(())
+}
+
+// The years each file's notice lists, or with a filter's contents,
+// the years the notice in them lists. (Ones we can't read are left out
+// without a fuss, since updating them will complain about it.)
+fn notice_years(
+    paths: PathSet,
+    filter_contents: Option<Vec<u8>>,
+    scan: &notice::ScanOptions,
+) -> YearMap {
+    let contents = match filter_contents {
+        Some(c) => c,
+        None => return existing::get_year_map_to_update(paths, scan),
+    };
+    paths
+        .into_iter()
+        .filter_map(
+            |path| match existing::scan_contents(&path, &contents, scan) {
+                Ok(years) => Some((path, years)),
+                Err(e) => {
+                    debug!("Couldn't read {} (yet): {}", path, e);
+                    None
+                }
+            },
+        )
         .collect()
 }
 
-fn trim_header_years(header_years: &mut YearMap, first_year: Year) {
-    // We trust Git history more than we do copyright comments,
-    // so discard all years after the year of the first Git commit
-    // from the ones we parsed out of the files.
-    //
-    // Unless the first commit was made at 00:00:00 on January 1,
-    // there's a chance changes were made that year before Git,
-    // so we keep the first year around.
-    for val in header_years.values_mut() {
-        val.retain(|&y| y <= first_year);
+// Without -o, fall back to the environment, then the config file,
+// then the Git config, so nobody has to retype the exact legal name.
+fn find_organizations(
+    vcs: &dyn Vcs,
+    given: Vec<String>,
+    config: Vec<String>,
+) -> Result<Vec<String>, Error> {
+    let mut organizations = given;
+    if organizations.is_empty() {
+        organizations = env::var(ORGANIZATION_VAR).map_or_else(|_| Vec::new(), |o| vec![o]);
+    }
+    organizations = or_config(organizations, config);
+    if organizations.is_empty() {
+        organizations = vcs.config_values(ORGANIZATION_CONFIG_KEY)?;
+    }
+    organizations.retain(|o| !o.trim().is_empty());
+    Ok(organizations)
+}
+
+// Looks the repo over, asks about what we found (if there's anyone to ask),
+// then writes a starter config file (see config::starter()) at the top of the repo,
+// and maybe a pre-commit hook.
+fn init(kind: vcs::Kind, matches: &ArgMatches) -> Result<(), Error> {
+    let force = matches.get_flag("force");
+    // Don't make anybody answer questions just to find out we won't write anything.
+    if !force && Path::new(config::CONFIG_PATH).exists() {
+        return Err(Error::AlreadyExists(config::CONFIG_PATH.to_owned()));
+    }
+
+    // (init doesn't take any of the Git options.)
+    let git = GitOptions::default();
+    let vcs = vcs::open(kind, git.clone());
+    let vcs = vcs.as_ref();
+    let survey = init::survey(vcs)?;
+    let mut extensions = survey.suggested_extensions();
+    if extensions.is_empty() {
+        println!("Found no files we know how to add notices to");
+    } else {
+        println!(
+            "Found {} we can add notices to, mostly {}",
+            count_files(survey.files),
+            extensions.iter().map(|e| format!(".{}", e)).join(", ")
+        );
+    }
+    if let Some(holder) = survey.suggested_holder() {
+        println!(
+            "{} of them have notices, most often naming {}",
+            survey.with_notices, holder
+        );
+    }
+    if let Some(year) = survey.first_year {
+        println!("History starts in {}", year);
+    }
+
+    let mut organizations = find_organizations(vcs, strings(matches, "organization"), Vec::new())?;
+    if organizations.is_empty() {
+        organizations.extend(survey.suggested_holder().map(str::to_owned));
+    }
+    let mut hook = matches.get_flag("hook");
+
+    if !matches.get_flag("yes") && io::stdin().is_terminal() {
+        // (Organizations can have commas in them, so several can't be given
+        // as one answer. Those who want several can pass -o for each.)
+        if organizations.len() <= 1 {
+            let answer = ask("Organization", organizations.first().map_or("", |o| o))?;
+            organizations = vec![answer];
+            organizations.retain(|o| !o.is_empty());
+        }
+        extensions = ask("Extensions to update", &extensions.join(","))?
+            .split(',')
+            .map(|e| e.trim().trim_start_matches('.').to_owned())
+            .filter(|e| !e.is_empty())
+            .collect();
+        if !hook && vcs.kind() == vcs::Kind::Git {
+            hook = ask_yes_no("Install a pre-commit hook that updates staged files?")?;
+        }
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    options
+        .open(config::CONFIG_PATH)
+        .and_then(|mut f| f.write_all(config::starter(&organizations, &extensions).as_bytes()))
+        .map_err(Error::io(format!("write {}", config::CONFIG_PATH)))?;
+    println!("Wrote {}", config::CONFIG_PATH);
+
+    if hook {
+        let path = init::install_hook(vcs, &git, force)?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+// Asks the user a question (on stderr, so stdout is just what we did),
+// and returns their answer, or `default` if they just hit enter.
+fn ask(question: &str, default: &str) -> Result<String, Error> {
+    if default.is_empty() {
+        eprint!("{}: ", question);
+    } else {
+        eprint!("{} [{}]: ", question, default);
+    }
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(Error::io("read an answer"))?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_owned())
+}
+
+// Asks the user a yes-or-no question, taking anything but a yes as a no.
+fn ask_yes_no(question: &str) -> Result<bool, Error> {
+    let answer = ask(&format!("{} [y/N]", question), "")?;
+    Ok(answer.to_lowercase().starts_with('y'))
+}
+
+// Reads the paths listed in the given file (relative to `cwd`, since we've
+// since moved to the top of the repo), or stdin for "-".
+fn read_path_list(source: &str, cwd: &Path, nul_separated: bool) -> Result<Vec<String>, Error> {
+    let mut list = Vec::new();
+    let what = || format!("read paths from {}", source);
+    if source == "-" {
+        io::stdin()
+            .read_to_end(&mut list)
+            .map_err(Error::io(what()))?;
+    } else {
+        list = fs::read(cwd.join(source)).map_err(Error::io(what()))?;
+    }
+
+    // (Names that aren't UTF-8 are skipped, so the list itself needn't be.)
+    Ok(if nul_separated {
+        utf8_paths(list.split(|b| *b == 0))
+    } else {
+        utf8_paths(
+            list.split(|b| *b == b'\n')
+                .map(|l| l.strip_suffix(b"\r").unwrap_or(l)),
+        )
+    })
+}
+
+// Turns a path given relative to `cwd` into one relative to the repo's `top`.
+// (Paths outside the repo are left alone, and Git will complain about them.)
+fn from_top(top: &Path, cwd: &Path, path: &str) -> String {
+    // Resolve . and .. ourselves, so we get the same path Git would list,
+    // without following symlinks like canonicalize() would.
+    let mut resolved = PathBuf::new();
+    for component in cwd.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            c => resolved.push(c),
+        }
+    }
+
+    match resolved.strip_prefix(top) {
+        // Git always uses forward slashes.
+        Ok(relative) => relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .join("/"),
+        Err(_) => path.to_owned(),
+    }
+}
+
+// Prints the diff of a dry run's changes (colored, if asked), or writes it
+// to the file given with --emit-patch (or stdout, for -).
+// (With --output-format jsonl or sarif, stdout is for JSON, so dry runs don't print it.)
+fn write_patch(
+    dest: Option<&str>,
+    report: &update::Report,
+    color: bool,
+    machine_output: bool,
+) -> Result<(), Error> {
+    match dest {
+        None if machine_output => Ok(()),
+        None if color => {
+            print!("{}", diff::colorize(&report.patch));
+            Ok(())
+        }
+        None | Some("-") => {
+            print!("{}", report.patch);
+            Ok(())
+        }
+        Some(path) => fs::write(path, &report.patch)
+            .map_err(Error::io(format!("write the patch to {}", path))),
+    }
+}
+
+// Whether a flag the config file can turn on too (see `switch()`) is on:
+// whichever of it and its opposite the command line gave, or else the config's
+fn switched_on(matches: &ArgMatches, on: &str, off: &str, config: bool) -> bool {
+    if matches.get_flag(on) {
+        true
+    } else if matches.get_flag(off) {
+        false
+    } else {
+        config
     }
 }
 
-fn combine_year_maps(header_years: YearMap, git_years: YearMap) -> YearMap {
-    // Merge the smaller map into the larger to try to avoid a realloc
-    let (mut larger, smaller) = if git_years.len() > header_years.len() {
-        (git_years, header_years)
+// Use values from the command line, if there were any, or the config file.
+fn or_config(command_line: Vec<String>, config: Vec<String>) -> Vec<String> {
+    if command_line.is_empty() {
+        config
     } else {
-        (header_years, git_years)
+        command_line
+    }
+}
+
+// Parses a range of years from the config file, like 2012-2018,
+// where either end can be left off (e.g. 2019- for "since 2019").
+fn parse_year_range(range: &str) -> Option<(Option<Year>, Option<Year>)> {
+    let year = |y: &str| -> Option<Option<Year>> {
+        match y.trim() {
+            "" => Some(None),
+            y => y.parse().ok().and_then(Year::new).map(Some),
+        }
+    };
+    let (first, last) = match range.split_once('-') {
+        Some((first, last)) => (year(first)?, year(last)?),
+        None => {
+            let y = year(range)?;
+            (y, y)
+        }
     };
+    match (first, last) {
+        (Some(f), Some(l)) if f > l => None,
+        (None, None) => None,
+        range => Some(range),
+    }
+}
 
-    // Transfer all of smaller's entries into larger.
-    for (k, mut v) in smaller {
-        let e = larger.entry(k).or_insert_with(Vec::new);
-        e.append(&mut v);
+// Warns about the files we skipped, and complains about the ones
+// we couldn't handle.
+fn log_problems(report: &update::Report) {
+    for (path, holder) in &report.skipped {
+        warn!("Skipped {}: its notice names {:?}", path, holder);
+    }
+    for path in &report.binary {
+        warn!(
+            "Skipped {}: it looks binary (--force updates it anyway)",
+            path
+        );
+    }
+    for path in &report.generated {
+        warn!(
+            "Skipped {}: it says it's generated (--include-generated updates it anyway)",
+            path
+        );
     }
 
-    // Sort and dedup our master map.
-    for v in larger.values_mut() {
-        v.sort();
-        v.dedup();
-        // Once sorted and deduped, we won't be modifying this anymore,
-        // so free up any memory we aren't using.
-        v.shrink_to_fit();
+    for (_, failure) in &report.failures {
+        error!("{}", failure);
     }
+}
 
-    // Ditto for the hashmap itself
-    larger.shrink_to_fit();
+// List any files we couldn't handle, then exit with a code saying how things went.
+// check lists the files that need updating, and report lists each file's years
+// and what we'd do to its notice (unless stdout is for --output-format).
+fn finish(
+    command: &str,
+    mode: update::Mode,
+    machine_output: bool,
+    years: &YearMap,
+    report: &update::Report,
+) {
+    let reporting = command == "report";
+    if machine_output {
+        // (We already printed everything there is to say about each file.)
+    } else if reporting {
+        for (path, action) in &report.actions {
+            let listed = years
+                .get(path)
+                .map_or_else(String::new, |y| update::format_year_list(y, ","));
+            println!("{:<8} {}: {}", action, path, listed);
+        }
+    } else if mode == update::Mode::Check {
+        for path in &report.changed {
+            println!("{}", path);
+        }
+    }
+
+    log_problems(report);
+
+    // (Filters run on each file separately, so a summary of one is just noise.)
+    if !reporting && mode != update::Mode::Check && command != "filter" {
+        let note = match mode {
+            update::Mode::DryRun => " (dry run)",
+            update::Mode::Plan => " (planned)",
+            _ => "",
+        };
+        eprintln!("{}{}", report.summary(), note);
+    }
 
-    larger
+    if !report.failures.is_empty() {
+        eprintln!("{} couldn't be handled", count_files(report.failures.len()));
+        exit(ERROR_EXIT_CODE);
+    }
+    if !reporting && mode == update::Mode::Check && !report.changed.is_empty() {
+        let needs = if report.changed.len() == 1 {
+            "needs"
+        } else {
+            "need"
+        };
+        eprintln!(
+            "{} {} updated copyright notices",
+            count_files(report.changed.len()),
+            needs
+        );
+        exit(1);
+    }
 }
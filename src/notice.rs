@@ -0,0 +1,828 @@
+//! Finds existing copyright notices at the top of files.
+//! Both the scanner (which parses years out of the notice) and the updater
+//! (which rewrites it) need to agree on where a notice is, so that lives here.
+//!
+//! Files aren't always UTF-8 (older ones are often in Latin-1 or the like),
+//! so we read their lines as bytes. Only the notice itself has to be UTF-8,
+//! since we rewrite it.
+
+use std::io::{self, BufRead};
+
+use lazy_static::lazy_static;
+use regex::{bytes, Match, Regex};
+
+use crate::comment::{self, CommentStyle, StyleRegistry};
+use crate::common::Year;
+
+/// The location and contents of an existing notice.
+#[derive(Debug)]
+pub struct Notice {
+    /// Byte offset of the start of the first notice line
+    pub start: usize,
+
+    /// Length in bytes of the notice lines, not counting the newline
+    /// (or trailing space) of the last one.
+    pub length: usize,
+
+    /// Byte offset of the first line belonging to the notice.
+    /// This is usually `start`, unless "Copyright" is on its own line
+    /// before the years.
+    pub line_start: usize,
+
+    /// Byte offset just past the last notice line (and its newline)
+    pub line_end: usize,
+
+    /// Everything that comes before the list of years,
+    /// e.g. `// Copyright ©` or ` * @copyright`.
+    pub lead: String,
+
+    /// The comment opener that starts each notice line (e.g. `//` or ` *`),
+    /// along with any whitespace before it
+    pub opener: String,
+
+    /// Whatever closes the comment on each notice line (e.g. `*/`),
+    /// if the lines are closed.
+    pub closer: Option<&'static str>,
+
+    /// The (unparsed) list of years from each notice line
+    pub year_lists: Vec<String>,
+
+    /// Whatever follows the years on each notice line (the holder,
+    /// and any text after it), minus any comment closer and license text
+    pub holders: Vec<String>,
+
+    /// The license text after the holder on each notice line, if any
+    /// (e.g. `. Licensed under the MIT License.` or an SPDX tag),
+    /// starting with whatever separates it from the holder.
+    /// We keep it as it is whenever we rewrite the line.
+    pub licenses: Vec<Option<String>>,
+}
+
+lazy_static! {
+    // A comment (or the continuation of a /* block) mentioning copyright,
+    // including SPDX's `SPDX-FileCopyrightText:` tags.
+    // Only digits in the run right after "Copyright" (and its symbol)
+    // are years. Anything after the holder, like a "Generated 2024"
+    // that came from {now_year}, is left alone.
+    // (Years can be separated by commas, semicolons, or just spaces.)
+    // Like the other patterns that start with a comment marker, which ones
+    // count depends on the file (see `captures()`).
+    static ref COPYRIGHT_LINE: bytes::Regex = bytes::Regex::new(&format!(
+        r"^(\s*(?P<marker>{}|\*)).*?[Cc]opyright(?:Text:)?(?:\s|©|\([Cc]\))*(?P<years>[\d\s,;–—-]*)",
+        comment::opener_pattern()
+    ))
+    .unwrap();
+
+    // The second half of a notice split across two lines: a comment
+    // (or the continuation of a /* block) that starts with a year.
+    static ref YEARS_LINE: bytes::Regex = bytes::Regex::new(&format!(
+        r"^(\s*(?P<marker>{}|\*))\s*(?P<years>\d{{4}}[\d\s,;–—-]*)",
+        comment::opener_pattern()
+    ))
+    .unwrap();
+
+    // Lines we can look past while searching for a notice
+    // (including XML declarations, which come before any comments)
+    // (Closers go first so that e.g. `#}` isn't taken for `#`.)
+    static ref COMMENT_OR_BLANK: bytes::Regex = bytes::Regex::new(&format!(
+        r"^\s*(?:$|<\?xml|(?P<marker>{}|{}|\*))",
+        comment::closer_pattern(),
+        comment::opener_pattern()
+    ))
+    .unwrap();
+
+    // A comment (or the continuation of a /* block) starting with
+    // a copyright symbol and years, but not the word "Copyright",
+    // like the ones `--format symbol-only` writes.
+    static ref SYMBOL_LINE: bytes::Regex = bytes::Regex::new(&format!(
+        r"^(\s*(?P<marker>{}|\*))\s*(?:©|\([Cc]\))\s*(?P<years>\d{{4}}[\d\s,;–—-]*)",
+        comment::opener_pattern()
+    ))
+    .unwrap();
+
+    // A copyright line in the middle of a block comment, without
+    // a leading opener or `*` (only checked inside one).
+    static ref BARE_COPYRIGHT_LINE: bytes::Regex =
+        bytes::Regex::new(r"^(\s*).*?[Cc]opyright(?:Text:)?(?:\s|©|\([Cc]\))*(?P<years>[\d\s,;–—-]*)").unwrap();
+
+    static ref DOC_COMMENT_OPENER: bytes::Regex = bytes::Regex::new(r"^\s*/\*\*").unwrap();
+
+    // A Javadoc/JSDoc tag inside a /** ... */ block
+    static ref COPYRIGHT_TAG: bytes::Regex =
+        bytes::Regex::new(r"^(\s*(?P<marker>\*)?\s*@copyright)(?:\s|©|\([Cc]\))*(?P<years>[\d\s,;–—-]*)").unwrap();
+
+    // An SPDX license tag, e.g. `// SPDX-License-Identifier: MIT OR Apache-2.0`.
+    // The license expression is everything up to the comment closer, if any.
+    static ref LICENSE_LINE: bytes::Regex = bytes::Regex::new(&format!(
+        r"SPDX-License-Identifier:[ \t]*(.*?)\s*(?:{})?\s*$",
+        comment::closer_pattern()
+    ))
+    .unwrap();
+
+    // License text at the end of a notice line, like
+    // `Acme Corp. Licensed under the MIT License.`
+    // or `2019 Acme, SPDX-License-Identifier: MIT`
+    static ref TRAILING_LICENSE: Regex = Regex::new(
+        r"(?i)[,;]?\s*\b(?:(?:licensed|released|distributed)\s+under\b|SPDX-License-Identifier:).*$"
+    )
+    .unwrap();
+
+    // What generators put at the top of their output, e.g.
+    // `// Code generated by protoc-gen-go. DO NOT EDIT.` or `# @generated`
+    static ref GENERATED_MARKER: bytes::Regex =
+        bytes::Regex::new(r"@generated\b|(?i:do not edit|code generated by)").unwrap();
+}
+
+/// Does the top of the given file contents (the first `scan.lines` lines)
+/// say it was generated? Its generator will just overwrite any notice we add.
+pub fn looks_generated<R: BufRead>(mut reader: R, scan: &Scan) -> io::Result<bool> {
+    for _ in 0..scan.lines {
+        let mut line = Vec::new();
+        if !read_line(&mut reader, &mut line, scan.max_line_length)? {
+            break;
+        }
+        if GENERATED_MARKER.is_match(&line) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+impl Notice {
+    /// Is the given byte offset in the notice lines?
+    pub fn covers(&self, offset: usize) -> bool {
+        (self.start..self.start + self.length).contains(&offset)
+    }
+}
+
+/// A copyright line further down a file's comments that names
+/// one of the holders of its notice again (see `find_repeated_lines()`)
+#[derive(Debug)]
+pub struct RepeatedLine {
+    /// Byte offset of the start of the line
+    pub start: usize,
+
+    /// Byte offset just past the line (and its newline)
+    pub end: usize,
+
+    /// The (unparsed) list of years on the line
+    pub year_list: String,
+
+    /// Whatever follows the years on the line
+    pub holder: String,
+}
+
+/// What notice lines start with (after the comment opener) by default
+pub const DEFAULT_LEAD: &str = "Copyright ©";
+
+/// How many lines at the top of a file we look through for a notice
+/// by default, in case it's preceded by a banner, blank lines, etc.
+/// (Lines of a leading doc comment don't count against this.)
+pub const DEFAULT_SCAN_LINES: usize = 10;
+
+/// How long (in bytes) a line can be before we stop looking through it
+/// (and any after it) for a notice, by default. No notice is that long,
+/// but minified code can be megabytes of one line.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// How we look for notices at the top of files,
+/// and what the lines of the ones we find start with once they're rewritten
+pub struct ScanOptions {
+    /// The comment styles each file's notice can be in
+    pub styles: StyleRegistry,
+
+    /// What notice lines start with (after the comment opener),
+    /// e.g. `Copyright (c)`
+    pub lead: String,
+
+    /// How many lines at the top of a file we look through for a notice
+    /// (see `DEFAULT_SCAN_LINES`)
+    pub lines: usize,
+
+    /// How long a line can be before we stop looking through it
+    /// (see `DEFAULT_MAX_LINE_LENGTH`)
+    pub max_line_length: usize,
+}
+
+impl ScanOptions {
+    /// Looks for notices in the given styles, and otherwise the default way.
+    pub fn new(styles: StyleRegistry) -> Self {
+        Self {
+            styles,
+            lead: DEFAULT_LEAD.to_owned(),
+            lines: DEFAULT_SCAN_LINES,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+        }
+    }
+
+    /// How we look for a notice in the file at the given path
+    pub fn for_file(&self, path: &str) -> Scan<'_> {
+        Scan {
+            styles: self.styles.used_in(path),
+            lead: &self.lead,
+            lines: self.lines,
+            max_line_length: self.max_line_length,
+        }
+    }
+}
+
+/// How we look for a notice in a particular file (see `ScanOptions`)
+#[derive(Copy, Clone, Debug)]
+pub struct Scan<'a> {
+    /// The comment styles the file's comments can be in
+    /// (see `StyleRegistry::used_in()`)
+    pub styles: &'static [&'static CommentStyle],
+
+    // (The rest are the `ScanOptions`' own.)
+    pub lead: &'a str,
+    pub lines: usize,
+    pub max_line_length: usize,
+}
+
+/// Reads the next line of the given file contents (and its newline) into
+/// `line`, but no more than `limit` bytes of it.
+/// Returns whether it got a whole line, i.e. false at the end
+/// of the contents, or partway through a line longer than that.
+pub fn read_line<R: BufRead>(reader: &mut R, line: &mut Vec<u8>, limit: usize) -> io::Result<bool> {
+    let read = io::Read::take(reader, limit as u64).read_until(b'\n', line)?;
+    Ok(read > 0 && (read < limit || line.ends_with(b"\n")))
+}
+
+/// Looks for a notice at the top of the given file contents.
+///
+/// A notice is one or more consecutive copyright lines (one per holder),
+/// or consecutive `@copyright` tags inside a leading doc comment.
+/// If a line is just the word "Copyright", the notice is the line(s)
+/// of years and holders after it.
+///
+/// We look past leading comments (in `scan.styles`, which the file's
+/// comments can be in; see `comment::used_in()`) and blank lines without
+/// a notice (including the insides of block comments),
+/// but give up at the first line of code or after `scan.lines` lines.
+pub fn find_notice<R: BufRead>(mut reader: R, scan: &Scan) -> io::Result<Option<Notice>> {
+    let mut offset = skip_bom(&mut reader)?;
+    let mut scanned = 0;
+    let mut in_doc_comment = false;
+    // The closer we're waiting for, if we're in a block comment
+    let mut block_closer: Option<&'static str> = None;
+
+    loop {
+        let mut line = Vec::new();
+        if !read_line(&mut reader, &mut line, scan.max_line_length)? {
+            return Ok(None);
+        }
+
+        // Check for @copyright tags first, since they'd also look like
+        // a continuation of a block comment containing "copyright".
+        if let Some(cap) = captures(&COPYRIGHT_TAG, scan.styles, &line) {
+            // Update the tags in place to keep the doc comment intact.
+            let lead = text(&cap[1]);
+            let opener = lead.trim_end_matches("@copyright").trim_end().to_owned();
+            let lines = read_matching_lines(&mut reader, line, &COPYRIGHT_TAG, scan)?;
+            return build_notice(offset, lead, opener, &lines, &COPYRIGHT_TAG).map(Some);
+        }
+
+        for re in [&*COPYRIGHT_LINE, &*SYMBOL_LINE] {
+            if captures(re, scan.styles, &line).is_some() {
+                return copyright_line_notice(reader, offset, line, re, scan).map(Some);
+            }
+        }
+
+        if block_closer.is_some() {
+            if BARE_COPYRIGHT_LINE.is_match(&line) {
+                return copyright_line_notice(reader, offset, line, &BARE_COPYRIGHT_LINE, scan)
+                    .map(Some);
+            }
+        } else if !is_comment_or_blank(&line, scan.styles) {
+            return Ok(None);
+        }
+
+        block_closer = track_block_comment(block_closer, &line, scan.styles);
+
+        if DOC_COMMENT_OPENER.is_match(&line) {
+            in_doc_comment = true;
+        }
+        if in_doc_comment && contains(&line, "*/") {
+            in_doc_comment = false;
+        }
+
+        offset += line.len();
+
+        if !in_doc_comment {
+            scanned += 1;
+            if scanned >= scan.lines {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Looks for copyright lines after the given notice that name one of its
+/// holders again, e.g. `// Copyright 2019 Fluke Corporation` a few lines below
+/// `// Copyright 2015 Fluke Corporation`.
+///
+/// Like `find_notice()`, we only look through comments and blank lines,
+/// within the first `scan.lines` lines of the file.
+/// Lines without any years (like "... the above copyright notice ...")
+/// don't count, and neither do ones with license text after the holder
+/// (which merging them would lose).
+pub fn find_repeated_lines<R: BufRead>(
+    mut reader: R,
+    notice: &Notice,
+    scan: &Scan,
+) -> io::Result<Vec<RepeatedLine>> {
+    let mut offset = skip_bom(&mut reader)?;
+    let mut block_closer: Option<&'static str> = None;
+    let mut repeated = Vec::new();
+
+    for _ in 0..scan.lines {
+        let mut line = Vec::new();
+        if !read_line(&mut reader, &mut line, scan.max_line_length)? {
+            break;
+        }
+        let start = offset;
+        offset += line.len();
+
+        if start >= notice.line_end {
+            if block_closer.is_none() && !is_comment_or_blank(&line, scan.styles) {
+                break;
+            }
+
+            let cap = [&*COPYRIGHT_TAG, &*COPYRIGHT_LINE, &*SYMBOL_LINE]
+                .iter()
+                .find_map(|re| captures(re, scan.styles, &line))
+                .or_else(|| block_closer.and_then(|_| BARE_COPYRIGHT_LINE.captures(&line)));
+            if let Some(cap) = cap {
+                let years = cap.name("years").unwrap();
+                let holder = text(&line[years.end()..]);
+                let holder = holder.trim();
+                if !years.as_bytes().trim_ascii().is_empty()
+                    && !TRAILING_LICENSE.is_match(holder)
+                    && notice
+                        .holders
+                        .iter()
+                        .any(|h| names_holder(h, holder) || names_holder(holder, h))
+                {
+                    repeated.push(RepeatedLine {
+                        start,
+                        end: offset,
+                        year_list: text(years.as_bytes()),
+                        holder: holder.to_owned(),
+                    });
+                }
+            }
+        }
+
+        block_closer = track_block_comment(block_closer, &line, scan.styles);
+    }
+
+    Ok(repeated)
+}
+
+/// Does the given holder line (e.g. "Fluke Corporation. All rights reserved.")
+/// name the given organization (e.g. "Fluke Corporation")?
+pub fn names_holder(holder: &str, organization: &str) -> bool {
+    let organization = organization.trim();
+    !organization.is_empty()
+        && holder
+            .get(..organization.len())
+            .is_some_and(|h| h.eq_ignore_ascii_case(organization))
+        // "Fluke" shouldn't match "Flukeworks Inc."
+        && !holder[organization.len()..].starts_with(|c: char| c.is_alphanumeric())
+}
+
+/// Measures the comment at the start of the given contents, e.g. a description
+/// of the file, or a license stub without the word "copyright":
+/// consecutive comment lines, or the lines of a block comment.
+/// (A blank line or code ends it.)
+///
+/// Returns its length in bytes (0 if the contents don't start with a comment),
+/// or `None` if it's more than `max_lines` lines long.
+pub fn leading_comment_length<R: BufRead>(
+    mut reader: R,
+    max_lines: usize,
+    scan: &Scan,
+) -> io::Result<Option<usize>> {
+    let mut length = 0;
+    let mut lines = 0;
+    let mut block_closer: Option<&'static str> = None;
+
+    loop {
+        let mut line = Vec::new();
+        if !read_line(&mut reader, &mut line, scan.max_line_length)? {
+            break;
+        }
+        if block_closer.is_none()
+            && (line.trim_ascii().is_empty() || !is_comment_or_blank(&line, scan.styles))
+        {
+            break;
+        }
+        block_closer = track_block_comment(block_closer, &line, scan.styles);
+
+        lines += 1;
+        if lines > max_lines {
+            return Ok(None);
+        }
+        length += line.len();
+    }
+
+    Ok(Some(length))
+}
+
+/// The UTF-8 byte order mark some (mostly Windows) editors start files with
+pub const BOM: &str = "\u{feff}";
+
+/// Skips the byte order mark at the start of the given file contents,
+/// if there is one, and returns how many bytes that was.
+fn skip_bom<R: BufRead>(reader: &mut R) -> io::Result<usize> {
+    if reader.fill_buf()?.starts_with(BOM.as_bytes()) {
+        reader.consume(BOM.len());
+        Ok(BOM.len())
+    } else {
+        Ok(0)
+    }
+}
+
+/// Given the closer we were waiting for (if we were in a block comment)
+/// before `line`, returns the one we're waiting for after it.
+fn track_block_comment(
+    closer: Option<&'static str>,
+    line: &[u8],
+    styles: &[&CommentStyle],
+) -> Option<&'static str> {
+    match closer {
+        Some(c) if contains(line, c) => None,
+        Some(c) => Some(c),
+        None => block_opened_by(line, styles),
+    }
+}
+
+/// If the given line opens a block comment (in one of the given styles)
+/// without closing it, returns the closer that ends it.
+fn block_opened_by(line: &[u8], styles: &[&CommentStyle]) -> Option<&'static str> {
+    let trimmed = line.trim_ascii_start();
+    styles.iter().find_map(|style| {
+        let closer = style.closer?;
+        let rest = trimmed.strip_prefix(style.opener.as_bytes())?;
+        if contains(rest, closer) {
+            None
+        } else {
+            Some(closer)
+        }
+    })
+}
+
+/// Matches the given pattern against a line, as long as whatever comment
+/// marker the pattern found it starts with (if any) is one of the given
+/// styles'. The patterns take any style's, so that we can build them once.
+fn captures<'l>(
+    re: &bytes::Regex,
+    styles: &[&CommentStyle],
+    line: &'l [u8],
+) -> Option<bytes::Captures<'l>> {
+    re.captures(line).filter(|cap| {
+        cap.name("marker")
+            .is_none_or(|m| styles.iter().any(|s| s.marks(m.as_bytes())))
+    })
+}
+
+/// Is the given line blank, or a comment in one of the given styles?
+fn is_comment_or_blank(line: &[u8], styles: &[&CommentStyle]) -> bool {
+    captures(&COMMENT_OR_BLANK, styles, line).is_some()
+}
+
+/// Does the given line (of file contents) contain the given text?
+fn contains(line: &[u8], text: &str) -> bool {
+    line.windows(text.len()).any(|w| w == text.as_bytes())
+}
+
+/// Decodes part of a line we matched. (Whatever a pattern captures
+/// is UTF-8, but what's around it might not be.)
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Builds a notice starting with the given copyright line
+/// (which matched `re`) at `offset`.
+fn copyright_line_notice<R: BufRead>(
+    mut reader: R,
+    offset: usize,
+    first_line: Vec<u8>,
+    re: &bytes::Regex,
+    scan: &Scan,
+) -> io::Result<Notice> {
+    let cap = re.captures(&first_line).unwrap();
+    let opener = text(&cap[1]);
+    let has_years = !cap["years"].trim_ascii().is_empty();
+
+    let mut lines = vec![first_line];
+    let mut next_line = Vec::new();
+    let mut whole = read_line(&mut reader, &mut next_line, scan.max_line_length)?;
+
+    // Some banners put "Copyright" on its own line and the years
+    // (and holder) on the next one. If so, leave the first line be
+    // and update the years where they are.
+    if !has_years && whole {
+        if let Some(cap) = captures(&YEARS_LINE, scan.styles, &next_line) {
+            let lead = text(&cap[1]);
+            let start = offset + lines[0].len();
+            let lines = read_matching_lines(&mut reader, next_line, &YEARS_LINE, scan)?;
+            let mut notice = build_notice(start, lead.clone(), lead, &lines, &YEARS_LINE)?;
+            notice.line_start = offset;
+            return Ok(notice);
+        }
+    }
+
+    while whole && captures(re, scan.styles, &next_line).is_some() {
+        lines.push(next_line);
+        next_line = Vec::new();
+        whole = read_line(&mut reader, &mut next_line, scan.max_line_length)?;
+    }
+
+    // Keep SPDX notices in SPDX's format.
+    // (Bare lines in a block comment have no opener to put a space after.)
+    let space = if opener.trim().is_empty() { "" } else { " " };
+    let lead = if contains(&lines[0], "SPDX-FileCopyrightText:") {
+        format!("{}{}SPDX-FileCopyrightText:", opener, space)
+    } else {
+        format!("{}{}{}", opener, space, scan.lead)
+    };
+    let mut notice = build_notice(offset, lead, opener.clone(), &lines, re)?;
+
+    // If the notice lines close their comments (e.g. /* ... */),
+    // we need to keep doing that.
+    notice.closer = comment::from_opener(opener.trim_start())
+        .and_then(|style| style.closer)
+        .filter(|closer| {
+            lines
+                .iter()
+                .all(|l| l.trim_ascii_end().ends_with(closer.as_bytes()))
+        });
+
+    if let Some(closer) = notice.closer {
+        for (holder, license) in notice.holders.iter_mut().zip(&mut notice.licenses) {
+            // (It's at the end of the license text, if there is any.)
+            let text = license.as_mut().unwrap_or(holder);
+            let without_closer = text.trim_end_matches(closer).trim_end().len();
+            text.truncate(without_closer);
+        }
+    }
+
+    Ok(notice)
+}
+
+/// Starting with `first` (which matched `re`), reads lines until one doesn't
+/// match, and returns the ones that did.
+fn read_matching_lines<R: BufRead>(
+    reader: &mut R,
+    first: Vec<u8>,
+    re: &bytes::Regex,
+    scan: &Scan,
+) -> io::Result<Vec<Vec<u8>>> {
+    let mut lines = vec![first];
+    loop {
+        let mut line = Vec::new();
+        if !read_line(reader, &mut line, scan.max_line_length)?
+            || captures(re, scan.styles, &line).is_none()
+        {
+            break;
+        }
+        lines.push(line);
+    }
+    Ok(lines)
+}
+
+/// Builds a notice from the given lines (which matched `re`) at `start`,
+/// or fails if they aren't UTF-8, since we'd mangle them rewriting them.
+/// (Files that aren't UTF-8 are read as Latin-1, see `encoding`, so this
+/// only happens if they don't look like it until past the notice.)
+fn build_notice(
+    start: usize,
+    lead: String,
+    opener: String,
+    lines: &[Vec<u8>],
+    re: &bytes::Regex,
+) -> io::Result<Notice> {
+    if lines.iter().any(|l| std::str::from_utf8(l).is_err()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "its notice isn't UTF-8",
+        ));
+    }
+
+    // We don't want to mess with the newline (or trailing space)
+    // of the last notice line.
+    let (last, rest) = lines.split_last().unwrap();
+    let length = rest.iter().map(|l| l.len()).sum::<usize>() + last.trim_ascii_end().len();
+
+    let line_end = start + lines.iter().map(|l| l.len()).sum::<usize>();
+
+    let mut year_lists = Vec::with_capacity(lines.len());
+    let mut holders = Vec::with_capacity(lines.len());
+    let mut licenses = Vec::with_capacity(lines.len());
+    for line in lines {
+        let years = re.captures(line).unwrap().name("years").unwrap();
+        year_lists.push(text(years.as_bytes()));
+        let (holder, license) = split_license(text(&line[years.end()..]).trim());
+        holders.push(holder);
+        licenses.push(license);
+    }
+
+    Ok(Notice {
+        start,
+        length,
+        line_start: start,
+        line_end,
+        lead,
+        opener,
+        closer: None,
+        year_lists,
+        holders,
+        licenses,
+    })
+}
+
+/// Splits any license text (see `Notice::licenses`) off of the end of
+/// what follows a notice line's years.
+fn split_license(text: &str) -> (String, Option<String>) {
+    match TRAILING_LICENSE.find(text) {
+        Some(m) => (
+            text[..m.start()].trim_end().to_owned(),
+            Some(text[m.start()..].to_owned()),
+        ),
+        None => (text.to_owned(), None),
+    }
+}
+
+/// An existing `SPDX-License-Identifier:` line
+#[derive(Debug)]
+pub struct License {
+    /// Byte offset of the license expression
+    pub start: usize,
+
+    /// The license expression, e.g. `MIT OR Apache-2.0`
+    pub id: String,
+}
+
+/// Looks for an `SPDX-License-Identifier:` line in the comments
+/// at the top of the given file contents (in `scan.styles`),
+/// giving up at the first line of code.
+pub fn find_license<R: BufRead>(mut reader: R, scan: &Scan) -> io::Result<Option<License>> {
+    let mut offset = skip_bom(&mut reader)?;
+    let mut block_closer: Option<&'static str> = None;
+
+    loop {
+        let mut line = Vec::new();
+        if !read_line(&mut reader, &mut line, scan.max_line_length)? {
+            return Ok(None);
+        }
+
+        if let Some(cap) = LICENSE_LINE.captures(&line) {
+            let id = cap.get(1).unwrap();
+            return Ok(Some(License {
+                start: offset + id.start(),
+                id: text(id.as_bytes()),
+            }));
+        }
+
+        if block_closer.is_none() && !is_comment_or_blank(&line, scan.styles) {
+            return Ok(None);
+        }
+        block_closer = track_block_comment(block_closer, &line, scan.styles);
+
+        offset += line.len();
+    }
+}
+
+/// Parses a list of years and year ranges (e.g. `2015, 2017-2019`).
+///
+/// A mangled notice shouldn't take the whole run down with it,
+/// so we skip anything we can't make sense of, and return complaints
+/// about it alongside the years we could parse.
+pub fn parse_year_list(list: &str) -> (Vec<Year>, Vec<String>) {
+    lazy_static! {
+        static ref YEAR_OR_RANGE: Regex =
+            Regex::new(r"((\d{4})\s*[-–—]\s*(\d{4}))|(\d{4})").unwrap();
+    }
+
+    let mut years = Vec::new();
+    let mut complaints = Vec::new();
+
+    for cap in YEAR_OR_RANGE.captures_iter(list) {
+        match cap.get(1) {
+            // A single year:
+            None => {
+                if let Some(year) = parse_year(cap.get(4), &mut complaints) {
+                    years.push(year);
+                }
+            }
+            // A range of years (<yyyy>-<yyyy>):
+            Some(range) => {
+                let start = parse_year(cap.get(2), &mut complaints);
+                let end = parse_year(cap.get(3), &mut complaints);
+                let (start, end) = match (start, end) {
+                    (Some(s), Some(e)) => (s, e),
+                    _ => continue,
+                };
+
+                if start > end {
+                    complaints.push(format!(
+                        "ignoring backwards year range {:?}",
+                        range.as_str()
+                    ));
+                    continue;
+                }
+
+                // Every year in a valid range is valid too.
+                for i in start.get()..=end.get() {
+                    years.push(Year::new(i).unwrap());
+                }
+            }
+        };
+    }
+
+    (years, complaints)
+}
+
+fn parse_year(m: Option<Match>, complaints: &mut Vec<String>) -> Option<Year> {
+    let m = m?;
+    match m.as_str().parse() {
+        Ok(y) => {
+            let year = Year::new(y);
+            if year.is_none() {
+                complaints.push(format!("ignoring implausible year {}", y));
+            }
+            year
+        }
+        Err(e) => {
+            complaints.push(format!("couldn't parse year {:?}: {}", m.as_str(), e));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(path: &str, contents: &str) -> Option<Notice> {
+        let options = ScanOptions::new(StyleRegistry::new(&comment::DOUBLE_SLASH));
+        find_notice(contents.as_bytes(), &options.for_file(path)).unwrap()
+    }
+
+    #[test]
+    fn notice_after_banner_and_blank_line() {
+        let banner = "// Frobnicator: frobs widgets\n// (see frob.md)\n\n";
+        let contents = format!("{}// Copyright 2015, 2017 Acme Corp.\nint x;\n", banner);
+        let notice = find("frob.c", &contents).unwrap();
+        assert_eq!(notice.start, banner.len());
+        assert_eq!(notice.line_end, contents.find("int").unwrap());
+        assert_eq!(notice.opener, "//");
+        assert_eq!(notice.year_lists, ["2015, 2017 "]);
+        assert_eq!(notice.holders, ["Acme Corp."]);
+    }
+
+    #[test]
+    fn preprocessor_lines_arent_comments() {
+        let contents = "#include <stdio.h>\n#define V \"Copyright 2019 Acme\"\n";
+        assert!(find("v.c", contents).is_none());
+        // ...but they are in languages that comment with #.
+        let notice = find("v.py", "#!/usr/bin/env python\n# Copyright 2019 Acme\n").unwrap();
+        assert_eq!(notice.holders, ["Acme"]);
+    }
+
+    #[test]
+    fn string_literals_arent_notices() {
+        let contents = "static const char *v = \"Copyright 2019 Acme\";\n";
+        assert!(find("v.c", contents).is_none());
+        let contents = "/* Frobnicator */\nconst char *v =\n    \"Copyright 2019 Acme\";\n";
+        assert!(find("v.c", contents).is_none());
+    }
+
+    #[test]
+    fn unknown_languages_take_any_comment() {
+        let notice = find("notes.weird", "-- Copyright 2019 Acme\n").unwrap();
+        assert_eq!(notice.opener, "--");
+        let notice = find("notes.weird", "# Copyright 2019 Acme\n").unwrap();
+        assert_eq!(notice.opener, "#");
+    }
+
+    #[test]
+    fn mangled_years_are_skipped() {
+        let years = |list: &str| {
+            let (years, complaints) = parse_year_list(list);
+            let years: Vec<u16> = years.into_iter().map(Year::get).collect();
+            (years, complaints.len())
+        };
+        // (Other scripts' digits match \d, but don't parse.)
+        assert_eq!(years("2 019, 2020"), (vec![2020], 0));
+        assert_eq!(years("\u{662}\u{660}\u{661}\u{669}, 2020"), (vec![2020], 1));
+        assert_eq!(years("0000, 9999, 2020"), (vec![2020], 2));
+        assert_eq!(years("2019-0001, 2020"), (vec![2020], 1));
+        assert_eq!(years("2019-2017, 2020"), (vec![2020], 1));
+        assert_eq!(years("2018 - 2019"), (vec![2018, 2019], 0));
+
+        let notice = find("a.c", "// Copyright 0000-9999, 2 019 Acme\nint a;\n").unwrap();
+        assert_eq!(notice.holders, ["Acme"]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Gathers every copyright holder across the repo into a single NOTICE file
+//! (`copyrighter notice`), like the ones Apache-2.0 distributions ship.
+//!
+//! Each file counts for the holders its notice will name once it's updated:
+//! ours, with the years history (and its notice) gives it, or whoever its
+//! notice names if that's someone else's (since we'd leave it alone).
+//! Each holder then gets a line listing every year any of their files do,
+//! with runs of years as ranges, e.g. `Copyright © 2015,2017-2020 Acme`.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufReader};
+
+use log::error;
+use rayon::prelude::*;
+
+use crate::common::{Year, YearMap};
+use crate::encoding;
+use crate::notice::{find_notice, names_holder, parse_year_list, Notice, ScanOptions};
+use crate::update::{format_year_ranges, listed_years, NoticeOptions};
+
+/// Finds who holds the given files (with their years, as `update` would write
+/// them), and the years each holder's files list, by holder.
+pub fn collect_holders(years: &YearMap, options: &NoticeOptions) -> BTreeMap<String, Vec<Year>> {
+    let found: Vec<(String, Vec<Year>)> = years
+        .par_iter()
+        .flat_map(|(path, years)| match read_notice(path, &options.scan) {
+            Ok(notice) => file_holders(path, years, options, notice.as_ref()),
+            Err(e) => {
+                error!("Couldn't read {}: {}", path, e);
+                Vec::new()
+            }
+        })
+        .collect();
+
+    let mut holders: BTreeMap<String, Vec<Year>> = BTreeMap::new();
+    for (holder, mut years) in found {
+        holders.entry(holder).or_default().append(&mut years);
+    }
+    for years in holders.values_mut() {
+        years.sort();
+        years.dedup();
+        *years = options.fill_gaps(years);
+    }
+    holders.retain(|_, years| !years.is_empty());
+    holders
+}
+
+/// Finds the notice at the top of the given file, if it has one.
+pub fn read_notice(path: &str, scan: &ScanOptions) -> io::Result<Option<Notice>> {
+    find_notice(BufReader::new(encoding::open(path)?), &scan.for_file(path))
+}
+
+/// The holders the notice of the file at the given path (currently `notice`)
+/// will name, and the years each of them will list.
+pub fn file_holders(
+    path: &str,
+    years: &[Year],
+    options: &NoticeOptions,
+    notice: Option<&Notice>,
+) -> Vec<(String, Vec<Year>)> {
+    let ours = options.organizations_for(path);
+    // Holder lines that name one of ours (e.g. with "All rights reserved."
+    // after it) count for that organization, as do ones naming a name
+    // our (first) organization used to go by.
+    let old_name = |h: &str| {
+        options
+            .organization_years
+            .iter()
+            .any(|o| names_holder(h, &o.name))
+    };
+    let holder = |h: &str| {
+        let h = h.trim();
+        ours.iter()
+            .find(|o| names_holder(h, o))
+            .or_else(|| ours.first().filter(|_| old_name(h)))
+            .map_or_else(|| h.to_owned(), |o| o.clone())
+    };
+
+    let holders: Vec<(String, Vec<Year>)> = match notice {
+        // We'd leave someone else's notice as it is.
+        Some(n) if options.foreign_holder(path, &n.holders).is_some() => n
+            .holders
+            .iter()
+            .zip(&n.year_lists)
+            .map(|(h, list)| (holder(h), parse_year_list(list).0))
+            .collect(),
+        // We'd keep the holders of existing notices, and update their years
+        // (besides those of holders we share the file with, which keep theirs).
+        Some(n) if !options.force_organization && !options.authorship.contains_key(path) => n
+            .holders
+            .iter()
+            .map(|h| {
+                if options.names_ours(path, h) || !options.names_joint_holders(path, &n.holders) {
+                    (holder(h), options.fill_gaps(years))
+                } else {
+                    (holder(h), listed_years(n, h))
+                }
+            })
+            .collect(),
+        // Otherwise, the notice is ours.
+        _ => ours
+            .iter()
+            .enumerate()
+            .map(|(line, o)| {
+                (
+                    o.clone(),
+                    options.fill_gaps(options.line_years(path, line, years)),
+                )
+            })
+            .collect(),
+    };
+
+    // Our organizations' years go to whatever name they went by then.
+    holders
+        .into_iter()
+        .flat_map(|(h, years)| {
+            if ours.contains(&h) {
+                options.split_years(&h, &years)
+            } else {
+                vec![(h, years)]
+            }
+        })
+        .collect()
+}
+
+/// Renders a NOTICE file listing the given holders, the earliest first,
+/// after the given title (e.g. the name of the product), if any.
+/// Each line starts with the given lead, e.g. `Copyright ©`.
+pub fn render(title: Option<&str>, holders: &BTreeMap<String, Vec<Year>>, lead: &str) -> String {
+    let mut text = String::new();
+    if let Some(title) = title {
+        text.push_str(title);
+        text.push('\n');
+    }
+    let mut lines: Vec<(&String, &Vec<Year>)> = holders.iter().collect();
+    lines.sort_by_key(|(holder, years)| (years[0], *holder));
+    for (holder, years) in lines {
+        text.push_str(&format!("{} {} {}\n", lead, year_ranges(years), holder));
+    }
+    text
+}
+
+/// Lists the given (sorted) years, with runs of them as ranges,
+/// e.g. `2015,2017-2020`.
+pub fn year_ranges(years: &[Year]) -> String {
+    format_year_ranges(years, ",")
+}
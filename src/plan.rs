@@ -0,0 +1,95 @@
+//! Splits a run in two, for when history is read in one place and files are
+//! rewritten in another: `copyrighter analyze` works out each file's years
+//! and the edits that bring its notice up to date, and saves them to a plan,
+//! which `copyrighter apply` makes later (e.g. on another checkout,
+//! once someone has reviewed it).
+//!
+//! Each edit remembers a hash of what its file held,
+//! and is only made if the file still holds that.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::{Year, YearMap};
+use crate::error::Error;
+use crate::update::{Action, PlannedEdit, PlannedEdits, Report};
+
+/// Bump this whenever what we store (or what it means) changes.
+const VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct Plan {
+    version: u32,
+
+    /// The years we found for each file, sorted by path
+    years: BTreeMap<String, Vec<u16>>,
+
+    edits: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: String,
+
+    /// What the edit does to the file's notice
+    action: Action,
+
+    #[serde(flatten)]
+    edit: PlannedEdit,
+}
+
+/// Saves the years we found for each file,
+/// and the edits the report planned (see `update::Mode::Plan`).
+pub fn write(path: &str, years: &YearMap, report: &Report) -> Result<(), Error> {
+    let plan = Plan {
+        version: VERSION,
+        years: years
+            .iter()
+            .map(|(p, ys)| (p.clone(), ys.iter().map(|y| y.get()).collect()))
+            .collect(),
+        edits: report
+            .planned
+            .iter()
+            .map(|(p, action, edit)| Entry {
+                path: p.clone(),
+                action: *action,
+                edit: edit.clone(),
+            })
+            .collect(),
+    };
+
+    let json = serde_json::to_string_pretty(&plan).unwrap();
+    fs::write(path, json + "\n").map_err(Error::io(format!("write {}", path)))
+}
+
+/// Reads a plan saved by `write()`, returning the years it found for each file
+/// and the edits to make (see `update::apply_planned_edits()`).
+pub fn read(path: &str) -> Result<(YearMap, PlannedEdits), Error> {
+    let json = fs::read_to_string(path).map_err(Error::io(format!("read {}", path)))?;
+    let plan: Plan =
+        serde_json::from_str(&json).map_err(|e| Error::io(format!("parse {}", path))(e.into()))?;
+    if plan.version != VERSION {
+        return Err(Error::io(format!("read {}", path))(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "it's a version {} plan, and we only know version {}",
+                plan.version, VERSION
+            ),
+        )));
+    }
+
+    let years = plan
+        .years
+        .into_iter()
+        .map(|(p, ys)| (p, ys.into_iter().filter_map(Year::new).collect()))
+        .collect();
+    let edits = plan
+        .edits
+        .into_iter()
+        .map(|e| (e.path, e.action, e.edit))
+        .collect();
+    Ok((years, edits))
+}
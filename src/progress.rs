@@ -0,0 +1,46 @@
+//! Progress bars for the slow parts of a run (walking Git history and
+//! rewriting files), so users on huge repos know we haven't hung.
+
+use std::io::{self, IsTerminal};
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Should we draw progress bars at all?
+///
+/// They're drawn to stderr, so if that isn't a terminal
+/// (e.g., we're running in CI), they'd just be noise in the logs.
+pub fn should_show(quiet: bool) -> bool {
+    !quiet && io::stderr().is_terminal()
+}
+
+/// Makes a progress bar counting `len` files, or a hidden one if `show` is false.
+///
+/// The bar can be bumped from Rayon's worker threads with `inc()`; it keeps
+/// an atomic count and only redraws periodically.
+pub fn file_bar(len: usize, message: &'static str, show: bool) -> ProgressBar {
+    if !show {
+        return ProgressBar::hidden();
+    }
+
+    let bar = ProgressBar::with_draw_target(Some(len as u64), ProgressDrawTarget::stderr());
+    bar.set_style(
+        ProgressStyle::with_template("{msg:>20} [{bar:40}] {pos}/{len} files")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar.set_message(message);
+    bar
+}
+
+/// Makes a spinner counting commits, for when we're walking history
+/// and don't know how far back it goes, or a hidden one if `show` is false.
+pub fn commit_spinner(message: &'static str, show: bool) -> ProgressBar {
+    if !show {
+        return ProgressBar::hidden();
+    }
+
+    let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr());
+    bar.set_style(ProgressStyle::with_template("{msg:>20} {spinner} {pos} commits").unwrap());
+    bar.set_message(message);
+    bar
+}
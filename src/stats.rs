@@ -0,0 +1,121 @@
+//! Sums up the state of the repo's notices (`copyrighter report --stats`),
+//! for snapshots like the one legal asks for every year: how many files
+//! were first changed in each year, how many are missing notices or have
+//! out-of-date ones, who the notices name, and the years the files span.
+
+use std::collections::BTreeMap;
+
+use rayon::prelude::*;
+
+use crate::common::{Year, YearMap};
+use crate::notice::ScanOptions;
+use crate::notice_file::read_notice;
+use crate::update::{Action, Report};
+
+/// What `report --stats` prints
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub files: usize,
+
+    /// How many files were first changed in each year
+    pub first_years: BTreeMap<Year, usize>,
+
+    /// Files without a notice (that we'd add one to)
+    pub missing: usize,
+
+    /// Files whose notices are out of date
+    pub stale: usize,
+
+    /// Files whose notices are up to date
+    pub current: usize,
+
+    /// Files we left alone (see `Action::Skipped`)
+    pub skipped: usize,
+
+    /// Files we couldn't read
+    pub failed: usize,
+
+    /// How many files' notices (as they are now) name each holder
+    pub holders: BTreeMap<String, usize>,
+
+    /// The earliest and latest years any file was changed in
+    pub span: Option<(Year, Year)>,
+}
+
+/// Sums up the given files' years, and what the given report says
+/// we'd do to their notices.
+pub fn collect(years: &YearMap, report: &Report, scan: &ScanOptions) -> Stats {
+    let mut stats = Stats {
+        files: report.actions.len(),
+        ..Stats::default()
+    };
+
+    for (path, _) in &report.actions {
+        let file_years = match years.get(path) {
+            Some(y) if !y.is_empty() => y,
+            _ => continue,
+        };
+        let (first, last) = (file_years[0], file_years[file_years.len() - 1]);
+        *stats.first_years.entry(first).or_default() += 1;
+        stats.span = Some(match stats.span {
+            Some((start, end)) => (start.min(first), end.max(last)),
+            None => (first, last),
+        });
+    }
+
+    stats.missing = report.count(Action::Added);
+    stats.stale = report.count(Action::Updated);
+    stats.current = report.count(Action::Current);
+    stats.skipped = report.count(Action::Skipped);
+    stats.failed = report.count(Action::Failed);
+
+    // (Files we couldn't read are already among the report's failures.)
+    let holders: Vec<String> = report
+        .actions
+        .par_iter()
+        .filter_map(|(path, _)| read_notice(path, scan).ok().flatten())
+        .flat_map(|notice| notice.holders)
+        .collect();
+    for holder in holders {
+        *stats.holders.entry(holder.trim().to_owned()).or_default() += 1;
+    }
+
+    stats
+}
+
+/// Renders the given stats for people to read.
+pub fn render(stats: &Stats) -> String {
+    let mut text = format!("Files: {}\n", stats.files);
+    if let Some((start, end)) = stats.span {
+        text.push_str(&format!("Years: {}-{}\n", start, end));
+    }
+    for (what, count) in &[
+        ("Missing notices", stats.missing),
+        ("Out-of-date notices", stats.stale),
+        ("Current notices", stats.current),
+        ("Skipped", stats.skipped),
+        ("Couldn't be read", stats.failed),
+    ] {
+        text.push_str(&format!("{}: {}\n", what, count));
+    }
+
+    text.push_str("\nFiles by the year they were first changed:\n");
+    for (year, count) in &stats.first_years {
+        text.push_str(&format!("  {}  {}\n", year, count));
+    }
+
+    text.push_str("\nFiles by who their notices name:\n");
+    if stats.holders.is_empty() {
+        text.push_str("  (none)\n");
+    }
+    let width = stats.holders.keys().map(|h| h.chars().count()).max();
+    for (holder, count) in &stats.holders {
+        text.push_str(&format!(
+            "  {:<width$}  {}\n",
+            holder,
+            count,
+            width = width.unwrap_or(0)
+        ));
+    }
+    text
+}
@@ -0,0 +1,178 @@
+//! Multi-line notice templates (`--template`), for when a project wants
+//! a whole license boilerplate block at the top of each file,
+//! not just a copyright line.
+//!
+//! Templates are plain text with placeholders:
+//!
+//! - `{years}`: the list of years the file was changed
+//! - `{organization}`: the organization(s), and suffix (if any)
+//! - `{filename}`: the file's name, without its directory
+//! - `{spdx}`: the SPDX license expression given with `--spdx`
+//! - `{now_year}`: the year of this run (see `expand_placeholders()`)
+//!
+//! Each line is put in a comment of the file's style when rendered.
+
+use std::fs;
+use std::io;
+
+use regex::bytes::Regex;
+
+use crate::comment::CommentStyle;
+use crate::common::expand_placeholders;
+use crate::notice::BOM;
+
+/// What a template's placeholders are replaced with
+pub struct Fields<'a> {
+    pub years: &'a str,
+    pub organization: &'a str,
+    pub filename: &'a str,
+    pub spdx: &'a str,
+}
+
+#[derive(Debug)]
+pub struct Template {
+    lines: Vec<String>,
+}
+
+impl Template {
+    /// Reads a template from the given file.
+    pub fn load(path: &str) -> io::Result<Template> {
+        Ok(Template::new(&fs::read_to_string(path)?))
+    }
+
+    pub fn new(text: &str) -> Template {
+        let mut lines: Vec<String> = text.lines().map(|l| l.trim_end().to_owned()).collect();
+        // Trailing blank lines would just push code further down.
+        while lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        Template { lines }
+    }
+
+    /// Renders the template in the given comment style, without a trailing newline.
+    ///
+    /// Line comment styles get an opener on each line. Block comment styles
+    /// get the whole template in one comment, so a `/*` template looks like
+    ///
+    /// ```text
+    /// /*
+    ///  * Copyright 2019 Fluke Corporation
+    ///  */
+    /// ```
+    pub fn render(&self, fields: &Fields, style: &CommentStyle) -> String {
+        self.render_with(style, |line| {
+            expand_placeholders(line)
+                .replace("{years}", fields.years)
+                .replace("{organization}", fields.organization)
+                .replace("{filename}", fields.filename)
+                .replace("{spdx}", fields.spdx)
+        })
+    }
+
+    /// Finds an existing rendering of this template (with any fields,
+    /// from any year) in the given file contents, and returns its byte range.
+    /// Only renderings starting in the first `within` bytes count.
+    pub fn find(
+        &self,
+        contents: &[u8],
+        style: &CommentStyle,
+        within: usize,
+    ) -> Option<(usize, usize)> {
+        // Render the template with markers where the fields go,
+        // then swap the (escaped) markers for patterns that match any field.
+        const MARKER: &str = "\u{0}";
+        let pattern = self.render_with(style, |line| {
+            let mut line = line.to_owned();
+            for placeholder in &[
+                "{years}",
+                "{organization}",
+                "{filename}",
+                "{spdx}",
+                "{now_year}",
+            ] {
+                line = line.replace(placeholder, MARKER);
+            }
+            line
+        });
+        let pattern = regex::escape(&pattern)
+            .replace(MARKER, r"[^\n]*?")
+            .replace('\n', r"\r?\n");
+        let re = Regex::new(&format!(r"(?m)^{}[ \t]*\r?$", pattern)).unwrap();
+
+        // A byte order mark would keep a rendering at the top of the file
+        // from starting at the start of a line.
+        let bom = if contents.starts_with(BOM.as_bytes()) {
+            BOM.len()
+        } else {
+            0
+        };
+
+        re.find(&contents[bom..])
+            .filter(|m| bom + m.start() < within)
+            .map(|m| {
+                // Leave the last line's carriage return (if any) be.
+                let end = m.end() - usize::from(m.as_bytes().ends_with(b"\r"));
+                (bom + m.start(), bom + end)
+            })
+    }
+
+    fn render_with<F: Fn(&str) -> String>(&self, style: &CommentStyle, fill: F) -> String {
+        let lines = self
+            .lines
+            .iter()
+            .map(|l| comment(style.continuation, &fill(l)));
+
+        match style.closing_line() {
+            None => lines.collect::<Vec<_>>().join("\n"),
+            Some(end) => {
+                let mut block = vec![style.opener.to_owned()];
+                block.extend(lines);
+                block.push(end);
+                block.join("\n")
+            }
+        }
+    }
+}
+
+/// Puts `prefix` before the given line, with a space between them
+/// unless the line is blank.
+fn comment(prefix: &str, line: &str) -> String {
+    if line.is_empty() {
+        prefix.trim_end().to_owned()
+    } else {
+        format!("{} {}", prefix, line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comment;
+    use crate::common::current_year;
+
+    #[test]
+    fn now_year_is_this_year() {
+        let template = Template::new("Copyright {years} {organization}\nGenerated {now_year}\n");
+        let fields = Fields {
+            years: "2015-2017",
+            organization: "Acme",
+            filename: "a.c",
+            spdx: "",
+        };
+        let rendered = template.render(&fields, &comment::DOUBLE_SLASH);
+        assert_eq!(
+            rendered,
+            format!(
+                "// Copyright 2015-2017 Acme\n// Generated {}",
+                current_year()
+            )
+        );
+
+        // A rendering from another year is still this template.
+        let contents = "// Copyright 2015-2017 Acme\n// Generated 2019\nint a;\n";
+        assert_eq!(
+            template.find(contents.as_bytes(), &comment::DOUBLE_SLASH, 100),
+            Some((0, contents.find("\nint").unwrap()))
+        );
+    }
+}
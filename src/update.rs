@@ -1,90 +1,1959 @@
 //! Updates copyright headers based on the information gathered.
 
-use std::fs::{File, OpenOptions};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File, OpenOptions, Permissions};
 use std::io;
 use std::io::prelude::*;
+#[cfg(not(windows))]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+#[cfg(not(windows))]
 use std::ptr;
 
+use fs2::FileExt;
+use globset::GlobMatcher;
 use itertools::Itertools;
-use lazy_static::lazy_static;
+use log::debug;
+#[cfg(not(windows))]
 use memmap::MmapMut;
 use rayon::prelude::*;
-use regex::Regex;
+use serde::{Deserialize, Serialize};
 
-use crate::common::{Year, YearMap};
+use crate::backup;
+use crate::comment::CommentStyle;
+use crate::common::{count_files, PathSet, Year, YearMap};
+use crate::diff;
+use crate::encoding::{self, Encoding};
+use crate::error::Error;
+use crate::notice::*;
+use crate::progress;
+use crate::template::{Fields, Template};
+
+/// Everything that determines what our notices say and how they're written
+pub struct NoticeOptions {
+    /// Holders, each of which gets their own line
+    pub organizations: Vec<String>,
+
+    /// Holders for files matching certain globs, instead of `organizations`
+    pub path_organizations: Vec<PathOrganizations>,
+
+    /// Holders for each file going by who wrote it (`--per-author`),
+    /// instead of either of the above
+    pub authorship: HashMap<String, Authorship>,
+
+    /// Other names our organizations held the copyright under
+    /// in certain years (e.g. before a rename)
+    pub organization_years: Vec<OrganizationYears>,
+
+    /// Text following each holder
+    pub suffix: Option<String>,
+
+    /// What goes between listed years
+    pub year_separator: String,
+
+    /// How to find existing notices, and picks the comment style for new ones
+    pub scan: ScanOptions,
+
+    /// Rewrite notices in our format even if they already list
+    /// the right years and holders
+    pub normalize: bool,
+
+    /// Replace the holders of existing notices with ours,
+    /// instead of just updating their years
+    pub force_organization: bool,
+
+    /// Other holders whose notices we can update the years of.
+    /// Files with notices naming anyone else (besides our organizations)
+    /// are skipped, since they aren't ours to change.
+    pub allowed_holders: Vec<String>,
+
+    /// The SPDX license expression for `SPDX-License-Identifier:` lines,
+    /// if we should write them
+    pub license: Option<String>,
+
+    /// A multi-line template to stamp instead of single notice lines
+    pub template: Option<Template>,
+
+    /// Where new notices go
+    pub placement: Placement,
+
+    /// Which files' notices we touch
+    pub scope: Scope,
+
+    /// Keep writing notices whose years are listed with ranges
+    /// (like 2018-2024) that way, instead of listing each year
+    pub keep_ranges: bool,
+
+    /// Write years as ranges, taking gaps of up to this many years into them
+    /// (e.g. with 1, `2010,2011,2013` becomes `2010-2013`)
+    pub gap_tolerance: Option<u16>,
+}
+
+/// Where new notices go in a file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// The very first line (after any byte order mark),
+    /// even before a shebang or XML declaration
+    Top,
+
+    /// The first line, or the second if the first is a shebang
+    /// or XML declaration (which have to stay first)
+    AfterShebang,
+
+    /// After the comment the file starts with (past any shebang),
+    /// e.g. a description of the file or a license stub
+    AfterComment,
+}
+
+/// Which files' notices we touch, for rolling notices out a step at a time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Add notices to files without one, and update the rest
+    All,
+
+    /// Only add notices to files without one (`--add-missing-only`)
+    AddMissing,
+
+    /// Only update existing notices (`--update-only`)
+    UpdateExisting,
+}
+
+impl Scope {
+    /// Do we touch files that have a notice (or don't)?
+    fn covers(self, has_notice: bool) -> bool {
+        match self {
+            Scope::All => true,
+            Scope::AddMissing => !has_notice,
+            Scope::UpdateExisting => has_notice,
+        }
+    }
+}
+
+/// The holders for files matching a glob, e.g. a subtree owned by a subsidiary
+pub struct PathOrganizations {
+    pub glob: GlobMatcher,
+    pub organizations: Vec<String>,
+}
+
+/// The years an organization held the copyright under some name
+pub struct OrganizationYears {
+    pub name: String,
+
+    /// The first year it went by the name, if it did from the start
+    pub first: Option<Year>,
+
+    /// The last year it went by the name, if it still does
+    pub last: Option<Year>,
+}
+
+impl OrganizationYears {
+    fn contains(&self, year: Year) -> bool {
+        self.first.is_none_or(|f| f <= year) && self.last.is_none_or(|l| year <= l)
+    }
+}
+
+/// Who holds the copyright on a file, going by who wrote it
+pub struct Authorship {
+    /// Each holder, in the order they first changed the file
+    pub holders: Vec<String>,
+
+    /// The years each holder changed the file
+    pub years: Vec<Vec<Year>>,
+}
+
+impl NoticeOptions {
+    /// The holders for the file at the given path: the ones its authors
+    /// work for (with `--per-author`), the ones for the most specific
+    /// (i.e., longest) glob matching it, or the usual ones otherwise.
+    pub fn organizations_for(&self, path: &str) -> &[String] {
+        if let Some(authorship) = self.authorship.get(path) {
+            return &authorship.holders;
+        }
+        self.path_organizations
+            .iter()
+            .filter(|p| p.glob.is_match(path))
+            .max_by_key(|p| p.glob.glob().glob().len())
+            .map_or(&self.organizations, |p| &p.organizations)
+    }
+
+    /// What each of our holder lines say for the file at the given path
+    /// (see `holder()`)
+    fn holders_for(&self, path: &str) -> Vec<String> {
+        self.organizations_for(path)
+            .iter()
+            .map(|org| self.holder(org))
+            .collect()
+    }
+
+    /// The holders an existing notice of the file at the given path keeps:
+    /// each of our organizations once (see `collapse_holders()`), as the notice
+    /// words it. Only with `--organization-suffix` does what follows one of
+    /// our organizations there change, to the suffix.
+    fn kept_holders(&self, path: &str, holders: Vec<String>) -> Vec<String> {
+        let ours = self.organizations_for(path);
+        let suffix = match &self.suffix {
+            Some(s) => s,
+            None => return collapse_holders(holders, ours),
+        };
+        collapse_holders(holders, ours)
+            .into_iter()
+            .map(
+                |holder| match ours.iter().find(|o| names_holder(&holder, o)) {
+                    // (Keep the notice's spelling of the organization.)
+                    Some(organization) => {
+                        format!("{} {}", &holder[..organization.trim().len()], suffix)
+                    }
+                    None => holder,
+                },
+            )
+            .collect()
+    }
+
+    /// What a holder line for the given organization says:
+    /// the organization, then the suffix (if any)
+    fn holder(&self, organization: &str) -> String {
+        match &self.suffix {
+            Some(s) => format!("{} {}", organization, s),
+            None => organization.to_owned(),
+        }
+    }
+
+    /// The years the given line of our notice for the file at the given path
+    /// lists: its holder's own (with `--per-author`), or the file's otherwise.
+    pub(crate) fn line_years<'a>(
+        &'a self,
+        path: &str,
+        line: usize,
+        years: &'a [Year],
+    ) -> &'a [Year] {
+        match self.authorship.get(path) {
+            Some(authorship) => &authorship.years[line],
+            None => years,
+        }
+    }
+
+    /// Fills in the gaps between the given (sorted) years that are short enough
+    /// for `gap_tolerance`, if we have one.
+    pub fn fill_gaps(&self, years: &[Year]) -> Vec<Year> {
+        let tolerance = match self.gap_tolerance {
+            Some(t) => t,
+            None => return years.to_vec(),
+        };
+        let mut filled: Vec<Year> = Vec::with_capacity(years.len());
+        for &y in years {
+            if let Some(&last) = filled.last() {
+                let gap = (last.get() + 1)..y.get();
+                if !gap.is_empty() && gap.len() <= tolerance as usize {
+                    filled.extend(gap.filter_map(Year::new));
+                }
+            }
+            filled.push(y);
+        }
+        filled
+    }
+
+    /// Formats the given years for a notice: as ranges (with gaps filled)
+    /// if we have a `gap_tolerance`, or one by one otherwise.
+    pub fn format_years(&self, years: &[Year]) -> String {
+        match self.gap_tolerance {
+            Some(_) => format_year_ranges(&self.fill_gaps(years), &self.year_separator),
+            None => format_year_list(years, &self.year_separator),
+        }
+    }
+
+    /// Splits the given years of the given organization between the names
+    /// it went by (see `organization_years`), earliest first.
+    /// Years outside all of their ranges go to the organization's current name.
+    pub fn split_years(&self, organization: &str, years: &[Year]) -> Vec<(String, Vec<Year>)> {
+        let mut names: Vec<(String, Vec<Year>)> = Vec::new();
+        for &y in years {
+            let name = self
+                .organization_years
+                .iter()
+                .find(|o| o.contains(y))
+                .map_or(organization, |o| &o.name);
+            match names.last_mut() {
+                Some((n, years)) if n == name => years.push(y),
+                _ => names.push((name.to_owned(), vec![y])),
+            }
+        }
+        names
+    }
+
+    /// What the given line of our notice for the file at the given path
+    /// says after "Copyright": the years it lists, then the holder
+    /// (and whatever follows it).
+    ///
+    /// Lines for one of our organizations that went by other names list
+    /// the years it held the copyright under each of them, like
+    /// `2017,2018 OldCo, 2019,2020 NewCo`, so their holder lists the rest.
+    fn line(&self, path: &str, line: usize, holder: &str, years: &[Year]) -> (Vec<Year>, String) {
+        let years = &self.fill_gaps(self.line_years(path, line, years));
+        if self.organization_years.is_empty() {
+            return (years.to_vec(), holder.to_owned());
+        }
+
+        // (Lines naming one of the old names are for our first organization.)
+        let ours = self.organizations_for(path);
+        let organization = ours.iter().find(|o| names_holder(holder, o)).or_else(|| {
+            self.organization_years
+                .iter()
+                .any(|o| names_holder(holder, &o.name))
+                .then(|| ours.first())
+                .flatten()
+        });
+        let organization = match organization {
+            Some(o) => o,
+            None => return (years.to_vec(), holder.to_owned()),
+        };
+
+        let names = self.split_years(organization, years);
+        let (first_years, mut text) = match names.split_first() {
+            Some(((name, years), rest)) => {
+                let rest = rest
+                    .iter()
+                    .map(|(name, years)| format!(", {} {}", self.format_years(years), name));
+                (years.clone(), name.clone() + &rest.collect::<String>())
+            }
+            None => (Vec::new(), organization.clone()),
+        };
+        if let Some(s) = &self.suffix {
+            text.push(' ');
+            text.push_str(s);
+        }
+        (first_years, text)
+    }
+
+    /// Finds the first of the given holders (from a file's notice)
+    /// that isn't one of ours (or allowed), which means we shouldn't touch the file.
+    pub(crate) fn foreign_holder<'a>(&self, path: &str, holders: &'a [String]) -> Option<&'a str> {
+        if self.force_organization {
+            // We were told to replace them.
+            return None;
+        }
+
+        holders.iter().map(|h| h.trim()).find(|holder| {
+            !holder.is_empty()
+                && !self.names_ours(path, holder)
+                && !self.allowed_holders.iter().any(|o| names_holder(holder, o))
+        })
+    }
+
+    /// Whether the given holders (from the notice of the file at the given path)
+    /// name both one of ours and someone else, who shares the copyright with us.
+    pub(crate) fn names_joint_holders(&self, path: &str, holders: &[String]) -> bool {
+        let (ours, others): (Vec<&String>, Vec<&String>) = holders
+            .iter()
+            .filter(|h| !h.trim().is_empty())
+            .partition(|h| self.names_ours(path, h));
+        !ours.is_empty() && !others.is_empty()
+    }
+
+    /// Whether the given holder (from the notice of the file at the given path)
+    /// is one of our organizations, or a name one went by.
+    pub(crate) fn names_ours(&self, path: &str, holder: &str) -> bool {
+        let old_names = self.organization_years.iter().map(|o| &o.name);
+        // (Notices we wrote before going by who wrote each file are ours too.)
+        let usual: &[String] = if self.authorship.contains_key(path) {
+            &self.organizations
+        } else {
+            &[]
+        };
+        self.organizations_for(path)
+            .iter()
+            .chain(usual)
+            .chain(old_names)
+            .any(|o| names_holder(holder.trim(), o))
+    }
+}
+
+/// Drops the holder lines naming one of our organizations that an earlier
+/// line already named, so that each organization gets a single line.
+/// (Files sometimes pick up a second notice line for the same holder
+/// when someone adds one instead of updating the first.)
+fn collapse_holders(holders: Vec<String>, ours: &[String]) -> Vec<String> {
+    let mut seen = Vec::new();
+    holders
+        .into_iter()
+        .filter(
+            |holder| match ours.iter().find(|o| names_holder(holder, o)) {
+                Some(o) if seen.contains(&o) => false,
+                Some(o) => {
+                    seen.push(o);
+                    true
+                }
+                None => true,
+            },
+        )
+        .collect()
+}
+
+/// What to do with the notices we come up with
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Rewrite files
+    Write(WriteStrategy),
+
+    /// Come up with a diff of the changes we'd make (see `Report::patch`),
+    /// but don't make them
+    DryRun,
+
+    /// Just find out which files need changes
+    Check,
+
+    /// Work out the edits we'd make (see `Report::planned`) so that they
+    /// can be made later (see `apply_planned_edits()`), but don't make them
+    Plan,
+}
+
+impl Mode {
+    /// What doing `action` (e.g. "update") to a file amounts to in this mode,
+    /// for saying what we couldn't do when something goes wrong:
+    /// only `Write` changes anything.
+    fn verb(self, action: &'static str) -> &'static str {
+        match self {
+            Mode::Write(_) => action,
+            Mode::Check => "check",
+            Mode::DryRun | Mode::Plan => "read",
+        }
+    }
+}
+
+/// How we rewrite files
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WriteStrategy {
+    /// Slide the file's contents around in place to make room for the new
+    /// notice. Fast, but a file could be mangled if we die partway through.
+    InPlace,
+
+    /// Write the new contents to a temporary file next to the original,
+    /// then rename it over the original.
+    Atomic,
+}
+
+/// What happened to the files we were given
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Files whose notices needed changing, sorted
+    pub changed: Vec<String>,
+
+    /// What went wrong with the files we couldn't read (or write)
+    pub failures: Vec<(String, Error)>,
+
+    /// Files we left alone because their notices name someone else,
+    /// along with who
+    pub skipped: Vec<(String, String)>,
+
+    /// Files we left alone because they look binary
+    pub binary: Vec<String>,
+
+    /// Files we left alone because they say they're generated
+    pub generated: Vec<String>,
+
+    /// What we did (or would do) to each file, sorted by path
+    pub actions: Vec<(String, Action)>,
+
+    /// For dry runs, a unified diff of every change we'd make
+    /// (sorted by path), which `git apply` can make later
+    pub patch: String,
+
+    /// For `Mode::Plan`, the edit each file needs and what it does
+    /// to the file's notice, sorted by path
+    pub planned: PlannedEdits,
+}
+
+impl Report {
+    /// How many files we did the given thing to
+    pub fn count(&self, action: Action) -> usize {
+        self.actions.iter().filter(|(_, a)| *a == action).count()
+    }
+
+    /// Takes what `applied` (from `apply_planned_edits()` on some of this
+    /// report's planned edits) says happened to files, instead of what we
+    /// planned for them. Files whose edits weren't applied were left alone,
+    /// so they count as skipped.
+    pub fn settle(mut self, applied: Report) -> Report {
+        let done: HashMap<&str, Action> = applied
+            .actions
+            .iter()
+            .map(|(p, a)| (p.as_str(), *a))
+            .collect();
+        let planned: HashSet<&str> = self.planned.iter().map(|(p, ..)| p.as_str()).collect();
+        for (path, action) in &mut self.actions {
+            if planned.contains(path.as_str()) {
+                *action = done.get(path.as_str()).copied().unwrap_or(Action::Skipped);
+            }
+        }
+
+        self.changed = applied.changed;
+        self.failures.extend(applied.failures);
+        self.failures.sort_by(|a, b| a.0.cmp(&b.0));
+        self.planned.clear();
+        self
+    }
+
+    /// A line summarizing what we did, e.g.
+    /// "12 files: 2 notices added, 3 updated, 7 already current"
+    pub fn summary(&self) -> String {
+        let counts = [
+            (Action::Added, "notices added"),
+            (Action::Updated, "updated"),
+            (Action::Removed, "removed"),
+            (Action::Current, "already current"),
+            (Action::Skipped, "skipped"),
+            (Action::Failed, "errors"),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .map(|&(action, what)| (self.count(action), what))
+            .filter(|&(n, _)| n > 0)
+            .map(|(n, what)| format!("{} {}", n, what))
+            .collect();
+
+        if parts.is_empty() {
+            "No files to examine".to_owned()
+        } else {
+            format!("{}: {}", count_files(self.actions.len()), parts.join(", "))
+        }
+    }
+}
+
+/// What we did (or would do) to a single file's notice
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// It didn't have one, so we added one.
+    Added,
+
+    Updated,
+
+    /// It was stripped.
+    Removed,
+
+    /// It was already up to date (or there wasn't one to strip),
+    /// or it's out of the `Scope` we were given.
+    Current,
+
+    /// It names a holder besides us (or looks binary or generated),
+    /// so we left it alone.
+    Skipped,
+
+    /// We couldn't read or write the file.
+    Failed,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // (The same names we give them in JSON)
+        f.write_str(match self {
+            Action::Added => "added",
+            Action::Updated => "updated",
+            Action::Removed => "removed",
+            Action::Current => "current",
+            Action::Skipped => "skipped",
+            Action::Failed => "failed",
+        })
+    }
+}
+
+/// What happened to a single file
+enum Outcome {
+    Unchanged,
+
+    /// The file needed changes. For dry runs, this holds their diff.
+    Changed(Option<String>),
+
+    /// The file needs the given edit, which we'll make later.
+    Planned(PlannedEdit),
+
+    /// The file's notice names the given holder, who isn't us.
+    Skipped(String),
+
+    /// The file looks binary.
+    Binary,
+
+    /// The file says it was generated.
+    Generated,
+
+    Failed(Error),
+}
+
+/// A change to a file: replace `old_length` bytes at `start` with `text`.
+/// (For UTF-16 files, that's in the UTF-8 we decode them to;
+/// see `encoding::utf8_view()`.)
+#[derive(Debug)]
+pub struct Edit {
+    pub start: usize,
+    pub old_length: usize,
+    pub text: String,
+}
+
+/// An `Edit` in terms of the bytes of the file itself
+struct EncodedEdit {
+    start: usize,
+    old_length: usize,
+    bytes: Vec<u8>,
+}
+
+/// An edit `Mode::Plan` came up with, along with the SHA-1 of what the file
+/// held (as hex), so that it's only made later if the file hasn't changed.
+/// (Checking just the text the edit replaces isn't enough: an edit that adds
+/// a notice replaces nothing, and would add a second one to a file
+/// that got its notice some other way in the meantime.)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlannedEdit {
+    pub start: usize,
+    pub old_length: usize,
+    pub text: String,
+    pub sha1: String,
+}
+
+/// Fingerprints a file's contents for a `PlannedEdit`.
+fn contents_sha1(contents: &[u8]) -> String {
+    sha1_smol::Sha1::from(contents).digest().to_string()
+}
+
+/// Each file's planned edit, and what it does to the file's notice
+pub type PlannedEdits = Vec<(String, Action, PlannedEdit)>;
+
+pub fn update_headers(
+    map: &YearMap,
+    options: &NoticeOptions,
+    mode: Mode,
+    files: &FileOptions,
+    show_progress: bool,
+) -> Report {
+    let bar = progress::file_bar(map.len(), "Updating notices", show_progress);
 
-pub fn update_headers(map: &YearMap, organization: &str) {
     // Let's paralellize! I'm assuming this process will be largely bottlenecked
     // by the I/O of actually reading the files, but we can let the OS'es I/O
     // scheduler figure that out.
-    map.par_iter().for_each(|(k, v)| {
-        let result = update_file(&k, v, &organization);
-        match result {
-            Ok(()) => { /* Everything worked, nothing to do */ }
-            Err(e) => eprintln!("Error updating {}: {}", k, e),
+    let outcomes: Vec<(&String, Outcome, Action)> = map
+        .par_iter()
+        .map(|(k, v)| {
+            let mut found = Findings::new();
+            let result = edit_file(k, mode, files, |fh| {
+                plan_file_update(k, v, options, files, fh, &mut found)
+            });
+            bar.inc(1);
+            let change = found.change;
+            (k, found.outcome(k, mode.verb("update"), result), change)
+        })
+        .collect();
+
+    bar.finish_and_clear();
+
+    report(outcomes)
+}
+
+/// Updates (or adds) the notice in the given contents of the file at `path`
+/// (e.g. as it's being staged, for a Git clean filter) the way
+/// `update_headers()` would update the file itself, and returns the new
+/// contents. Contents we'd leave alone (see the report) come back unchanged.
+pub fn update_contents(
+    path: &str,
+    years: &[Year],
+    options: &NoticeOptions,
+    files: &FileOptions,
+    contents: Vec<u8>,
+) -> (Vec<u8>, Report) {
+    let path = path.to_owned();
+    let mut found = Findings::new();
+    let mut updated = None;
+
+    // Everything we look at contents with works on files (of UTF-8).
+    let encoding = Encoding::detect(&contents);
+    let result = tempfile::tempfile().and_then(|mut fh| {
+        let decoded = encoding.decode(contents.clone())?;
+        fh.write_all(&decoded)?;
+        fh.seek(io::SeekFrom::Start(0))?;
+        let edit = match plan_file_update(&path, years, options, files, &fh, &mut found)? {
+            Some(e) => e,
+            None => return Ok(Outcome::Unchanged),
+        };
+
+        let mut new = decoded;
+        new.splice(edit.start..edit.start + edit.old_length, edit.text.bytes());
+        let new = encoding.encode(&new)?;
+        // A rewrite that comes out the same doesn't count.
+        if new == contents {
+            return Ok(Outcome::Unchanged);
         }
+        updated = Some(new);
+        Ok(Outcome::Changed(None))
     });
+
+    let change = found.change;
+    let outcome = found.outcome(&path, "update", result);
+    let report = report(vec![(&path, outcome, change)]);
+    (updated.unwrap_or(contents), report)
 }
 
-/// Update the existing copyright notice of a file, or tack on a new one.
-fn update_file(path: &str, years: &[Year], organization: &str) -> io::Result<()> {
-    // Open the file with read and write perms.
-    let mut fh = OpenOptions::new().read(true).write(true).open(path)?;
+/// What planning a file's update (see `plan_file_update()`) found out about it,
+/// besides the edit it needs
+struct Findings {
+    /// What updating the file does to its notice
+    change: Action,
 
-    // Read in the existing first line (so we can look for an existing notice).
-    let mut first_line_buff = String::new();
-    {
-        let mut br = io::BufReader::new(&fh);
-        br.read_line(&mut first_line_buff)?;
+    /// Who its notice names, if that isn't us
+    foreign: Option<String>,
+
+    binary: bool,
+    generated: bool,
+}
+
+impl Findings {
+    fn new() -> Self {
+        Self {
+            change: Action::Updated,
+            foreign: None,
+            binary: false,
+            generated: false,
+        }
     }
 
-    // We don't want to mess with the newline (or trailing space).
-    let old_first_line = first_line_buff.trim_right();
+    /// What happened to the file at `path`, given how editing it went
+    /// (and what we were doing to it, for `Outcome::Failed`)
+    fn outcome(self, path: &str, verb: &str, result: io::Result<Outcome>) -> Outcome {
+        match (result, self.foreign) {
+            (Ok(_), _) if self.binary => Outcome::Binary,
+            (Ok(_), _) if self.generated => Outcome::Generated,
+            (Ok(_), Some(holder)) => Outcome::Skipped(holder),
+            (Ok(o), None) => o,
+            (Err(e), _) => Outcome::Failed(Error::io(format!("{} {}", verb, path))(e)),
+        }
+    }
+}
 
-    lazy_static! {
-        static ref COPYRIGHT_OPENER: Regex = Regex::new(r"^(\s*/[/*]).*[Cc]opyright").unwrap();
+/// Comes up with the edit a file needs (see `plan_update()`), unless it's one
+/// we leave alone: a binary or generated file, or one whose notice names
+/// someone else. What we find out along the way goes in `found`.
+fn plan_file_update(
+    path: &str,
+    years: &[Year],
+    options: &NoticeOptions,
+    files: &FileOptions,
+    mut fh: &File,
+    found: &mut Findings,
+) -> io::Result<Option<Edit>> {
+    if files.is_binary(path, fh)? {
+        found.binary = true;
+        return Ok(None);
+    }
+    let scan = options.scan.for_file(path);
+    fh.seek(io::SeekFrom::Start(0))?;
+    if files.is_generated(fh, &scan)? {
+        found.generated = true;
+        return Ok(None);
+    }
+    fh.seek(io::SeekFrom::Start(0))?;
+    // Files without a notice get one added; the rest get theirs updated,
+    // unless they name someone else. (Or we were told to do just one of those.)
+    let old = find_notice(io::BufReader::new(fh), &scan)?;
+    if !options.scope.covers(old.is_some()) {
+        return Ok(None);
     }
+    match &old {
+        None => found.change = Action::Added,
+        Some(old) => {
+            found.foreign = options
+                .foreign_holder(path, &old.holders)
+                .map(|h| h.to_owned());
+            if found.foreign.is_some() {
+                return Ok(None);
+            }
+        }
+    }
+    plan_update(path, years, options, &scan, old, fh)
+}
+
+/// Comes up with the edit that updates the existing copyright notice
+/// of a file (`old_notice`, as `find_notice()` found it), or tacks on
+/// a new one. Returns `None` if the file's notice is already up to date.
+///
+/// Existing `@copyright` doc comment tags are updated in place.
+/// Each organization gets its own notice line, in the order given,
+/// followed by the suffix (if any).
+/// If we're given an SPDX license, its `SPDX-License-Identifier:` line
+/// is updated too, or added after the notice.
+///
+/// If we're given a template, files get that instead (see `plan_template()`).
+fn plan_update(
+    path: &str,
+    years: &[Year],
+    options: &NoticeOptions,
+    scan: &Scan,
+    mut old_notice: Option<Notice>,
+    mut fh: &File,
+) -> io::Result<Option<Edit>> {
+    // Copyright lines for our organizations further down get folded into
+    // the notice. (Their years are already among the ones we were given.)
+    let duplicates: Vec<RepeatedLine> = match &old_notice {
+        Some(old) => {
+            fh.seek(io::SeekFrom::Start(0))?;
+            let ours = options.organizations_for(path);
+            find_repeated_lines(io::BufReader::new(fh), old, scan)?
+                .into_iter()
+                .filter(|line| ours.iter().any(|o| names_holder(&line.holder, o)))
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let old_license = match options.license {
+        Some(_) => {
+            fh.seek(io::SeekFrom::Start(0))?;
+            find_license(io::BufReader::new(fh), scan)?
+        }
+        None => None,
+    };
 
-    let mut new_first_line: String;
-    let replacing_existing_notice: bool;
+    // A license tag on a notice line (e.g. `// Copyright 2019 Acme,
+    // SPDX-License-Identifier: MIT`) is part of that line's license text,
+    // which we keep as it is when rewriting the line. So if we'd rewrite it,
+    // the new license has to go there, instead of in an edit of its own.
+    let license_in_notice = match (&mut old_notice, &old_license, &options.license) {
+        (Some(notice), Some(old), Some(id)) if notice.covers(old.start) && old.id != *id => {
+            for license in notice.licenses.iter_mut().filter_map(Option::as_mut) {
+                if let Some(rest) = license.strip_suffix(&old.id) {
+                    if rest.trim_end().ends_with("SPDX-License-Identifier:") {
+                        *license = format!("{}{}", rest, id);
+                    }
+                }
+            }
+            true
+        }
+        _ => false,
+    };
+
+    let mut first_line = Vec::new();
+    fh.seek(io::SeekFrom::Start(0))?;
+    let whole_line = read_line(
+        &mut io::BufReader::new(fh),
+        &mut first_line,
+        scan.max_line_length,
+    )?;
+
+    // A byte order mark has to stay at the very start of the file,
+    // so look past it.
+    let bom = if first_line.starts_with(BOM.as_bytes()) {
+        BOM.len()
+    } else {
+        0
+    };
+    let first_text = String::from_utf8_lossy(&first_line[bom..]);
+
+    // New notices go at the top of the file (after any BOM),
+    // unless its first line has to stay there (or we're told otherwise).
+    let after_first = if whole_line && must_stay_first(&first_line[bom..]) {
+        first_line.len()
+    } else {
+        bom
+    };
+    let top = match options.placement {
+        Placement::Top => bom,
+        Placement::AfterShebang => after_first,
+        Placement::AfterComment => {
+            // Past the comment, we'd need to find the notice there next time.
+            // If we couldn't, put it at the top instead.
+            let before = if after_first > bom { 1 } else { 0 };
+            let max_lines = scan.lines.saturating_sub(before + 1);
+            fh.seek(io::SeekFrom::Start(after_first as u64))?;
+            match leading_comment_length(io::BufReader::new(fh), max_lines, scan)? {
+                Some(length) => after_first + length,
+                None => after_first,
+            }
+        }
+    };
 
-    match COPYRIGHT_OPENER.captures(old_first_line) {
-        // If there's an existing copyright notice, update that.
-        Some(capture) => {
-            // Preserve the existing // or /* and following whitespace.
-            new_first_line = capture.get(1).unwrap().as_str().to_owned();
-            replacing_existing_notice = true;
+    // Whatever comes before a new notice (e.g. a shebang) might not
+    // end its last line.
+    let line_ended = top == bom || {
+        let mut last = [0];
+        fh.seek(io::SeekFrom::Start(top as u64 - 1))?;
+        fh.read_exact(&mut last)?;
+        last[0] == b'\n'
+    };
+
+    // Match the file's line endings, going by its first line.
+    let newline = if first_line.ends_with(b"\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    // A block we rendered from the template before gets rendered again.
+    // (Files with some other notice have it updated as usual,
+    // so that they don't end up with two.)
+    if let Some(template) = &options.template {
+        let style = options.scan.styles.detect(path, &first_text);
+        let mut contents = Vec::new();
+        fh.seek(io::SeekFrom::Start(0))?;
+        fh.read_to_end(&mut contents)?;
+
+        let window = contents
+            .split_inclusive(|b| *b == b'\n')
+            .take(scan.lines)
+            .map(|l| l.len())
+            .sum();
+        let found = template.find(&contents, style, window);
+        if found.is_some() || old_notice.is_none() {
+            return Ok(plan_template(
+                path, years, options, template, style, found, top, newline, &contents,
+            ));
         }
-        // Otherwise we'll add one.
+    }
+
+    // Preserve the existing opener (e.g., the // or /* and surrounding
+    // whitespace) and closer if there is one. Otherwise, figure out how
+    // comments are written in this file.
+    let (opener, closer) = match &old_notice {
+        Some(old) => (old.opener.clone(), old.closer),
         None => {
-            new_first_line = "//".to_string();
-            replacing_existing_notice = false;
+            let style = options.scan.styles.detect(path, &first_text);
+            (style.opener.to_owned(), style.closer)
         }
     };
 
-    new_first_line.push_str(" Copyright © ");
-    // Insert a comma-separated list of years modified.
-    // TODO: Also allow dashed ranges.
-    new_first_line.push_str(&years.into_iter().map(|y| y.to_string()).join(","));
-    new_first_line.push(' ');
-    new_first_line.push_str(organization);
+    let mut notice_edit = plan_notice(
+        path,
+        years,
+        options,
+        old_notice.as_ref(),
+        top,
+        &opener,
+        closer,
+    );
+
+    // If the file is just a shebang (or whatever) without a newline,
+    // we need to end that line before we can start ours.
+    if let Some(edit) = &mut notice_edit {
+        if edit.old_length == 0 && !line_ended {
+            edit.text.insert(0, '\n');
+        }
+    }
 
-    if !replacing_existing_notice {
-        // We need a newline if we're creating our own notice.
-        new_first_line.push('\n');
-        // Slide the existing contents forward, making way for the new notice.
-        slide_file_contents(&fh, 0, new_first_line.len() as isize)?;
+    let license_edit = match (&options.license, old_license) {
+        (None, _) => None,
+        (Some(_), Some(_)) if license_in_notice && notice_edit.is_some() => None,
+        (Some(id), Some(old)) if old.id == *id => None,
+        // Swap the old license for the new one.
+        (Some(id), Some(old)) => Some(Edit {
+            start: old.start,
+            old_length: old.id.len(),
+            text: id.clone(),
+        }),
+        // Add a license line after the notice.
+        (Some(id), None) => {
+            let line = comment_line(&opener, &format!("SPDX-License-Identifier: {}", id), closer);
+            Some(match &old_notice {
+                Some(old) => Edit {
+                    start: old.start + old.length,
+                    old_length: 0,
+                    text: format!("\n{}", line),
+                },
+                None => Edit {
+                    start: top,
+                    old_length: 0,
+                    text: format!("{}\n", line),
+                },
+            })
+        }
+    };
+
+    let removals = duplicates.iter().map(|line| Edit {
+        start: line.start,
+        old_length: line.end - line.start,
+        text: String::new(),
+    });
+
+    // Sorting is stable, so a new notice still goes before a new license line
+    // when they're both added at the top.
+    let mut edits: Vec<Edit> = with_newlines(notice_edit, newline)
+        .into_iter()
+        .chain(with_newlines(license_edit, newline))
+        .chain(removals)
+        .collect();
+    edits.sort_by_key(|e| e.start);
+
+    let mut merged = None;
+    for edit in edits {
+        merged = merge_edits(fh, merged, Some(edit))?;
+    }
+    Ok(merged)
+}
+
+/// Swaps the newlines in the given edit's text for the file's own
+/// (e.g. `\r\n`), so we don't leave it with a mix of line endings.
+fn with_newlines(edit: Option<Edit>, newline: &str) -> Option<Edit> {
+    edit.map(|mut edit| {
+        if newline != "\n" {
+            edit.text = edit.text.replace('\n', newline);
+        }
+        edit
+    })
+}
+
+/// Comes up with the edit to the notice lines themselves (see `plan_update()`).
+fn plan_notice(
+    path: &str,
+    years: &[Year],
+    options: &NoticeOptions,
+    old_notice: Option<&Notice>,
+    top: usize,
+    opener: &str,
+    closer: Option<&'static str>,
+) -> Option<Edit> {
+    // Existing notices keep their holders, unless we're told to replace them
+    // with ours, or who wrote the file decides who holds it.
+    // Each of our holder lines says the organization, then the suffix (if any),
+    // so existing lines naming one of ours get whatever suffix we have now.
+    // Only other holders' lines (e.g. a third party we share the file with)
+    // stay as they are.
+    let by_author = options.authorship.contains_key(path);
+    let kept = old_notice.filter(|_| !options.force_organization && !by_author);
+    let holders: Vec<String> = match kept {
+        Some(old) => options.kept_holders(path, old.holders.clone()),
+        None => options.holders_for(path),
+    };
+    // Holders we share the file with (see --allow-holder) keep the years
+    // their lines list, since our history doesn't say which years they hold.
+    let joint = kept.filter(|old| options.names_joint_holders(path, &old.holders));
+    let (line_years, holders): (Vec<Vec<Year>>, Vec<String>) = holders
+        .iter()
+        .enumerate()
+        .map(
+            |(line, holder)| match joint.filter(|_| !options.names_ours(path, holder)) {
+                Some(old) => (listed_years(old, holder), holder.clone()),
+                None => options.line(path, line, holder, years),
+            },
+        )
+        .unzip();
+
+    // If the existing notice already says what we would, leave it be,
+    // even if it's formatted differently. (Unless we're told to normalize it.)
+    // Reformatting notices nobody asked us to change just makes noisy diffs.
+    if let Some(old) = old_notice {
+        if !options.normalize && old.holders == holders && lists_same_years(old, &line_years) {
+            return None;
+        }
+    }
+
+    // Keep the existing lead (e.g. `// Copyright ©` or ` * @copyright`).
+    let lead = match old_notice {
+        Some(old) => old.lead.clone(),
+        None => format!("{} {}", opener, options.scan.lead),
+    };
+
+    // Since we replace each notice line wholesale, a suffix (or with
+    // --force-org, a holder) that changed since the last run replaces the old one.
+    // License text after the old holders never goes anywhere, though.
+    let licenses = line_licenses(old_notice, &holders, kept.is_some());
+    let ranges = options.gap_tolerance.is_some()
+        || options.keep_ranges
+            && old_notice
+                .is_some_and(|old| old.year_lists.iter().any(|l| l.contains(['-', '–', '—'])));
+    let mut new_notice = holders
+        .iter()
+        .zip(line_years)
+        .zip(licenses)
+        .map(|((holder, years), license)| {
+            let year_list = if ranges {
+                format_year_ranges(&years, &options.year_separator)
+            } else {
+                format_year_list(&years, &options.year_separator)
+            };
+            let body = format!("{} {}{}", year_list, holder, license);
+            comment_line(&lead, &body, closer)
+        })
+        .join("\n");
+
+    let edit = match old_notice {
+        // If there's an existing copyright notice, replace that.
+        Some(old) => Edit {
+            start: old.start,
+            old_length: old.length,
+            text: new_notice,
+        },
+        // Otherwise we'll add one to the top of the file.
+        None => {
+            // We need a newline if we're creating our own notice.
+            new_notice.push('\n');
+            Edit {
+                start: top,
+                old_length: 0,
+                text: new_notice,
+            }
+        }
+    };
+
+    Some(edit)
+}
+
+/// Comes up with the edit that renders `template` over the existing
+/// rendering at `found`, or adds a new one at `top`.
+///
+/// Templates list all organizations (and the suffix) wherever they say
+/// `{organization}`, and the license only goes wherever they say `{spdx}`.
+#[allow(clippy::too_many_arguments)]
+fn plan_template(
+    path: &str,
+    years: &[Year],
+    options: &NoticeOptions,
+    template: &Template,
+    style: &CommentStyle,
+    found: Option<(usize, usize)>,
+    top: usize,
+    newline: &str,
+    contents: &[u8],
+) -> Option<Edit> {
+    let mut organization = options.organizations_for(path).join(", ");
+    if let Some(s) = &options.suffix {
+        organization.push(' ');
+        organization.push_str(s);
+    }
+
+    let fields = Fields {
+        years: &options.format_years(years),
+        organization: &organization,
+        filename: Path::new(path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(path),
+        spdx: options.license.as_deref().unwrap_or(""),
+    };
+    let mut text = template.render(&fields, style).replace('\n', newline);
+
+    match found {
+        Some((start, end)) if contents[start..end] == *text.as_bytes() => None,
+        Some((start, end)) => Some(Edit {
+            start,
+            old_length: end - start,
+            text,
+        }),
+        None => {
+            text.push_str(newline);
+            // Like plan_update() does for single notices, end a first line
+            // (e.g. a shebang) that has to stay there if it isn't already.
+            // (A lone byte order mark isn't a line.)
+            let before = &contents[..top];
+            if top > 0 && !before.ends_with(b"\n") && !before.ends_with(BOM.as_bytes()) {
+                text.insert(0, '\n');
+            }
+            Some(Edit {
+                start: top,
+                old_length: 0,
+                text,
+            })
+        }
+    }
+}
+
+/// Does the given first line of a file have to stay first?
+/// Shebangs only work on the first line, and XML declarations
+/// must come before anything else (comments included).
+fn must_stay_first(first_line: &[u8]) -> bool {
+    first_line.starts_with(b"#!") || first_line.starts_with(b"<?xml")
+}
+
+/// Formats a (by default, comma-separated) list of years for a notice.
+pub fn format_year_list(years: &[Year], separator: &str) -> String {
+    years.iter().map(|y| y.to_string()).join(separator)
+}
+
+/// Like `format_year_list()`, but writes runs of (sorted) years as ranges,
+/// e.g. `2015,2017-2020`.
+pub fn format_year_ranges(years: &[Year], separator: &str) -> String {
+    let mut runs: Vec<(Year, Year)> = Vec::new();
+    for &y in years {
+        match runs.last_mut() {
+            Some((_, end)) if end.get() + 1 == y.get() => *end = y,
+            _ => runs.push((y, y)),
+        }
+    }
+    runs.iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .join(separator)
+}
+
+/// Builds a line of a notice: `<lead> <body>`, then the closer (if any).
+pub fn comment_line(lead: &str, body: &str, closer: Option<&str>) -> String {
+    let mut line = format!("{} {}", lead, body);
+    if let Some(c) = closer {
+        line.push(' ');
+        line.push_str(c);
+    }
+    line
+}
+
+/// Combines two edits to the same file into one that makes both of them,
+/// keeping whatever's between them. If they start at the same spot,
+/// `a`'s text goes first.
+fn merge_edits(mut fh: &File, a: Option<Edit>, b: Option<Edit>) -> io::Result<Option<Edit>> {
+    let (first, second) = match (a, b) {
+        (Some(a), Some(b)) if b.start < a.start => (b, a),
+        (Some(a), Some(b)) => (a, b),
+        (a, b) => return Ok(a.or(b)),
+    };
+
+    // (plan_update() shouldn't come up with any, but if it does,
+    // we'd better leave the file alone than mangle it.)
+    let first_end = first.start + first.old_length;
+    if first_end > second.start {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "we came up with overlapping edits to it",
+        ));
+    }
+
+    let mut between = vec![0; second.start - first_end];
+    fh.seek(io::SeekFrom::Start(first_end as u64))?;
+    fh.read_exact(&mut between)?;
+    let between =
+        String::from_utf8(between).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    Ok(Some(Edit {
+        start: first.start,
+        old_length: second.start + second.old_length - first.start,
+        text: first.text + &between + &second.text,
+    }))
+}
+
+/// Finds the license text (see `Notice::licenses`) each of the new notice lines
+/// for the given holders keeps from the old notice: the text from the old line
+/// for the same holder, if they were `kept`, or from the line in the same place
+/// otherwise. Text without a line to go on goes on the last one.
+fn line_licenses(old: Option<&Notice>, holders: &[String], kept: bool) -> Vec<String> {
+    let mut licenses = vec![String::new(); holders.len()];
+    let old = match old {
+        Some(old) if !holders.is_empty() => old,
+        _ => return licenses,
+    };
+
+    for (line, (holder, license)) in old.holders.iter().zip(&old.licenses).enumerate() {
+        let license = match license {
+            Some(l) => l,
+            None => continue,
+        };
+        // (Kept holders might have lost repeated lines; see collapse_holders().)
+        let new_line = if kept {
+            holders.iter().position(|h| h == holder)
+        } else {
+            Some(line).filter(|l| *l < holders.len())
+        };
+        let text = &mut licenses[new_line.unwrap_or(holders.len() - 1)];
+        if !text.contains(license.trim()) {
+            text.push_str(license);
+        }
+    }
+    licenses
+}
+
+/// The years the given notice's line for the given holder lists
+pub(crate) fn listed_years(notice: &Notice, holder: &str) -> Vec<Year> {
+    let list = notice
+        .holders
+        .iter()
+        .position(|h| h == holder)
+        .map_or("", |line| &notice.year_lists[line]);
+    let (mut years, _) = parse_year_list(list);
+    years.sort();
+    years.dedup();
+    years
+}
+
+/// Does each line of the given notice list exactly the given years
+/// for that line? (Each list of `years` should be sorted and deduplicated.)
+fn lists_same_years(notice: &Notice, years: &[Vec<Year>]) -> bool {
+    notice.year_lists.len() == years.len()
+        && notice.year_lists.iter().zip(years).all(|(list, years)| {
+            let (mut listed, _) = parse_year_list(list);
+            listed.sort();
+            listed.dedup();
+            listed == *years
+        })
+}
+
+/// Removes the notices of the given files (see `plan_strip()`),
+/// finding them as `scan` says to.
+pub fn strip_headers(
+    paths: &PathSet,
+    scan: &ScanOptions,
+    mode: Mode,
+    files: &FileOptions,
+    show_progress: bool,
+) -> Report {
+    let bar = progress::file_bar(paths.len(), "Stripping notices", show_progress);
+
+    let outcomes: Vec<(&String, Outcome, Action)> = paths
+        .par_iter()
+        .map(|path| {
+            let mut binary = false;
+            let mut generated = false;
+            let result = edit_file(path, mode, files, |mut fh| {
+                if files.is_binary(path, fh)? {
+                    binary = true;
+                    return Ok(None);
+                }
+                let scan = scan.for_file(path);
+                fh.seek(io::SeekFrom::Start(0))?;
+                if files.is_generated(fh, &scan)? {
+                    generated = true;
+                    return Ok(None);
+                }
+                fh.seek(io::SeekFrom::Start(0))?;
+                plan_strip(fh, &scan)
+            });
+            bar.inc(1);
+            let outcome = match result {
+                Ok(_) if binary => Outcome::Binary,
+                Ok(_) if generated => Outcome::Generated,
+                Ok(o) => o,
+                Err(e) => Outcome::Failed(Error::io(format!("{} {}", mode.verb("strip"), path))(e)),
+            };
+            (path, outcome, Action::Removed)
+        })
+        .collect();
+
+    bar.finish_and_clear();
+
+    report(outcomes)
+}
+
+/// Writes (or updates) a REUSE-style `<file>.license` companion
+/// for each file, instead of touching the file itself.
+/// (For images and other files that can't hold a notice.)
+pub fn update_companions(
+    map: &YearMap,
+    options: &NoticeOptions,
+    mode: Mode,
+    files: &FileOptions,
+    show_progress: bool,
+) -> Report {
+    let bar = progress::file_bar(map.len(), "Updating .license files", show_progress);
+
+    let outcomes: Vec<(&String, Outcome, Action)> = map
+        .par_iter()
+        .map(|(path, years)| {
+            let companion = companion_path(path);
+            let exists = Path::new(&companion).exists();
+            if !options.scope.covers(exists) {
+                bar.inc(1);
+                return (path, Outcome::Unchanged, Action::Current);
+            }
+            // As with notices, we leave companions naming someone else be.
+            let mut foreign = None;
+            let plan = |mut fh: &File| {
+                let mut old = String::new();
+                fh.read_to_string(&mut old)?;
+                let holders: Vec<String> = old
+                    .lines()
+                    .filter_map(|l| l.strip_prefix(FILE_COPYRIGHT_TAG))
+                    .map(|text| companion_holder(text).to_owned())
+                    .collect();
+                foreign = options.foreign_holder(path, &holders).map(|h| h.to_owned());
+                if foreign.is_some() {
+                    return Ok(None);
+                }
+
+                let new = companion_contents(path, years, options, &old);
+                Ok(if new == old {
+                    None
+                } else {
+                    Some(Edit {
+                        start: 0,
+                        old_length: old.len(),
+                        text: new,
+                    })
+                })
+            };
+
+            let result = match mode {
+                // There's nothing to diff against or check yet.
+                Mode::DryRun if !exists => Ok(Outcome::Changed(Some(diff::new_file_diff(
+                    &companion,
+                    &companion_contents(path, years, options, ""),
+                )))),
+                Mode::Check if !exists => Ok(Outcome::Changed(None)),
+                Mode::Write(_) if !exists => files
+                    .backups
+                    .as_ref()
+                    .map_or(Ok(()), |b| b.creating(&companion))
+                    .and_then(|_| {
+                        OpenOptions::new()
+                            .write(true)
+                            .create(true)
+                            .truncate(false)
+                            .open(&companion)
+                    })
+                    .and_then(|_| edit_file(&companion, mode, files, plan)),
+                _ => edit_file(&companion, mode, files, plan),
+            };
+            bar.inc(1);
+            let outcome = match (result, foreign) {
+                (Ok(_), Some(holder)) => Outcome::Skipped(holder),
+                (Ok(o), None) => o,
+                (Err(e), _) => {
+                    Outcome::Failed(Error::io(format!("{} {}", mode.verb("update"), companion))(
+                        e,
+                    ))
+                }
+            };
+            let change = if exists {
+                Action::Updated
+            } else {
+                Action::Added
+            };
+            (path, outcome, change)
+        })
+        .collect();
+
+    bar.finish_and_clear();
+
+    report(outcomes)
+}
+
+/// Where the REUSE spec puts the license info for the given file
+pub fn companion_path(path: &str) -> String {
+    format!("{}.license", path)
+}
+
+const FILE_COPYRIGHT_TAG: &str = "SPDX-FileCopyrightText:";
+const LICENSE_TAG: &str = "SPDX-License-Identifier:";
+
+/// Comes up with what a `.license` companion file should say,
+/// given what it says now (if anything).
+///
+/// Like notices, existing `SPDX-FileCopyrightText:` lines keep their holders
+/// (unless we're told to replace them; see `NoticeOptions::kept_holders()`)
+/// and get their years updated.
+/// Existing license lines are kept unless we're given a license,
+/// and anything else in the file is kept after them.
+fn companion_contents(path: &str, years: &[Year], options: &NoticeOptions, old: &str) -> String {
+    let mut holders = Vec::new();
+    let mut licenses = Vec::new();
+    let mut rest = Vec::new();
+    for line in old.lines() {
+        if let Some(text) = line.strip_prefix(FILE_COPYRIGHT_TAG) {
+            holders.push(companion_holder(text).to_owned());
+        } else if let Some(license) = line.strip_prefix(LICENSE_TAG) {
+            licenses.push(license.trim().to_owned());
+        } else {
+            rest.push(line);
+        }
+    }
+
+    if holders.is_empty() || options.force_organization || options.authorship.contains_key(path) {
+        holders = options.holders_for(path);
     } else {
-        // Calculate the difference in length between the old notice and the new
-        // one, then slide all contents *after* the old notice that distance.
-        let slide_amount = new_first_line.len() as isize - old_first_line.len() as isize;
-        slide_file_contents(&fh, old_first_line.len(), slide_amount)?;
+        holders = options.kept_holders(path, holders);
+    }
+    if let Some(license) = &options.license {
+        licenses = vec![license.clone()];
     }
 
-    // Rewind to the start and write our notice line.
+    let mut contents = String::new();
+    for (line, holder) in holders.iter().enumerate() {
+        let (years, holder) = options.line(path, line, holder, years);
+        let year_list = options.format_years(&years);
+        contents.push_str(&format!(
+            "{} {} {}\n",
+            FILE_COPYRIGHT_TAG, year_list, holder
+        ));
+    }
+    for license in licenses {
+        contents.push_str(&format!("{} {}\n", LICENSE_TAG, license));
+    }
+    for line in rest {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    contents
+}
+
+/// Finds the holder in what follows `SPDX-FileCopyrightText:`,
+/// past any copyright symbol and years.
+fn companion_holder(text: &str) -> &str {
+    let text = text.trim_start().trim_start_matches('©').trim_start();
+    let text = text
+        .strip_prefix("(c)")
+        .or_else(|| text.strip_prefix("(C)"))
+        .unwrap_or(text);
+    text.trim_start_matches(|c: char| {
+        c.is_ascii_digit() || c.is_whitespace() || ",;–—-".contains(c)
+    })
+    .trim_end()
+}
+
+/// Comes up with the edit that removes the existing copyright notice
+/// of a file (if any), along with the blank line following it (if any).
+/// A block comment holding nothing but the notice goes with it.
+fn plan_strip(mut fh: &File, scan: &Scan) -> io::Result<Option<Edit>> {
+    let notice = match find_notice(io::BufReader::new(fh), scan)? {
+        Some(n) => n,
+        None => return Ok(None),
+    };
+
+    let mut strip_start = notice.line_start;
+    let mut strip_end = notice.line_end;
+
+    // Is the line before the notice just a block comment's opener
+    // (e.g. `/*`, or `/**` for doc comments)...
+    let mut before = vec![0; strip_start];
     fh.seek(io::SeekFrom::Start(0))?;
+    fh.read_exact(&mut before)?;
+    let bom = if before.starts_with(BOM.as_bytes()) {
+        BOM.len()
+    } else {
+        0
+    };
+    let opener_start = before[..strip_start.saturating_sub(1)]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(bom, |i| i + 1);
+    let opener_line = before[opener_start..].trim_ascii();
+    let closer = scan.styles.iter().find_map(|style| {
+        let rest = opener_line.strip_prefix(style.opener.as_bytes())?;
+        style.closer.filter(|_| rest.iter().all(|&b| b == b'*'))
+    });
+    // ...and the one after it just its closer?
+    if let Some(closer) = closer.filter(|_| !opener_line.is_empty()) {
+        fh.seek(io::SeekFrom::Start(strip_end as u64))?;
+        let mut closing_line = Vec::new();
+        if read_line(
+            &mut io::BufReader::new(fh),
+            &mut closing_line,
+            scan.max_line_length,
+        )? && closing_line.trim_ascii() == closer.as_bytes()
+        {
+            strip_start = opener_start;
+            strip_end += closing_line.len();
+        }
+    }
+
+    // Take a blank line after the notice with it.
+    fh.seek(io::SeekFrom::Start(strip_end as u64))?;
+    let mut next_line = Vec::new();
+    if read_line(
+        &mut io::BufReader::new(fh),
+        &mut next_line,
+        scan.max_line_length,
+    )? && next_line.trim_ascii().is_empty()
+    {
+        strip_end += next_line.len();
+    }
+
+    Ok(Some(Edit {
+        start: strip_start,
+        old_length: strip_end - strip_start,
+        text: String::new(),
+    }))
+}
+
+/// Plans an edit to the given file with `plan`, then, depending on the mode,
+/// makes it, returns a diff of it, or just notes that it's needed.
+/// (`plan` gets the file as UTF-8; see `encoding::utf8_view()`.)
+fn edit_file<F>(path: &str, mode: Mode, files: &FileOptions, plan: F) -> io::Result<Outcome>
+where
+    F: FnOnce(&File) -> io::Result<Option<Edit>>,
+{
+    match mode {
+        Mode::Write(strategy) => {
+            let permissions = fs::metadata(path)?.permissions();
+            if !permissions.readonly() {
+                return rewrite_file(path, strategy, files, plan);
+            }
+
+            // Read-only files that are already up to date are fine as they are.
+            // The rest can only be rewritten if we're allowed to make them
+            // writable while we do.
+            if !files.allow_chmod {
+                let (_, mut fh) = encoding::utf8_view(File::open(path)?)?;
+                return match plan(&fh)? {
+                    Some(edit) if changes_file(&mut fh, &edit)? => Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "it's read-only (give --allow-chmod to rewrite it anyway)",
+                    )),
+                    _ => Ok(Outcome::Unchanged),
+                };
+            }
+            fs::set_permissions(path, writable(&permissions))?;
+            let result = rewrite_file(path, strategy, files, plan);
+            // (Put the permissions back even if the rewrite failed.)
+            let restored = fs::set_permissions(path, permissions);
+            result.and_then(|outcome| restored.map(|_| outcome))
+        }
+        Mode::DryRun => {
+            let (_, mut fh) = encoding::utf8_view(File::open(path)?)?;
+            let edit = match plan(&fh)? {
+                Some(e) => e,
+                None => return Ok(Outcome::Unchanged),
+            };
+
+            let mut contents = Vec::new();
+            fh.seek(io::SeekFrom::Start(0))?;
+            fh.read_to_end(&mut contents)?;
+            Ok(match diff::unified_diff(path, &contents, &edit) {
+                Some(d) => Outcome::Changed(Some(d)),
+                None => Outcome::Unchanged,
+            })
+        }
+        Mode::Plan => {
+            let (_, mut fh) = encoding::utf8_view(File::open(path)?)?;
+            let edit = match plan(&fh)? {
+                Some(e) => e,
+                None => return Ok(Outcome::Unchanged),
+            };
+
+            let mut contents = Vec::new();
+            fh.seek(io::SeekFrom::Start(0))?;
+            fh.read_to_end(&mut contents)?;
+            if contents.get(edit.start..edit.start + edit.old_length) == Some(edit.text.as_bytes())
+            {
+                return Ok(Outcome::Unchanged);
+            }
+            Ok(Outcome::Planned(PlannedEdit {
+                start: edit.start,
+                old_length: edit.old_length,
+                text: edit.text,
+                sha1: contents_sha1(&contents),
+            }))
+        }
+        Mode::Check => {
+            let (_, mut fh) = encoding::utf8_view(File::open(path)?)?;
+            let edit = match plan(&fh)? {
+                Some(e) => e,
+                None => return Ok(Outcome::Unchanged),
+            };
+
+            // A rewrite that comes out the same doesn't count.
+            if changes_file(&mut fh, &edit)? {
+                Ok(Outcome::Changed(None))
+            } else {
+                Ok(Outcome::Unchanged)
+            }
+        }
+    }
+}
+
+/// Does the given edit change the file at all,
+/// or would it replace some text with the same thing?
+fn changes_file(fh: &mut File, edit: &Edit) -> io::Result<bool> {
+    if edit.old_length != edit.text.len() {
+        return Ok(true);
+    }
+    let mut old = vec![0; edit.old_length];
+    fh.seek(io::SeekFrom::Start(edit.start as u64))?;
+    fh.read_exact(&mut old)?;
+    Ok(old != edit.text.as_bytes())
+}
+
+/// Makes the edit `plan` comes up with (if any) to the given file.
+fn rewrite_file<F>(
+    path: &str,
+    strategy: WriteStrategy,
+    files: &FileOptions,
+    plan: F,
+) -> io::Result<Outcome>
+where
+    F: FnOnce(&File) -> io::Result<Option<Edit>>,
+{
+    let mut fh = open_for_rewrite(path, files.lock)?;
+    let mtime = if files.preserve_mtimes {
+        Some(fh.metadata()?.modified()?)
+    } else {
+        None
+    };
+    let (encoding, mut view) = encoding::utf8_view(fh.try_clone()?)?;
+    match plan(&view)? {
+        // Rewriting the file with what it already says would just
+        // bump its mtime (and set off rebuilds) for nothing.
+        Some(edit) if !changes_file(&mut view, &edit)? => Ok(Outcome::Unchanged),
+        Some(edit) => {
+            let edit = encode_edit(encoding, &mut view, edit)?;
+            if let Some(backups) = &files.backups {
+                backups.back_up(path)?;
+            }
+            match strategy {
+                WriteStrategy::InPlace => apply_edit(&mut fh, &edit)?,
+                WriteStrategy::Atomic => apply_edit_atomically(path, &mut fh, &edit)?,
+            }
+            if let Some(mtime) = mtime {
+                // (An atomic write replaced the file we have open.)
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)?
+                    .set_modified(mtime)?;
+            }
+            Ok(Outcome::Changed(None))
+        }
+        None => Ok(Outcome::Unchanged),
+    }
+}
+
+/// Tallies up what happened to each file, where the `Action` alongside
+/// each outcome is what it means for that file to have changed,
+/// and collects any diffs (sorted so that dry runs are repeatable)
+/// into one patch.
+fn report(mut outcomes: Vec<(&String, Outcome, Action)>) -> Report {
+    outcomes.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut report = Report::default();
+    for (path, outcome, change) in outcomes {
+        let action = match outcome {
+            Outcome::Unchanged => {
+                debug!("{}: nothing to change", path);
+                Action::Current
+            }
+            Outcome::Changed(diff) => {
+                if let Some(d) = diff {
+                    report.patch.push_str(&d);
+                }
+                debug!("{}: {}", path, change);
+                report.changed.push(path.clone());
+                change
+            }
+            Outcome::Planned(edit) => {
+                debug!("{}: {} (planned)", path, change);
+                report.changed.push(path.clone());
+                report.planned.push((path.clone(), change, edit));
+                change
+            }
+            Outcome::Skipped(holder) => {
+                debug!("{}: skipped, since its notice names {:?}", path, holder);
+                report.skipped.push((path.clone(), holder));
+                Action::Skipped
+            }
+            Outcome::Binary => {
+                debug!("{}: skipped, since it looks binary", path);
+                report.binary.push(path.clone());
+                Action::Skipped
+            }
+            Outcome::Generated => {
+                debug!("{}: skipped, since it says it's generated", path);
+                report.generated.push(path.clone());
+                Action::Skipped
+            }
+            Outcome::Failed(e) => {
+                report.failures.push((path.clone(), e));
+                Action::Failed
+            }
+        };
+        report.actions.push((path.clone(), action));
+    }
+    report
+}
+
+/// Makes edits planned earlier (see `Mode::Plan`), to each file that still
+/// starts the way it did when they were planned. (Files that don't are
+/// listed as failures, since their notices might not be where we thought.)
+pub fn apply_planned_edits(
+    planned: &[(String, Action, PlannedEdit)],
+    strategy: WriteStrategy,
+    files: &FileOptions,
+    show_progress: bool,
+) -> Report {
+    let bar = progress::file_bar(planned.len(), "Applying the plan", show_progress);
+
+    let outcomes: Vec<(&String, Outcome, Action)> = planned
+        .par_iter()
+        .map(|(path, action, planned)| {
+            let result = edit_file(path, Mode::Write(strategy), files, |mut fh| {
+                let mut contents = Vec::new();
+                fh.read_to_end(&mut contents)?;
+                if contents_sha1(&contents) != planned.sha1 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "it changed since the plan was made",
+                    ));
+                }
+                Ok(Some(Edit {
+                    start: planned.start,
+                    old_length: planned.old_length,
+                    text: planned.text.clone(),
+                }))
+            });
+            bar.inc(1);
+            let outcome = match result {
+                Ok(o) => o,
+                Err(e) => Outcome::Failed(Error::io(format!("update {}", path))(e)),
+            };
+            (path, outcome, *action)
+        })
+        .collect();
+
+    bar.finish_and_clear();
 
-    fh.write_all(new_first_line.as_bytes())
+    report(outcomes)
+}
+
+/// How much of each file we look through for NUL bytes,
+/// which text files don't have (but most binary formats do).
+pub const BINARY_SNIFF_LENGTH: u64 = 8 * 1024;
+
+/// How we go about rewriting files (and which we leave alone),
+/// whatever goes in them
+#[derive(Default)]
+pub struct FileOptions {
+    /// Take an advisory lock on each file while we rewrite it,
+    /// so that concurrent runs take turns (see `open_for_rewrite()`)
+    pub lock: bool,
+
+    /// Put each file's modification time back after rewriting it,
+    /// so build systems don't think it changed
+    pub preserve_mtimes: bool,
+
+    /// Make read-only files writable while we rewrite them, then make them
+    /// read-only again, instead of failing to
+    pub allow_chmod: bool,
+
+    /// Leave binary files alone: the given ones (e.g. that Git attributes
+    /// say are binary), plus any with a NUL byte in their first
+    /// `BINARY_SNIFF_LENGTH` bytes
+    pub skip_binary: Option<HashSet<String>>,
+
+    /// Leave files alone that say they're generated, e.g. with `@generated`
+    /// or `DO NOT EDIT` near the top (see `notice::looks_generated()`)
+    pub skip_generated: bool,
+
+    /// Back up each file before rewriting it (`--backup`)
+    pub backups: Option<backup::Backups>,
+}
+
+impl FileOptions {
+    /// Should we leave the given file alone because it's binary?
+    fn is_binary(&self, path: &str, fh: &File) -> io::Result<bool> {
+        let known = match &self.skip_binary {
+            Some(k) => k,
+            None => return Ok(false),
+        };
+        if known.contains(path) {
+            return Ok(true);
+        }
+
+        let mut start = Vec::new();
+        fh.take(BINARY_SNIFF_LENGTH).read_to_end(&mut start)?;
+        Ok(start.contains(&0))
+    }
+
+    /// Should we leave the given file alone because it's generated?
+    fn is_generated(&self, fh: &File, scan: &Scan) -> io::Result<bool> {
+        if !self.skip_generated {
+            return Ok(false);
+        }
+        looks_generated(io::BufReader::new(fh), scan)
+    }
+}
+
+/// The given permissions (of a read-only file), plus permission
+/// for its owner (us, hopefully) to write it
+#[cfg(not(windows))]
+fn writable(permissions: &Permissions) -> Permissions {
+    Permissions::from_mode(permissions.mode() | 0o200)
+}
+
+/// The given permissions (of a read-only file), without the read-only bit
+#[cfg(windows)]
+#[allow(clippy::permissions_set_readonly_false)]
+fn writable(permissions: &Permissions) -> Permissions {
+    let mut permissions = permissions.clone();
+    permissions.set_readonly(false);
+    permissions
+}
+
+/// Opens a file we're about to rewrite with read and write perms.
+///
+/// If asked, also takes an advisory lock on it so that concurrent runs
+/// (or other well-behaved programs) take turns instead of corrupting it
+/// mid-slide. The lock is released when the file is closed,
+/// whether or not the rewrite succeeded.
+fn open_for_rewrite(path: &str, lock: bool) -> io::Result<File> {
+    let fh = OpenOptions::new().read(true).write(true).open(path)?;
+    if lock {
+        fh.lock_exclusive()?;
+    }
+    Ok(fh)
+}
+
+/// Turns an edit to the UTF-8 view of a file (see `encoding::utf8_view()`)
+/// into one to the file itself, in the given encoding.
+fn encode_edit(encoding: Encoding, view: &mut File, edit: Edit) -> io::Result<EncodedEdit> {
+    if encoding == Encoding::Utf8 {
+        return Ok(EncodedEdit {
+            start: edit.start,
+            old_length: edit.old_length,
+            bytes: edit.text.into_bytes(),
+        });
+    }
+
+    // (Edits start and end between characters, so each part encodes whole.)
+    let mut before = vec![0; edit.start + edit.old_length];
+    view.seek(io::SeekFrom::Start(0))?;
+    view.read_exact(&mut before)?;
+    Ok(EncodedEdit {
+        start: encoding.encode(&before[..edit.start])?.len(),
+        old_length: encoding.encode(&before[edit.start..])?.len(),
+        bytes: encoding.encode(edit.text.as_bytes())?,
+    })
+}
+
+/// Makes the given edit to the file.
+fn apply_edit(fh: &mut File, edit: &EncodedEdit) -> io::Result<()> {
+    let edit_end = edit.start + edit.old_length;
+
+    if edit_end >= fh.metadata()?.len() as usize {
+        // There's nothing after the edit to slide around.
+        // Just chop off whatever we're replacing.
+        fh.set_len(edit.start as u64)?;
+    } else {
+        // Calculate the difference in length between the old text and the new
+        // text, then slide all contents *after* the old text that distance.
+        let slide_amount = edit.bytes.len() as isize - edit.old_length as isize;
+        slide_file_contents(fh, edit_end, slide_amount)?;
+    }
+
+    // Seek to where the new text goes and write it.
+    // (slide_file_contents() wrote or flushed everything it moved,
+    // so this can't be clobbered by dirty pages from its mapping.)
+    fh.seek(io::SeekFrom::Start(edit.start as u64))?;
+
+    fh.write_all(&edit.bytes)
+}
+
+/// Makes the given edit by writing the edited contents to a temporary file
+/// in the same directory, then renaming that over the original.
+/// If we die partway through, the original is left untouched.
+fn apply_edit_atomically(path: &str, fh: &mut File, edit: &EncodedEdit) -> io::Result<()> {
+    let mut contents = Vec::new();
+    fh.seek(io::SeekFrom::Start(0))?;
+    fh.read_to_end(&mut contents)?;
+    contents.splice(
+        edit.start..edit.start + edit.old_length,
+        edit.bytes.iter().copied(),
+    );
+
+    // Renames are only atomic within a filesystem, so stay in the same directory.
+    let dir = match Path::new(path).parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    let mut temp = tempfile::Builder::new()
+        .prefix(".copyrighter-")
+        .tempfile_in(dir)?;
+    temp.write_all(&contents)?;
+    temp.as_file()
+        .set_permissions(fh.metadata()?.permissions())?;
+    temp.as_file().sync_all()?;
+    temp.persist(path).map_err(|e| e.error)?;
+    Ok(())
 }
 
 /// We slide file contents around using mmap and memmove, assuming
@@ -93,9 +1962,10 @@ fn update_file(path: &str, years: &[Year], organization: &str) -> io::Result<()>
 ///    then overwriting the existing file with the temp file.
 /// 2. The file fits comfortably in memory space. Besides, if a *code* file
 ///    is more than a few dozen kilobytes, you have other problems.
+#[cfg(not(windows))]
 fn slide_file_contents(fd: &File, offset: usize, amount: isize) -> io::Result<()> {
     // We simplify casting and math below if we can assume offset can be signed.
-    assert!(offset <= isize::max_value() as usize);
+    assert!(offset <= isize::MAX as usize);
 
     // Don't let us slide contents past the start of the file.
     assert!(offset as isize + amount >= 0);
@@ -109,34 +1979,49 @@ fn slide_file_contents(fd: &File, offset: usize, amount: isize) -> io::Result<()
     // (usize is 32 bits on x86, and files can be much larger than 4GB.)
     // But we're trying to mmap it (so it should fit in our address space),
     // and if a code file is that big...
-    let file_length_64 : u64 = fd.metadata()?.len();
-    if file_length_64 > isize::max_value() as u64 {
+    let file_length_64: u64 = fd.metadata()?.len();
+    if file_length_64 > isize::MAX as u64 {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
             "The file is too large to be mapped.",
         ));
     }
     let file_length = file_length_64 as usize;
-    assert!(file_length > offset); // Return error instead of asserting?
+    if offset > file_length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Can't slide contents from past the end of the file.",
+        ));
+    }
 
     let new_length = (file_length as isize + amount) as u64;
 
+    // If there's nothing after the offset (e.g., the file is empty),
+    // there's nothing to move, and nothing to map either.
+    // (Mapping an empty file fails.) Just make or take back the room.
+    if offset == file_length {
+        return fd.set_len(new_length);
+    }
+
     if amount < 0 {
         // We have to shrink the file.
         // Shift its contents over.
         unsafe {
-            let mut mapping = MmapMut::map_mut(&fd)?;
+            let mut mapping = MmapMut::map_mut(fd)?;
             // memmove, a la Rust
             ptr::copy(
                 mapping.as_ptr().add(offset),
                 mapping.as_mut_ptr().add(offset).offset(amount),
                 file_length - offset,
             );
+            // Flush the moved contents before the mapping goes away,
+            // so they're in the file before anybody writes to it
+            // through a plain old file handle.
+            mapping.flush()?;
         }
 
         // Then shrink it.
         fd.set_len(new_length)?;
-
     } else if amount > 0 {
         // We have to grow the file.
         fd.set_len(new_length)?;
@@ -150,6 +2035,10 @@ fn slide_file_contents(fd: &File, offset: usize, amount: isize) -> io::Result<()
                 mapping.as_mut_ptr().add(offset).offset(amount),
                 file_length - offset,
             );
+            // Flush the moved contents before the mapping goes away,
+            // so they're in the file before anybody writes to it
+            // through a plain old file handle.
+            mapping.flush()?;
         }
     } else {
         // wat
@@ -157,3 +2046,28 @@ fn slide_file_contents(fd: &File, offset: usize, amount: isize) -> io::Result<()
     }
     Ok(())
 }
+
+/// Windows won't let us resize a file while any part of it is mapped,
+/// and doesn't promise to unmap views as soon as we drop them.
+/// So there, we read everything after `offset` and write it back
+/// `amount` bytes over instead.
+#[cfg(windows)]
+fn slide_file_contents(mut fd: &File, offset: usize, amount: isize) -> io::Result<()> {
+    // Don't let us slide contents past the start of the file.
+    assert!(offset as isize + amount >= 0);
+
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let mut rest = Vec::new();
+    fd.seek(io::SeekFrom::Start(offset as u64))?;
+    fd.read_to_end(&mut rest)?;
+
+    let new_offset = (offset as isize + amount) as u64;
+    fd.seek(io::SeekFrom::Start(new_offset))?;
+    fd.write_all(&rest)?;
+
+    // If we slid things back, chop off what's left over at the end.
+    fd.set_len(new_offset + rest.len() as u64)
+}
@@ -0,0 +1,303 @@
+//! What we need from version control to date files, behind a trait
+//! so that Mercurial repos can be dated as well as Git ones.
+//!
+//! Git's implementation lives here, since it's spread across `git`
+//! (for single commands) and `history` (for walking history).
+//! Mercurial's is in `hg`.
+
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use rayon::prelude::*;
+use regex::Regex;
+
+use crate::cache;
+use crate::common::{AuthorMap, PathSet, Year, YearMap};
+use crate::error::Error;
+use crate::git::{self, GitOptions, SHA1};
+use crate::hg::Mercurial;
+use crate::history::{self, Method};
+use crate::progress;
+
+/// The version control systems we can read history from
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Git,
+    Mercurial,
+}
+
+impl Kind {
+    /// The directory at the top of each repo
+    fn marker(self) -> &'static str {
+        match self {
+            Kind::Git => ".git",
+            Kind::Mercurial => ".hg",
+        }
+    }
+
+    /// The program we run to talk to it
+    pub fn command(self) -> &'static str {
+        match self {
+            Kind::Git => "git",
+            Kind::Mercurial => "hg",
+        }
+    }
+
+    /// What users call it
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Git => "Git",
+            Kind::Mercurial => "Mercurial",
+        }
+    }
+}
+
+/// The operations on a repo that dating files needs.
+///
+/// Revisions are identified by their 40-character SHA-1 hash,
+/// which both Git and Mercurial use.
+pub trait Vcs: Sync {
+    fn kind(&self) -> Kind;
+
+    /// Returns an error unless we're at the top of the repo.
+    /// (Everything else assumes paths are relative to it.)
+    fn assert_at_repo_top(&self) -> Result<(), Error>;
+
+    /// Finds the year of the oldest commit in the current history.
+    fn first_commit_year(&self) -> Result<Year, Error>;
+
+    /// Finds the years each of the given files was changed,
+    /// not counting the given commits.
+    fn file_years(
+        &self,
+        paths: PathSet,
+        ignoring_commits: &HashSet<SHA1>,
+        method: Method,
+        show_progress: bool,
+    ) -> Result<YearMap, Error>;
+
+    /// Finds who wrote each commit that changed each of the given files,
+    /// and when, not counting the given commits.
+    fn file_authors(
+        &self,
+        paths: PathSet,
+        ignoring_commits: &HashSet<SHA1>,
+        show_progress: bool,
+    ) -> Result<AuthorMap, Error>;
+
+    /// Looks up the hash of a revision given by name, short hash, etc.
+    fn resolve_revision(&self, revision: &str) -> Result<SHA1, Error>;
+
+    /// Finds the commits in the current history whose author,
+    /// written as `Name <email>`, matches the given pattern.
+    fn commits_by_authors(&self, pattern: &Regex) -> Result<HashSet<SHA1>, Error>;
+
+    /// Finds the commits in the current history whose subject
+    /// (the first line of their message) matches the given pattern.
+    fn commits_by_message(&self, pattern: &Regex) -> Result<HashSet<SHA1>, Error>;
+
+    /// Finds the merge commits (ones with more than one parent)
+    /// in the current history.
+    fn merge_commits(&self) -> Result<HashSet<SHA1>, Error>;
+
+    /// Lists the files with changes that haven't been committed yet,
+    /// including untracked (but not ignored) ones.
+    fn dirty_files(&self) -> Result<HashSet<String>, Error>;
+
+    /// Lists the files in the repo: tracked ones, plus untracked ones
+    /// that aren't ignored.
+    fn list_files(&self) -> Result<Vec<String>, Error>;
+
+    /// Finds which of the given files the repo says are binary,
+    /// whatever their contents look like.
+    fn binary_files(&self, paths: &PathSet) -> Result<HashSet<String>, Error>;
+
+    /// Finds which of the given files the repo ignores (e.g. with `.gitignore`).
+    /// Tracked files don't count, whatever the ignore rules say.
+    fn ignored_files(&self, paths: &PathSet) -> Result<HashSet<String>, Error>;
+
+    /// Gets every value of the given config key, e.g. `copyrighter.organization`,
+    /// or none if it isn't set.
+    fn config_values(&self, key: &str) -> Result<Vec<String>, Error>;
+}
+
+/// Talks to Git as the given options say to (e.g. with libgit2)
+pub struct Git {
+    pub options: GitOptions,
+}
+
+impl Vcs for Git {
+    fn kind(&self) -> Kind {
+        Kind::Git
+    }
+
+    fn assert_at_repo_top(&self) -> Result<(), Error> {
+        git::assert_at_repo_top(&self.options)
+    }
+
+    fn first_commit_year(&self) -> Result<Year, Error> {
+        git::get_first_commit_year(&self.options)
+    }
+
+    fn file_years(
+        &self,
+        paths: PathSet,
+        ignoring_commits: &HashSet<SHA1>,
+        method: Method,
+        show_progress: bool,
+    ) -> Result<YearMap, Error> {
+        cache::get_year_map(&self.options, paths, ignoring_commits, method, |paths| {
+            history::get_year_map(
+                &self.options,
+                paths,
+                ignoring_commits,
+                method,
+                show_progress,
+            )
+        })
+    }
+
+    fn file_authors(
+        &self,
+        paths: PathSet,
+        ignoring_commits: &HashSet<SHA1>,
+        show_progress: bool,
+    ) -> Result<AuthorMap, Error> {
+        let bar = progress::file_bar(paths.len(), "Reading authors", show_progress);
+        let ret = paths
+            .into_par_iter()
+            .map(|path| {
+                let authors = git::get_file_author_years(&self.options, &path, ignoring_commits);
+                bar.inc(1);
+                Ok((path, authors?))
+            })
+            .collect();
+        bar.finish_and_clear();
+        ret
+    }
+
+    fn resolve_revision(&self, revision: &str) -> Result<SHA1, Error> {
+        git::commit_ish_into_sha(&self.options, revision)
+    }
+
+    fn commits_by_authors(&self, pattern: &Regex) -> Result<HashSet<SHA1>, Error> {
+        git::get_commits_by_authors(&self.options, pattern)
+    }
+
+    fn commits_by_message(&self, pattern: &Regex) -> Result<HashSet<SHA1>, Error> {
+        git::get_commits_by_message(&self.options, pattern)
+    }
+
+    fn merge_commits(&self) -> Result<HashSet<SHA1>, Error> {
+        git::get_merge_commits(&self.options)
+    }
+
+    fn dirty_files(&self) -> Result<HashSet<String>, Error> {
+        git::get_dirty_files(&self.options)
+    }
+
+    fn list_files(&self) -> Result<Vec<String>, Error> {
+        git::list_files(&self.options)
+    }
+
+    fn binary_files(&self, paths: &PathSet) -> Result<HashSet<String>, Error> {
+        git::get_binary_files(&self.options, paths)
+    }
+
+    fn ignored_files(&self, paths: &PathSet) -> Result<HashSet<String>, Error> {
+        git::get_ignored_files(&self.options, paths)
+    }
+
+    fn config_values(&self, key: &str) -> Result<Vec<String>, Error> {
+        git::get_config_values(&self.options, key)
+    }
+}
+
+/// Gets the implementation for the given kind of repo.
+/// (Mercurial repos don't care about the Git options.)
+pub fn open(kind: Kind, git: GitOptions) -> Box<dyn Vcs> {
+    match kind {
+        Kind::Git => Box::new(Git { options: git }),
+        Kind::Mercurial => Box::new(Mercurial),
+    }
+}
+
+/// Finds the top of the repo containing the given directory,
+/// i.e. the closest directory with a `.git` or `.hg` in it,
+/// and what kind of repo it is. If `kind` is given, only repos of that kind
+/// count. (A `.git` is a file for worktrees and submodules.)
+pub fn find_repo_top(dir: &Path, kind: Option<Kind>) -> Option<(Kind, &Path)> {
+    let kinds = match kind {
+        Some(k) => vec![k],
+        None => vec![Kind::Git, Kind::Mercurial],
+    };
+
+    dir.ancestors().find_map(|d| {
+        kinds
+            .iter()
+            .find(|k| d.join(k.marker()).exists())
+            .map(|k| (*k, d))
+    })
+}
+
+/// Parses the comma-separated list of commits given with `--ignore-commits`.
+pub fn get_commits_to_ignore<S: Borrow<str>>(
+    vcs: &dyn Vcs,
+    ignore_arg: Option<S>,
+) -> Result<HashSet<SHA1>, Error> {
+    let ignore_arg = match ignore_arg {
+        Some(a) => a,
+        None => return Ok(HashSet::new()),
+    };
+
+    ignore_arg
+        .borrow()
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(|c| vcs.resolve_revision(c.trim()))
+        .collect()
+}
+
+/// Reads the commits listed in a file like `.git-blame-ignore-revs`:
+/// one per line, with blank lines and `#` comments allowed.
+pub fn read_ignore_revs_file(vcs: &dyn Vcs, path: &str) -> Result<HashSet<SHA1>, Error> {
+    let contents = fs::read_to_string(path).map_err(Error::io(format!("read {}", path)))?;
+
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|rev| !rev.is_empty())
+        .map(|rev| vcs.resolve_revision(rev))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closest_repo_wins_unless_told_which() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let outer = dir.path();
+        let inner = outer.join("vendor/lib");
+        fs::create_dir_all(outer.join(".git")).unwrap();
+        fs::create_dir_all(inner.join(".hg")).unwrap();
+        fs::create_dir_all(inner.join("src")).unwrap();
+
+        let from = inner.join("src");
+        assert_eq!(
+            find_repo_top(&from, None),
+            Some((Kind::Mercurial, inner.as_path()))
+        );
+        assert_eq!(
+            find_repo_top(&from, Some(Kind::Git)),
+            Some((Kind::Git, outer))
+        );
+        assert_eq!(
+            find_repo_top(&outer.join("vendor"), Some(Kind::Mercurial)),
+            None
+        );
+    }
+}
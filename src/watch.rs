@@ -0,0 +1,134 @@
+//! Keeps an eye on the working tree (`copyrighter watch`), so that files get
+//! their notices fixed as they're saved instead of whenever someone remembers
+//! to run us.
+//!
+//! Changes come in bursts (editors write temporary files, save several
+//! files at once, and so on), so we wait for things to settle down for
+//! `SETTLE_TIME` before handing over the files that changed.
+//! We only hand over the ones the filter wants, skipping ones the repo ignores
+//! (e.g. build outputs) and ones `.copyrighterignore` matches, just like
+//! the files we find ourselves.
+
+use std::env;
+use std::path::{Component, Path};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use log::{debug, error, info};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::common::{utf8_path, PathSet};
+use crate::discover::{self, FileFilter};
+use crate::error::Error;
+use crate::vcs::Vcs;
+
+/// How long things have to be quiet before we look at what changed
+pub const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Watches the working tree (from the current directory, which should be
+/// the top of the repo) until we're killed, and calls `on_change` with the
+/// files that changed each time things settle down. If it fails, we complain
+/// and keep watching.
+pub fn watch<F>(
+    vcs: &dyn Vcs,
+    filter: &FileFilter,
+    skip_vendored: bool,
+    mut on_change: F,
+) -> Result<(), Error>
+where
+    F: FnMut(PathSet) -> Result<(), Error>,
+{
+    let top = env::current_dir().map_err(Error::io("get the current directory"))?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(Error::Watch)?;
+    watcher
+        .watch(&top, RecursiveMode::Recursive)
+        .map_err(Error::Watch)?;
+    info!("Watching {} for changes", top.display());
+
+    // (The watcher closes its end when it's dropped, which it won't be.)
+    while let Ok(first) = rx.recv() {
+        let mut changed = PathSet::default();
+        let mut event = Some(first);
+        while let Some(e) = event {
+            match e {
+                Ok(e) if is_write(&e.kind) => {
+                    changed.extend(
+                        e.paths
+                            .iter()
+                            .filter_map(|p| p.strip_prefix(&top).ok())
+                            .filter(|p| !in_repo_dir(p))
+                            .filter_map(|p| utf8_path(p.as_os_str())),
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => error!("{}", Error::Watch(e)),
+            }
+            event = rx.recv_timeout(SETTLE_TIME).ok();
+        }
+
+        // (Files that were saved, then deleted or renamed, are gone.)
+        changed.retain(|p| filter.matches(p) && Path::new(p).is_file());
+        if changed.is_empty() {
+            continue;
+        }
+        let files = match wanted(vcs, filter, skip_vendored, changed) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("{}", e);
+                continue;
+            }
+        };
+        if !files.is_empty() {
+            if let Err(e) = on_change(files) {
+                error!("{}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Could the given event have changed a file's contents?
+fn is_write(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Create(_) => true,
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Modify(_) => true,
+        _ => false,
+    }
+}
+
+/// Is the given path in the repo's own directory (`.git` or `.hg`),
+/// which changes all the time without anybody saving anything?
+fn in_repo_dir(path: &Path) -> bool {
+    matches!(
+        path.components().next(),
+        Some(Component::Normal(c)) if c == ".git" || c == ".hg"
+    )
+}
+
+/// Drops the changed files the repo ignores, vendored ones (if asked),
+/// and ones `.copyrighterignore` matches.
+fn wanted(
+    vcs: &dyn Vcs,
+    filter: &FileFilter,
+    skip_vendored: bool,
+    changed: PathSet,
+) -> Result<PathSet, Error> {
+    let ignored = vcs.ignored_files(&changed)?;
+    let mut changed: PathSet = changed
+        .into_iter()
+        .filter(|p| {
+            let keep = !ignored.contains(p);
+            if !keep {
+                debug!("Skipping {}: {} ignores it", p, vcs.kind().name());
+            }
+            keep
+        })
+        .collect();
+    if skip_vendored {
+        changed = discover::skip_vendored(vcs, changed, filter)?;
+    }
+    discover::skip_ignored(changed)
+}
@@ -0,0 +1,42 @@
+//! Backing files up as we change them, and putting them back.
+
+mod common;
+
+use common::Repo;
+
+#[test]
+fn existing_files_arent_overwritten_by_backups() {
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.commit(2021, "Add a");
+    repo.write("a.c.bak", "mine\n");
+
+    repo.run(&["-o", "Acme", "--backup", "a.c"]);
+    assert_eq!(repo.read("a.c.bak"), "mine\n");
+    assert_eq!(repo.read("a.c.bak.1"), "int a;\n");
+
+    // The last run's backups are ours to replace, though.
+    repo.run(&["-o", "Initech", "--force-org", "--backup", "a.c"]);
+    assert_eq!(repo.read("a.c.bak"), "mine\n");
+    assert_eq!(repo.read("a.c.bak.1"), "// Copyright © 2021 Acme\nint a;\n");
+    assert!(!repo.file("a.c.bak.2").exists());
+
+    repo.run(&["restore"]);
+    assert_eq!(repo.read("a.c"), "// Copyright © 2021 Acme\nint a;\n");
+    assert_eq!(repo.read("a.c.bak"), "mine\n");
+}
+
+#[test]
+fn created_companions_are_removed() {
+    let repo = Repo::new();
+    repo.write("i.png", "not really\n");
+    repo.commit(2021, "Add i");
+
+    repo.run(&["update", "-o", "Acme", "--reuse", "--backup", "i.png"]);
+    assert!(repo.file("i.png.license").exists());
+    assert!(!repo.file("i.png.license.bak").exists());
+
+    repo.run(&["restore"]);
+    assert!(!repo.file("i.png.license").exists());
+    assert_eq!(repo.read("i.png"), "not really\n");
+}
@@ -0,0 +1,121 @@
+//! Scratch Git repos for the integration tests to run copyrighter in.
+
+// Each test binary only uses some of these.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+/// A Git repo in a temporary directory, removed when it's dropped.
+pub struct Repo {
+    dir: TempDir,
+}
+
+impl Repo {
+    pub fn new() -> Repo {
+        let repo = Repo {
+            dir: TempDir::new().expect("Couldn't make a temporary directory"),
+        };
+        repo.git(&["init", "-q"]);
+        repo
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn file(&self, path: &str) -> PathBuf {
+        self.path().join(path)
+    }
+
+    pub fn write(&self, path: &str, contents: &str) {
+        self.write_bytes(path, contents.as_bytes());
+    }
+
+    pub fn write_bytes(&self, path: &str, contents: &[u8]) {
+        let path = self.file(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+    }
+
+    pub fn read(&self, path: &str) -> String {
+        String::from_utf8(self.read_bytes(path)).expect("File isn't UTF-8")
+    }
+
+    pub fn read_bytes(&self, path: &str) -> Vec<u8> {
+        fs::read(self.file(path)).unwrap()
+    }
+
+    pub fn remove(&self, path: &str) {
+        fs::remove_file(self.file(path)).unwrap();
+    }
+
+    /// Commits everything in the working tree, dated in the middle of the
+    /// given year.
+    pub fn commit(&self, year: u32, message: &str) {
+        let date = format!("{}-06-15T12:00:00Z", year);
+        self.git(&["add", "-A"]);
+        let output = self
+            .git_command()
+            .args(["commit", "-q", "--allow-empty", "-m", message])
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date)
+            .output()
+            .unwrap();
+        check("git commit", &output);
+    }
+
+    /// Runs Git in the repo, returning what it prints.
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = self.git_command().args(args).output().unwrap();
+        check("git", &output);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    fn git_command(&self) -> Command {
+        let mut cmd = Command::new("git");
+        cmd.current_dir(self.path())
+            .args(["-c", "commit.gpgSign=false"])
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com");
+        cmd
+    }
+
+    /// Starts copyrighter in the repo, without an organization
+    /// from the environment.
+    pub fn copyrighter(&self) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_copyrighter"));
+        cmd.current_dir(self.path()).env_remove("COPYRIGHTER_ORG");
+        cmd
+    }
+
+    /// Runs copyrighter in the repo, panicking if it fails,
+    /// and returns what it prints.
+    pub fn run(&self, args: &[&str]) -> String {
+        let output = self.run_output(args);
+        check("copyrighter", &output);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    pub fn run_output(&self, args: &[&str]) -> Output {
+        self.copyrighter().args(args).output().unwrap()
+    }
+}
+
+fn check(what: &str, output: &Output) {
+    assert!(
+        output.status.success(),
+        "{} failed ({}):\n{}{}",
+        what,
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,44 @@
+//! How `.copyrighter.toml` and the command line combine.
+
+mod common;
+
+use common::Repo;
+
+fn repo_with_config(config: &str) -> Repo {
+    let repo = Repo::new();
+    repo.write(".copyrighter.toml", config);
+    repo.write("a.c", "int a;\n");
+    repo.write("b.c", "int b;\n");
+    repo.commit(2015, "Add a and b");
+    repo
+}
+
+#[test]
+fn config_extensions_only_pick_files_when_none_are_given() {
+    let repo = repo_with_config("ext = [\"c\"]\norganization = \"Acme\"\n");
+
+    repo.run(&["a.c"]);
+    assert_eq!(repo.read("a.c"), "// Copyright © 2015 Acme\nint a;\n");
+    assert_eq!(repo.read("b.c"), "int b;\n");
+
+    repo.run(&[]);
+    assert_eq!(repo.read("b.c"), "// Copyright © 2015 Acme\nint b;\n");
+}
+
+#[test]
+fn config_flags_can_be_turned_off() {
+    let repo = repo_with_config("organization = \"Acme\"\ninclude-dirty = true\n");
+    repo.write("a.c", "int a = 1;\n");
+
+    let now = repo.run(&["report", "a.c"]);
+    assert_ne!(now, "added a.c: 2015\n");
+    assert_eq!(
+        repo.run(&["report", "--no-include-dirty", "a.c"]),
+        "added a.c: 2015\n"
+    );
+    // The last one given wins.
+    assert_eq!(
+        repo.run(&["report", "--no-include-dirty", "--include-dirty", "a.c"]),
+        now
+    );
+}
@@ -0,0 +1,126 @@
+//! Finding files' years in a Mercurial repo, when there's an `hg` to make one.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+/// A Mercurial repo in a temporary directory, removed when it's dropped.
+struct HgRepo {
+    dir: TempDir,
+}
+
+impl HgRepo {
+    /// Makes a repo, or returns `None` if `hg` isn't on the PATH.
+    fn new() -> Option<HgRepo> {
+        let found = hg_command()
+            .arg("--version")
+            .output()
+            .is_ok_and(|o| o.status.success());
+        if !found {
+            eprintln!("hg isn't on the PATH; skipping");
+            return None;
+        }
+
+        let repo = HgRepo {
+            dir: TempDir::new().expect("Couldn't make a temporary directory"),
+        };
+        repo.hg(&["init"]);
+        Some(repo)
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    fn write(&self, path: &str, contents: &str) {
+        fs::write(self.path().join(path), contents).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.path().join(path)).unwrap()
+    }
+
+    /// Commits everything in the working tree (adding and removing files
+    /// as needed), dated in the middle of the given year.
+    fn commit(&self, year: u32, message: &str) {
+        let date = format!("{}-06-15 12:00:00 +0000", year);
+        self.hg(&["commit", "-q", "--addremove", "-d", &date, "-m", message]);
+    }
+
+    fn hg(&self, args: &[&str]) {
+        let output = hg_command()
+            .current_dir(self.path())
+            .args(args)
+            .output()
+            .unwrap();
+        check("hg", &output);
+    }
+
+    /// Runs copyrighter in the repo, panicking if it fails.
+    fn run(&self, args: &[&str]) {
+        let output = Command::new(env!("CARGO_BIN_EXE_copyrighter"))
+            .current_dir(self.path())
+            .env_remove("COPYRIGHTER_ORG")
+            .args(args)
+            .output()
+            .unwrap();
+        check("copyrighter", &output);
+    }
+}
+
+fn hg_command() -> Command {
+    let mut cmd = Command::new("hg");
+    cmd.env("HGPLAIN", "1")
+        .env("HGUSER", "Test <test@example.com>");
+    cmd
+}
+
+fn check(what: &str, output: &Output) {
+    assert!(
+        output.status.success(),
+        "{} failed ({}):\n{}{}",
+        what,
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn mercurial_history() {
+    let repo = match HgRepo::new() {
+        Some(r) => r,
+        None => return,
+    };
+    repo.write("a.c", "int a;\n");
+    repo.write("b.c", "int b;\n");
+    repo.commit(2015, "Add a and b");
+    repo.write("a.c", "int a = 1;\n");
+    repo.commit(2017, "Change a");
+    repo.hg(&["mv", "b.c", "c.c"]);
+    repo.commit(2018, "Rename b to c");
+
+    // (The repo is found to be Mercurial's without being told.)
+    repo.run(&["-o", "Acme", "a.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2015,2017 Acme\nint a = 1;\n"
+    );
+
+    // Renames are followed back, and ignored changesets don't count.
+    repo.run(&["--vcs", "hg", "-o", "Acme", "c.c"]);
+    assert_eq!(repo.read("c.c"), "// Copyright © 2015,2018 Acme\nint b;\n");
+    repo.write("c.c", "int b;\n");
+    repo.run(&[
+        "--vcs",
+        "hg",
+        "-o",
+        "Acme",
+        "--ignore-commits",
+        "tip",
+        "c.c",
+    ]);
+    assert_eq!(repo.read("c.c"), "// Copyright © 2015 Acme\nint b;\n");
+}
@@ -0,0 +1,202 @@
+//! Finding files' years from history, through the different ways we can ask Git.
+
+mod common;
+
+use common::Repo;
+
+/// A wrapper around Git that chats on stderr, like some do,
+/// shouldn't confuse what we read from its stdout.
+#[cfg(unix)]
+#[test]
+fn noisy_git_binary() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.commit(2015, "Add a");
+    repo.write("a.c", "int a = 1;\n");
+    repo.commit(2017, "Change a");
+
+    let wrapper = tempfile::Builder::new()
+        .suffix(".sh")
+        .tempfile()
+        .unwrap()
+        .into_temp_path();
+    std::fs::write(
+        &wrapper,
+        "#!/bin/sh\necho \"wrapping git $*\" >&2\necho 'warning: something' >&2\nexec git \"$@\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    repo.run(&[
+        "--git-binary",
+        wrapper.to_str().unwrap(),
+        "--git-backend",
+        "cli",
+        "-o",
+        "Acme",
+        "a.c",
+    ]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2015,2017 Acme\nint a = 1;\n"
+    );
+}
+
+/// Environment variables that would point Git at some other repo,
+/// or change how it prints things, shouldn't get through to it.
+#[test]
+fn git_environment_is_cleaned() {
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.commit(2016, "Add a");
+
+    let elsewhere = Repo::new();
+    elsewhere.write("a.c", "int b;\n");
+    elsewhere.commit(2011, "Add b");
+
+    let output = repo
+        .copyrighter()
+        .args(["--git-backend", "cli", "-o", "Acme", "a.c"])
+        .env("GIT_DIR", elsewhere.file(".git"))
+        .env("GIT_WORK_TREE", elsewhere.path())
+        .env("GIT_CONFIG_COUNT", "1")
+        .env("GIT_CONFIG_KEY_0", "log.showSignature")
+        .env("GIT_CONFIG_VALUE_0", "true")
+        .env("LANG", "de_DE.UTF-8")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(repo.read("a.c"), "// Copyright © 2016 Acme\nint a;\n");
+}
+
+/// With --ignore-whitespace, a commit that only changed whitespace
+/// shouldn't count, nor trip up reading the rest of the history.
+#[test]
+fn whitespace_only_commits_are_ignored() {
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.commit(2012, "Add a");
+    repo.write("a.c", "int  a;\n");
+    repo.commit(2017, "Respace a");
+
+    repo.run(&[
+        "--git-backend",
+        "cli",
+        "--ignore-whitespace",
+        "-o",
+        "Acme",
+        "a.c",
+    ]);
+    assert_eq!(repo.read("a.c"), "// Copyright © 2012 Acme\nint  a;\n");
+}
+
+/// Both backends should find the same years for a history with renames
+/// and whitespace-only changes, including the libgit2 one's single walk.
+#[test]
+fn backends_agree() {
+    let build = || {
+        let repo = Repo::new();
+        repo.write("old.c", "int a;\n");
+        repo.write("b.c", "int b;\n");
+        repo.commit(2012, "Add old and b");
+        repo.write("old.c", "int a = 1;\n");
+        repo.commit(2013, "Change old");
+        repo.git(&["mv", "old.c", "a.c"]);
+        repo.commit(2015, "Rename old to a");
+        repo.write("b.c", "int  b;\n");
+        repo.commit(2017, "Respace b");
+        repo
+    };
+
+    for backend in ["cli", "libgit2"] {
+        let repo = build();
+        repo.run(&["--git-backend", backend, "-o", "Acme", "a.c", "b.c"]);
+        assert_eq!(
+            repo.read("a.c"),
+            "// Copyright © 2012,2013,2015 Acme\nint a = 1;\n",
+            "{}",
+            backend
+        );
+        assert_eq!(
+            repo.read("b.c"),
+            "// Copyright © 2012,2017 Acme\nint  b;\n",
+            "{}",
+            backend
+        );
+
+        let repo = build();
+        repo.run(&[
+            "--git-backend",
+            backend,
+            "--ignore-whitespace",
+            "-o",
+            "Acme",
+            "a.c",
+            "b.c",
+        ]);
+        assert_eq!(
+            repo.read("b.c"),
+            "// Copyright © 2012 Acme\nint  b;\n",
+            "{}",
+            backend
+        );
+    }
+}
+
+/// Blame only credits the years of lines still in the file, where the log
+/// credits every commit that touched it, even ones whose changes are gone.
+#[test]
+fn blame_forgets_deleted_lines() {
+    let repo = Repo::new();
+    for (year, contents) in [
+        (2015, "int a;\n"),
+        (2016, "int a;\nint b;\n"),
+        (2017, "int a;\n"),
+        (2019, "int a;\nint b;\n"),
+    ] {
+        repo.write("a.c", contents);
+        repo.write("b.c", contents);
+        repo.commit(year, "Change a and b");
+    }
+
+    repo.run(&["-o", "Acme", "--by-blame", "a.c"]);
+    repo.run(&["-o", "Acme", "b.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2015,2019 Acme\nint a;\nint b;\n"
+    );
+    assert_eq!(
+        repo.read("b.c"),
+        "// Copyright © 2015,2016,2017,2019 Acme\nint a;\nint b;\n"
+    );
+}
+
+/// Years in notices after the first commit are history's to vouch for,
+/// and commits to ignore can be given as any commit-ish.
+#[test]
+fn notice_years_before_history_are_kept() {
+    let repo = Repo::new();
+    repo.write("a.c", "// Copyright 2010, 2014, 2018 Acme\nint a;\n");
+    repo.write("b.c", "// Copyright 2010, 2014, 2018 Acme\nint b;\n");
+    repo.commit(2015, "Add a and b");
+    repo.write("a.c", "// Copyright 2010, 2014, 2018 Acme\nint a = 1;\n");
+    repo.write("b.c", "// Copyright 2010, 2014, 2018 Acme\nint b = 1;\n");
+    repo.commit(2017, "Change a and b");
+
+    repo.run(&["-o", "Acme", "a.c"]);
+    repo.run(&["-o", "Acme", "--ignore-commits", "HEAD", "b.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2010,2014,2015,2017 Acme\nint a = 1;\n"
+    );
+    assert_eq!(
+        repo.read("b.c"),
+        "// Copyright © 2010,2014,2015 Acme\nint b = 1;\n"
+    );
+}
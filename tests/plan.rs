@@ -0,0 +1,25 @@
+//! Saving edits with `analyze` and making them later with `apply`.
+
+mod common;
+
+use common::Repo;
+
+#[test]
+fn files_changed_since_the_plan_are_left_alone() {
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.write("b.c", "int b;\n");
+    repo.commit(2015, "Add a and b");
+
+    let plan = repo.file("plan.json");
+    let plan = plan.to_str().unwrap();
+    repo.run(&["analyze", "--out", plan, "-o", "Acme", "a.c", "b.c"]);
+    // a.c gets its notice some other way in the meantime.
+    repo.run(&["-o", "Acme", "a.c"]);
+
+    let output = repo.run_output(&["apply", plan]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("a.c: it changed since the plan"));
+    assert_eq!(repo.read("a.c"), "// Copyright © 2015 Acme\nint a;\n");
+    assert_eq!(repo.read("b.c"), "// Copyright © 2015 Acme\nint b;\n");
+}
@@ -0,0 +1,431 @@
+//! Updating (and adding) notices in a repo's files, start to finish.
+
+mod common;
+
+use std::process::Stdio;
+
+use common::Repo;
+
+#[test]
+fn license_on_the_notice_line_is_swapped_in_place() {
+    let repo = Repo::new();
+    repo.write(
+        "a.c",
+        "// Copyright 2019 Acme SPDX-License-Identifier: MIT\nint a;\n",
+    );
+    repo.write(
+        "b.c",
+        "// Copyright 2021 Acme SPDX-License-Identifier: MIT\nint b;\n",
+    );
+    repo.commit(2021, "Add a and b");
+
+    repo.run(&["-o", "Acme", "--spdx", "Apache-2.0", "a.c", "b.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2019,2021 Acme SPDX-License-Identifier: Apache-2.0\nint a;\n"
+    );
+    // (Its years are current, so only the license changes.)
+    assert_eq!(
+        repo.read("b.c"),
+        "// Copyright 2021 Acme SPDX-License-Identifier: Apache-2.0\nint b;\n"
+    );
+}
+
+#[test]
+fn changing_the_suffix_rewrites_our_lines() {
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.commit(2020, "Add a");
+
+    repo.run(&[
+        "-o",
+        "Acme",
+        "--organization-suffix",
+        "All rights reserved.",
+        "a.c",
+    ]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2020 Acme All rights reserved.\nint a;\n"
+    );
+    repo.commit(2021, "Add a notice to a");
+
+    repo.run(&[
+        "-o",
+        "Acme",
+        "--organization-suffix",
+        "and contributors",
+        "a.c",
+    ]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2020,2021 Acme and contributors\nint a;\n"
+    );
+}
+
+#[test]
+fn suffix_is_replaced_not_repeated() {
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.commit(2020, "Add a");
+    let with_suffix = |suffix: &str| {
+        repo.run(&["-o", "Acme", "--organization-suffix", suffix, "a.c"]);
+        repo.read("a.c")
+    };
+
+    let expected = "// Copyright © 2020 Acme All rights reserved.\nint a;\n";
+    assert_eq!(with_suffix("All rights reserved."), expected);
+    assert_eq!(with_suffix("All rights reserved."), expected);
+
+    // (The years are current, so only the suffix changes.)
+    assert_eq!(
+        with_suffix("Proprietary and confidential."),
+        "// Copyright © 2020 Acme Proprietary and confidential.\nint a;\n"
+    );
+    // Without one, whatever follows the organization stays.
+    repo.run(&["-o", "Acme", "a.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2020 Acme Proprietary and confidential.\nint a;\n"
+    );
+}
+
+#[test]
+fn text_after_the_holder_survives_updates() {
+    let repo = Repo::new();
+    repo.write(
+        "a.c",
+        "// Copyright 2016 Acme Inc. All rights reserved.\nint a;\n",
+    );
+    repo.commit(2020, "Add a");
+
+    repo.run(&["-o", "Acme Inc.", "a.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2016,2020 Acme Inc. All rights reserved.\nint a;\n"
+    );
+}
+
+#[test]
+fn jsdoc_copyright_tags_are_updated_in_place() {
+    let repo = Repo::new();
+    let doc = |years: &str| {
+        format!(
+            "/**\n * Widgets.\n *\n * @copyright {} Acme\n */\nfunction f() {{}}\n",
+            years
+        )
+    };
+    repo.write("a.js", &doc("2017"));
+    repo.commit(2019, "Add a");
+
+    repo.run(&["-o", "Acme", "a.js"]);
+    assert_eq!(repo.read("a.js"), doc("2017,2019"));
+}
+
+#[test]
+fn progress_bars_stay_off_without_a_terminal() {
+    let repo = Repo::new();
+    for i in 0..20 {
+        repo.write(&format!("{}.c", i), "int a;\n");
+    }
+    repo.commit(2019, "Add some files");
+
+    // (Our stderr is a pipe here, like it would be in CI.)
+    let output = repo.run_output(&["-o", "Acme", "--ext", "c"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Reading history"), "{}", stderr);
+    assert!(!stderr.contains("Updating notices"), "{}", stderr);
+    assert!(!stderr.contains('\r'), "{}", stderr);
+}
+
+#[test]
+fn unknown_files_get_the_default_style() {
+    let repo = Repo::new();
+    repo.write("a.frob", "frob widgets\n");
+    repo.write("b.frob", "frob widgets\n");
+    repo.commit(2019, "Add a and b");
+
+    repo.run(&["-o", "Acme", "a.frob"]);
+    assert_eq!(
+        repo.read("a.frob"),
+        "// Copyright © 2019 Acme\nfrob widgets\n"
+    );
+    repo.run(&["-o", "Acme", "--default-comment-style", "#", "b.frob"]);
+    assert_eq!(
+        repo.read("b.frob"),
+        "# Copyright © 2019 Acme\nfrob widgets\n"
+    );
+}
+
+#[test]
+fn years_on_the_line_after_copyright_are_updated_there() {
+    let repo = Repo::new();
+    repo.write("a.c", "// Copyright\n// 2015-2017 Acme\nint a;\n");
+    repo.commit(2019, "Add a");
+
+    repo.run(&["-o", "Acme", "a.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright\n// 2015,2016,2017,2019 Acme\nint a;\n"
+    );
+}
+
+#[test]
+fn strip_removes_whole_notices() {
+    let repo = Repo::new();
+    repo.write("a.c", "// Copyright 2019 Acme\n\nint a;\n");
+    repo.write(
+        "b.c",
+        "/*\n * Copyright 2015 Acme\n * Copyright 2017 Widgets Inc.\n */\nint b;\n",
+    );
+    repo.write(
+        "c.sh",
+        "#!/bin/sh\n# Copyright 2019 Acme\n# Copyright 2019 Widgets Inc.\necho c\n",
+    );
+    repo.commit(2019, "Add a, b, and c");
+
+    repo.run(&["--strip", "a.c", "b.c", "c.sh"]);
+    assert_eq!(repo.read("a.c"), "int a;\n");
+    assert_eq!(repo.read("b.c"), "int b;\n");
+    assert_eq!(repo.read("c.sh"), "#!/bin/sh\necho c\n");
+}
+
+#[test]
+fn years_are_joined_with_the_given_separator() {
+    let repo = Repo::new();
+    for year in [2015, 2017] {
+        repo.write("a.c", &format!("int a = {};\n", year));
+        repo.write("b.c", &format!("int b = {};\n", year));
+        repo.commit(year, "Change a and b");
+    }
+    let update = || {
+        repo.run(&["-o", "Acme", "--year-separator", ", ", "a.c"]);
+        repo.run(&["-o", "Acme", "--year-separator", ";", "b.c"]);
+    };
+
+    update();
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2015, 2017 Acme\nint a = 2017;\n"
+    );
+    assert_eq!(
+        repo.read("b.c"),
+        "// Copyright © 2015;2017 Acme\nint b = 2017;\n"
+    );
+
+    // Notices written that way are read back the same way.
+    repo.commit(2019, "Add notices to a and b");
+    update();
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright © 2015, 2017, 2019 Acme\nint a = 2017;\n"
+    );
+    assert_eq!(
+        repo.read("b.c"),
+        "// Copyright © 2015;2017;2019 Acme\nint b = 2017;\n"
+    );
+}
+
+#[test]
+fn big_files_read_back_whole() {
+    let repo = Repo::new();
+    // (Bigger than a page or two, so the contents slid to make room
+    // for the notice span several.)
+    let body: String = (0..20_000).map(|i| format!("int a{};\n", i)).collect();
+    repo.write("a.c", &body);
+    repo.write("b.c", &format!("// Copyright 2019 Acme\n\n{}", body));
+    repo.commit(2019, "Add a and b");
+
+    repo.run(&["-o", "Acme", "a.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        format!("// Copyright © 2019 Acme\n{}", body)
+    );
+    repo.run(&["--strip", "b.c"]);
+    assert_eq!(repo.read("b.c"), body);
+}
+
+#[test]
+fn concurrent_updates_take_turns() {
+    let repo = Repo::new();
+    let body: String = (0..20_000).map(|i| format!("int a{};\n", i)).collect();
+    repo.write("a.c", &body);
+    repo.commit(2019, "Add a");
+
+    for _ in 0..5 {
+        let runs: Vec<_> = (0..2)
+            .map(|_| {
+                repo.copyrighter()
+                    .args(["-o", "Acme", "--concurrency-safe", "a.c"])
+                    .stdout(Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut run in runs {
+            assert!(run.wait().unwrap().success());
+        }
+        assert_eq!(
+            repo.read("a.c"),
+            format!("// Copyright © 2019 Acme\n{}", body)
+        );
+        repo.write("a.c", &body);
+    }
+}
+
+#[test]
+fn template_languages_keep_their_delimiters() {
+    let repo = Repo::new();
+    repo.write("a.html.j2", "<h1>{{ title }}</h1>\n");
+    repo.write("b.j2", "{# Copyright 2017 Acme #}\n<p>hi</p>\n");
+    repo.write("c.erb", "<%= title %>\n");
+    repo.write(
+        "d.html.erb",
+        "<%# Copyright 2017 Acme %>\n<p><%= x %></p>\n",
+    );
+    repo.commit(2019, "Add some templates");
+
+    let files = ["a.html.j2", "b.j2", "c.erb", "d.html.erb"];
+    for _ in 0..2 {
+        repo.run(&[&["-o", "Acme"][..], &files].concat());
+        assert_eq!(
+            repo.read("a.html.j2"),
+            "{# Copyright © 2019 Acme #}\n<h1>{{ title }}</h1>\n"
+        );
+        assert_eq!(
+            repo.read("b.j2"),
+            "{# Copyright © 2017,2019 Acme #}\n<p>hi</p>\n"
+        );
+        assert_eq!(
+            repo.read("c.erb"),
+            "<%# Copyright © 2019 Acme %>\n<%= title %>\n"
+        );
+        assert_eq!(
+            repo.read("d.html.erb"),
+            "<%# Copyright © 2017,2019 Acme %>\n<p><%= x %></p>\n"
+        );
+    }
+}
+
+#[test]
+fn count_only_tallies_notices() {
+    let repo = Repo::new();
+    repo.write("a.c", "// Copyright 2019 Acme\nint a;\n");
+    repo.write("b.py", "# Copyright 2018 Widgets Inc.\n");
+    repo.write("c.c", "int c;\n");
+    repo.write("d.c", "/* Copyright is a legal right. */\nint d;\n");
+
+    // (Nothing's been committed, so there's no history to look at.)
+    let output = repo.run(&["--count-only", "a.c", "b.py", "c.c", "d.c", "e.c"]);
+    assert_eq!(
+        output,
+        "2 files with copyright notices\n\
+         2 files without\n\
+         1 file couldn't be read\n"
+    );
+    assert_eq!(repo.read("c.c"), "int c;\n");
+}
+
+#[test]
+fn current_notices_keep_their_formatting() {
+    let repo = Repo::new();
+    for year in [2015, 2017] {
+        let notice = "// Copyright (c) 2015, 2017 Acme\n";
+        repo.write("a.c", &format!("{}int a = {};\n", notice, year));
+        repo.write("b.c", &format!("{}int b = {};\n", notice, year));
+        repo.commit(year, "Change a and b");
+    }
+
+    // Its years and holder are right, so it passes --check, and is left alone...
+    repo.run(&["-o", "Acme", "--check", "a.c"]);
+    repo.run(&["-o", "Acme", "a.c"]);
+    assert_eq!(
+        repo.read("a.c"),
+        "// Copyright (c) 2015, 2017 Acme\nint a = 2017;\n"
+    );
+
+    // ...unless we're asked to reformat it.
+    repo.run(&["-o", "Acme", "--normalize", "b.c"]);
+    assert_eq!(
+        repo.read("b.c"),
+        "// Copyright © 2015,2017 Acme\nint b = 2017;\n"
+    );
+}
+
+#[test]
+fn latin1_files_stay_latin1() {
+    let repo = Repo::new();
+    repo.write_bytes(
+        "a.c",
+        b"// Copyright 2019 Soci\xe9t\xe9\n/* caf\xe9 */\nint a;\n",
+    );
+    repo.write_bytes("b.c", b"int b; /* \xe9 */\n");
+    repo.commit(2021, "Add a and b");
+
+    repo.run(&["-o", "Soci\u{e9}t\u{e9}", "a.c", "b.c"]);
+    assert_eq!(
+        repo.read_bytes("a.c"),
+        b"// Copyright \xa9 2019,2021 Soci\xe9t\xe9\n/* caf\xe9 */\nint a;\n"
+    );
+    assert_eq!(
+        repo.read_bytes("b.c"),
+        b"// Copyright \xa9 2021 Soci\xe9t\xe9\nint b; /* \xe9 */\n"
+    );
+
+    // What Latin-1 can't hold fails the file, once.
+    let output = repo.run_output(&["-o", "\u{20ac}", "--force-org", "a.c"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("a.c").count(), 1, "{}", stderr);
+}
+
+#[test]
+fn holders_keep_their_order() {
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.write("b.c", "int b;\n");
+    repo.commit(2019, "Add a and b");
+    let holders = ["-o", "Zeta", "-o", "Acme", "-o", "Mid"];
+
+    // They go in the order given, and stay there on reruns.
+    let expected = "// Copyright © 2019 Zeta\n\
+                    // Copyright © 2019 Acme\n\
+                    // Copyright © 2019 Mid\n\
+                    int a;\n";
+    for _ in 0..2 {
+        repo.run(&[&holders[..], &["a.c"]].concat());
+        assert_eq!(repo.read("a.c"), expected);
+    }
+
+    repo.run(&[&holders[..], &["--sort-holders", "b.c"]].concat());
+    assert_eq!(
+        repo.read("b.c"),
+        "// Copyright © 2019 Acme\n\
+         // Copyright © 2019 Mid\n\
+         // Copyright © 2019 Zeta\n\
+         int b;\n"
+    );
+}
+
+#[test]
+fn failures_say_what_the_mode_was_doing() {
+    let repo = Repo::new();
+    repo.write("a.c", "int a;\n");
+    repo.commit(2019, "Add a");
+    // A directory where the file should be can't be read.
+    repo.remove("a.c");
+    std::fs::create_dir(repo.file("a.c")).unwrap();
+
+    for (args, verb) in [
+        (&["--check"][..], "check"),
+        (&["--dry-run"][..], "read"),
+        (&[][..], "update"),
+    ] {
+        let output = repo.run_output(&[&["-o", "Acme"], args, &["a.c"]].concat());
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let expected = format!("Couldn't {} a.c", verb);
+        assert!(stderr.contains(&expected), "{}", stderr);
+    }
+}